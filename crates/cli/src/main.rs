@@ -1,15 +1,33 @@
-use cillio_config::{load_config, print_config, ConfigError};
+use cillio_config::{
+    expand_subgraphs, fill_implementations, load_config, print_config, validate_file_with,
+    ConfigError, GraphConfig, NodeDescription, NodeImplementationData, TypeRules,
+};
 use cillio_graph::{Graph, GraphError};
-use cillio_runtime::execution_plan::ExecutionPlan;
+use cillio_runtime::batch::BatchRow;
+use cillio_runtime::component_cache::{cache_root, CacheStats, ComponentCache};
+use cillio_runtime::compose::compose_graph;
+use cillio_runtime::convert::val_to_json_untyped;
+use cillio_runtime::execution_plan::{ExecutionPlan, ExecutionResult};
+use cillio_runtime::instance_pool::{InstanceReuse, RuntimeConfig};
+use cillio_runtime::logs::NodeLogs;
+use cillio_runtime::result_cache::ResultCache;
+use cillio_runtime::runtime_data::PortValues;
+use cillio_runtime::streaming::{StreamEvent, StreamOptions};
 use cillio_runtime::Runtime;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::channel::mpsc;
+use futures::future::join;
+use futures::StreamExt;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{
     io::{self},
     path::PathBuf,
 };
 use thiserror::Error;
+use tracing_subscriber::EnvFilter;
 
 #[derive(Error, Debug)]
 pub enum CliError {
@@ -21,6 +39,12 @@ pub enum CliError {
 
     #[error("Failed to build graph: {0}")]
     GraphError(#[from] GraphError),
+
+    #[error("Graph configuration has {0} problem(s)")]
+    ValidationError(usize),
+
+    #[error("Runtime error: {0}")]
+    RuntimeError(#[from] anyhow::Error),
 }
 
 #[derive(Parser)]
@@ -40,20 +64,195 @@ enum Commands {
     Print {
         #[arg(short, long, value_name = "FILE")]
         config: Option<PathBuf>,
+        /// Additional directories to search for node components
+        #[arg(long = "components-dir", value_name = "DIR")]
+        components_dirs: Vec<PathBuf>,
     },
     Dot {
         #[arg(short, long, value_name = "FILE")]
         config: Option<PathBuf>,
+        /// Additional directories to search for node components
+        #[arg(long = "components-dir", value_name = "DIR")]
+        components_dirs: Vec<PathBuf>,
+    },
+    Validate {
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+        /// Additional directories to search for node components
+        #[arg(long = "components-dir", value_name = "DIR")]
+        components_dirs: Vec<PathBuf>,
+        /// Disallow implicit widening between port types, e.g. int to float
+        #[arg(long)]
+        strict_types: bool,
+    },
+    /// Derive the node implementation of a component from its description or `process` export
+    Describe {
+        #[arg(value_name = "WASM")]
+        wasm: PathBuf,
+    },
+    /// Print the description returned by the `describe` export of a component
+    Inspect {
+        #[arg(value_name = "WASM")]
+        wasm: PathBuf,
+        /// Print the description as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Execute a graph and print the outputs of its nodes
+    Run {
+        #[arg(value_name = "GRAPH")]
+        graph: PathBuf,
+        /// Additional directories to search for node components
+        #[arg(long = "components-dir", value_name = "DIR")]
+        components_dirs: Vec<PathBuf>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+        /// Run without reading or writing the component cache
+        #[arg(long)]
+        no_cache: bool,
+        /// Reuse the results of deterministic nodes from previous runs and store new ones
+        #[arg(long)]
+        result_cache: bool,
+        /// Directory of the component and result caches, `CILLIO_CACHE_DIR` or the user's
+        /// cache directory by default
+        #[arg(long, value_name = "DIR")]
+        cache_dir: Option<PathBuf>,
+        /// Keep polling the source nodes and stream each value through the graph
+        #[arg(long)]
+        stream: bool,
+        /// Time between two polls of the source nodes in streaming mode, e.g. `100ms`
+        #[arg(long, value_name = "DURATION", default_value = "100ms", value_parser = parse_duration)]
+        interval: Duration,
+        /// Stop streaming after this many polls of the source nodes
+        #[arg(long, value_name = "N")]
+        ticks: Option<u64>,
+        /// Number of events buffered between two nodes in streaming mode
+        #[arg(long, value_name = "N", default_value_t = 16)]
+        channel_capacity: usize,
+        /// Execute the graph once per row of a JSON Lines file. A graph input name feeds that
+        /// input like `--input`, a `"node"` key replaces the state of a node and a
+        /// `"node.port"` key feeds an input port
+        #[arg(long, value_name = "FILE", conflicts_with = "stream")]
+        batch: Option<PathBuf>,
+        /// Maximum number of nodes, or batch rows, executed at the same time
+        #[arg(long, value_name = "N")]
+        jobs: Option<usize>,
+        /// Disallow implicit widening between port types, e.g. int to float
+        #[arg(long)]
+        strict_types: bool,
+        /// When node instances are created
+        #[arg(long, value_enum, default_value_t = InstanceMode::PerRun)]
+        instances: InstanceMode,
+        /// Preallocate slots for this many node instances with the pooling allocator
+        #[arg(long, value_name = "INSTANCES")]
+        pooling_allocator: Option<u32>,
+        /// Feed a graph input, e.g. `x=3`. Values are parsed as JSON, falling back to a string
+        #[arg(long = "input", value_name = "NAME=VALUE", value_parser = parse_input, conflicts_with_all = ["stream", "batch"])]
+        inputs: Vec<(String, Value)>,
+        /// Call the graph as a function and print its declared outputs, implied by `--input`
+        #[arg(long, conflicts_with_all = ["stream", "batch"])]
+        call: bool,
+    },
+    /// Compile a graph into a single component exporting `cillio:graph/graph`
+    Compose {
+        #[arg(value_name = "GRAPH")]
+        graph: PathBuf,
+        /// Additional directories to search for node components
+        #[arg(long = "components-dir", value_name = "DIR")]
+        components_dirs: Vec<PathBuf>,
+        /// Path of the composed component, the graph file with a `.wasm` extension by default
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Manage the caches of compiled components and node results
+    Cache {
+        /// Directory of the component and result caches, `CILLIO_CACHE_DIR` or the user's
+        /// cache directory by default
+        #[arg(long, value_name = "DIR")]
+        cache_dir: Option<PathBuf>,
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Remove all cached components and results
+    Clear,
+    /// Print the number and size of cached components and results
+    Stats,
+    /// List the cached node results, most recently used first
+    Inspect,
+    /// Remove cached components and results not used within the given number of days
+    Gc {
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        max_age_days: u64,
     },
-    Run,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// JSON, or one JSON object per line for streams and batches
+    Json,
+    Table,
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum InstanceMode {
+    /// A new instance for every call of a node
+    Fresh,
+    /// One instance per node and run, reused by stream events and batch rows
+    PerRun,
+    /// Like `per-run`, and instances are kept for later runs
+    Pooled,
+}
+
+impl From<InstanceMode> for InstanceReuse {
+    fn from(mode: InstanceMode) -> Self {
+        match mode {
+            InstanceMode::Fresh => InstanceReuse::Fresh,
+            InstanceMode::PerRun => InstanceReuse::PerRun,
+            InstanceMode::Pooled => InstanceReuse::Pooled,
+        }
+    }
+}
+
+/// Caches read and written by `run`.
+struct Caches {
+    components: Option<ComponentCache>,
+    results: Option<ResultCache>,
+}
+
+/// How `run` plans the execution of a graph.
+struct PlanOptions {
+    /// Maximum number of nodes, or batch rows, executed at the same time.
+    jobs: Option<usize>,
+    type_rules: TypeRules,
+}
+
+/// How `run` executes a graph.
+enum RunMode {
+    Once,
+    /// Executes the graph as a function of the given graph inputs.
+    Call(Map<String, Value>),
+    Stream(StreamOptions),
+    Batch(PathBuf),
 }
 
 #[async_std::main]
 async fn main() -> anyhow::Result<(), CliError> {
     let cli = Cli::parse();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .init();
 
     match &cli.command {
-        Commands::Print { config } => {
+        Commands::Print {
+            config,
+            components_dirs,
+        } => {
             let config_path = config.as_ref().ok_or(CliError::ConfigPathNotProvided)?;
             let config = load_config(
                 config_path
@@ -62,111 +261,923 @@ async fn main() -> anyhow::Result<(), CliError> {
             )?;
             print_config(&config);
             println!("Printing graph:");
-            let graph = Graph::new(&config)?;
+            let graph = Graph::new(&load_graph_config(config_path, components_dirs).await?)?;
             graph.print_dot();
         }
-        Commands::Dot { config } => {
+        Commands::Dot {
+            config,
+            components_dirs,
+        } => {
             let config_path = config.as_ref().ok_or(CliError::ConfigPathNotProvided)?;
-            let config = load_config(
-                config_path
-                    .to_str()
-                    .ok_or(CliError::ConfigPathNotProvided)?,
-            )?;
-            let graph = Graph::new(&config)?;
+            let graph = Graph::new(&load_graph_config(config_path, components_dirs).await?)?;
             graph.print_dot();
         }
-        Commands::Run => test_sum_graph().await.expect("Error testing sum graph"),
+        Commands::Validate {
+            config,
+            components_dirs,
+            strict_types,
+        } => {
+            let config_path = config
+                .as_ref()
+                .and_then(|path| path.to_str())
+                .ok_or(CliError::ConfigPathNotProvided)?;
+            let graph_dir = graph_dir(Path::new(config_path));
+            let config = load_config(config_path)?;
+            // Expansion errors are reported by the validation below.
+            let mut expanded = expand_subgraphs(&config, graph_dir).unwrap_or(config);
+            let descriptions = describe_node_types(&expanded, graph_dir, components_dirs).await;
+            let diagnostics = validate_file_with(config_path, &descriptions)?;
+            for diagnostic in &diagnostics {
+                match diagnostic.location {
+                    Some(location) => println!(
+                        "{}:{}: {} ({})",
+                        config_path, location, diagnostic.message, diagnostic.path
+                    ),
+                    None => println!(
+                        "{}: {} ({})",
+                        config_path, diagnostic.message, diagnostic.path
+                    ),
+                }
+            }
+            if !diagnostics.is_empty() {
+                return Err(CliError::ValidationError(diagnostics.len()));
+            }
+            fill_implementations(&mut expanded, &descriptions);
+            let graph = Graph::new(&expanded)?;
+            if let Err(GraphError::TypeMismatchError(mismatches)) =
+                graph.check_types(&type_rules(*strict_types))
+            {
+                for mismatch in &mismatches {
+                    println!("{}: type mismatch: {}", config_path, mismatch);
+                }
+                return Err(CliError::ValidationError(mismatches.len()));
+            }
+            println!("{}: ok", config_path);
+        }
+        Commands::Describe { wasm } => describe_component(wasm).await?,
+        Commands::Inspect { wasm, json } => inspect_component(wasm, *json).await?,
+        Commands::Run {
+            graph,
+            components_dirs,
+            output,
+            no_cache,
+            result_cache,
+            cache_dir,
+            stream,
+            interval,
+            ticks,
+            channel_capacity,
+            batch,
+            jobs,
+            strict_types,
+            instances,
+            pooling_allocator,
+            inputs,
+            call,
+        } => {
+            let mode = match batch {
+                Some(rows_path) => RunMode::Batch(rows_path.clone()),
+                None if *stream => RunMode::Stream(StreamOptions {
+                    interval: *interval,
+                    ticks: *ticks,
+                    channel_capacity: *channel_capacity,
+                }),
+                None if *call || !inputs.is_empty() => {
+                    RunMode::Call(inputs.iter().cloned().collect())
+                }
+                None => RunMode::Once,
+            };
+            let root = cache_dir.clone().unwrap_or_else(cache_root);
+            let caches = Caches {
+                components: (!*no_cache)
+                    .then(|| ComponentCache::new(ComponentCache::dir_in(&root))),
+                results: result_cache.then(|| ResultCache::new(ResultCache::dir_in(&root))),
+            };
+            let runtime_config = RuntimeConfig {
+                instance_reuse: (*instances).into(),
+                pooling_allocator: *pooling_allocator,
+                ..Default::default()
+            };
+            run_graph(
+                graph,
+                components_dirs,
+                *output,
+                caches,
+                mode,
+                PlanOptions {
+                    jobs: *jobs,
+                    type_rules: type_rules(*strict_types),
+                },
+                runtime_config,
+            )
+            .await?
+        }
+        Commands::Compose {
+            graph,
+            components_dirs,
+            output,
+        } => compose(graph, components_dirs, output.as_deref()).await?,
+        Commands::Cache { cache_dir, command } => {
+            let root = cache_dir.clone().unwrap_or_else(cache_root);
+            manage_caches(command, &root).map_err(anyhow::Error::from)?
+        }
     }
 
     Ok(())
 }
 
+/// The rules `validate` and `run` check port types with.
+fn type_rules(strict: bool) -> TypeRules {
+    if strict {
+        TypeRules::strict()
+    } else {
+        TypeRules::default()
+    }
+}
+
+fn manage_caches(command: &CacheCommands, root: &Path) -> io::Result<()> {
+    let component_cache = ComponentCache::new(ComponentCache::dir_in(root));
+    let result_cache = ResultCache::new(ResultCache::dir_in(root));
+    match command {
+        CacheCommands::Clear => {
+            print_removed(
+                "component(s)",
+                component_cache.clear()?,
+                component_cache.dir(),
+            );
+            print_removed("result(s)", result_cache.clear()?, result_cache.dir());
+        }
+        CacheCommands::Stats => {
+            for (name, stats, dir) in [
+                (
+                    "Components",
+                    component_cache.stats()?,
+                    component_cache.dir(),
+                ),
+                ("Results", result_cache.stats()?, result_cache.dir()),
+            ] {
+                println!("{}:", name);
+                println!("  Directory: {:?}", dir);
+                println!("  Entries: {}", stats.entries);
+                println!("  Size: {} bytes", stats.size_bytes);
+            }
+        }
+        CacheCommands::Inspect => {
+            let entries = result_cache.entries()?;
+            println!(
+                "{:<64}  {:<20}  {:>10}  LAST USED",
+                "KEY", "NODE TYPE", "BYTES"
+            );
+            for entry in entries {
+                let age = entry.last_used.elapsed().unwrap_or_default();
+                println!(
+                    "{:<64}  {:<20}  {:>10}  {} min ago",
+                    entry.key,
+                    entry.node_type,
+                    entry.size_bytes,
+                    age.as_secs() / 60
+                );
+            }
+        }
+        CacheCommands::Gc { max_age_days } => {
+            let max_age = Duration::from_secs(max_age_days * 24 * 60 * 60);
+            print_removed(
+                "component(s)",
+                component_cache.gc(max_age)?,
+                component_cache.dir(),
+            );
+            print_removed("result(s)", result_cache.gc(max_age)?, result_cache.dir());
+        }
+    }
+    Ok(())
+}
+
+fn print_removed(what: &str, stats: CacheStats, dir: &Path) {
+    println!(
+        "Removed {} cached {}, {} bytes from {:?}",
+        stats.entries, what, stats.size_bytes, dir
+    );
+}
+
+/// Returns the directory relative paths in a graph file are resolved against.
+fn graph_dir(graph_path: &Path) -> &Path {
+    graph_path.parent().unwrap_or_else(|| Path::new("."))
+}
+
 fn load_wasm_module<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
     std::fs::read(path)
 }
 
-fn get_plugins_from_path(path: &str) -> anyhow::Result<Vec<PathBuf>> {
-    let mut plugins = std::fs::read_dir(path)?
-        .filter_map(|res| res.ok())
-        .map(|dir_entry| dir_entry.path())
-        .filter_map(|path| {
-            if path.extension().map_or(false, |ext| ext == "wasm") {
-                Some(path)
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<_>>();
-    plugins.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
-    Ok(plugins)
+/// Finds the component of a node implementation.
+///
+/// `wasm` may be a file name with or without the `.wasm` extension or a path, and is
+/// looked up relative to the graph file first and then in the given components directories.
+/// Components of subgraphs in other directories are also found in the components directories
+/// by their file name.
+fn resolve_component(wasm: &str, graph_dir: &Path, components_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let with_extension = |path: &Path| {
+        let mut candidates = vec![path.to_path_buf()];
+        if path.extension().is_none() {
+            candidates.push(PathBuf::from(format!("{}.wasm", path.display())));
+        }
+        candidates
+    };
+    let candidates = with_extension(Path::new(wasm));
+    let file_names = Path::new(wasm)
+        .file_name()
+        .map(|file_name| with_extension(Path::new(file_name)))
+        .unwrap_or_default();
+    let in_graph_dir = candidates.iter().map(|candidate| graph_dir.join(candidate));
+    let in_components_dirs = components_dirs.iter().flat_map(|dir| {
+        candidates
+            .iter()
+            .chain(&file_names)
+            .map(move |candidate| dir.join(candidate))
+    });
+    in_graph_dir
+        .chain(in_components_dirs)
+        .find(|path| path.is_file())
+}
+
+/// Finds the component implementing a node type, see [`resolve_component`].
+fn node_component(
+    node_type: &str,
+    node_implementation: &NodeImplementationData,
+    graph_dir: &Path,
+    components_dirs: &[PathBuf],
+) -> Result<PathBuf, anyhow::Error> {
+    let wasm = node_implementation
+        .wasm
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Node type <{}> has no component", node_type))?;
+    resolve_component(wasm, graph_dir, components_dirs).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Component <{}> of node type <{}> not found",
+            wasm,
+            node_type
+        )
+    })
+}
+
+/// Loads the component or native node implementing a node type into the runtime.
+async fn load_node_implementation(
+    runtime: &mut Runtime,
+    node_type: &str,
+    node_implementation: &NodeImplementationData,
+    graph_dir: &Path,
+    components_dirs: &[PathBuf],
+) -> Result<(), anyhow::Error> {
+    if let Some(native) = &node_implementation.native {
+        eprintln!("Loading native node: {} ({})", node_type, native);
+        runtime.load_native(node_type, native)?;
+    } else {
+        let component_path =
+            node_component(node_type, node_implementation, graph_dir, components_dirs)?;
+        eprintln!("Loading component: {} ({:?})", node_type, component_path);
+        runtime
+            .load_component(node_type, &load_wasm_module(&component_path)?)
+            .await?;
+    }
+    Ok(())
 }
 
-async fn test_sum_graph() -> Result<(), anyhow::Error> {
+/// Returns the descriptions of the node types of the config loaded into the runtime.
+fn loaded_descriptions(
+    runtime: &Runtime,
+    config: &GraphConfig,
+) -> HashMap<String, NodeDescription> {
+    config
+        .node_implementations
+        .keys()
+        .filter_map(|node_type| Some((node_type.clone(), runtime.node_description(node_type)?)))
+        .collect()
+}
+
+/// Loads the node types whose implementations leave ports undeclared and returns their
+/// descriptions.
+///
+/// Node types that fail to load are skipped with a warning; their undeclared ports are then
+/// reported by validation.
+async fn describe_node_types(
+    config: &GraphConfig,
+    graph_dir: &Path,
+    components_dirs: &[PathBuf],
+) -> HashMap<String, NodeDescription> {
+    let mut runtime = Runtime::new();
+    for (node_type, node_implementation) in &config.node_implementations {
+        let declared = node_implementation.input.is_some() && node_implementation.output.is_some();
+        if declared || node_implementation.graph.is_some() {
+            continue;
+        }
+        if let Err(err) = load_node_implementation(
+            &mut runtime,
+            node_type,
+            node_implementation,
+            graph_dir,
+            components_dirs,
+        )
+        .await
+        {
+            eprintln!("Warning: {}: {}", node_type, err);
+        }
+    }
+    loaded_descriptions(&runtime, config)
+}
+
+/// Loads a graph file with its subgraphs expanded and the ports its node implementations
+/// leave undeclared declared from their descriptions, see [`describe_node_types`].
+async fn load_graph_config(
+    graph_path: &Path,
+    components_dirs: &[PathBuf],
+) -> Result<GraphConfig, anyhow::Error> {
+    let config_path = graph_path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid graph path: {:?}", graph_path))?;
+    let graph_dir = graph_dir(graph_path);
+    let mut config = expand_subgraphs(&load_config(config_path)?, graph_dir)?;
+    let descriptions = describe_node_types(&config, graph_dir, components_dirs).await;
+    fill_implementations(&mut config, &descriptions);
+    Ok(config)
+}
+
+/// Resolves relative preopened host directories against the directory of the graph file.
+fn resolve_preopens(config: &mut GraphConfig, graph_dir: &Path) {
+    let capabilities = config
+        .node_implementations
+        .values_mut()
+        .filter_map(|implementation| implementation.capabilities.as_mut())
+        .chain(
+            config
+                .nodes
+                .values_mut()
+                .filter_map(|node| node.capabilities.as_mut()),
+        );
+    for capabilities in capabilities {
+        for preopen in &mut capabilities.preopens {
+            preopen.host = graph_dir.join(&preopen.host).to_string_lossy().into_owned();
+        }
+    }
+}
+
+async fn describe_component(path: &Path) -> Result<(), anyhow::Error> {
+    let wasm = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid component path: {:?}", path))?;
+    let mut runtime = Runtime::new();
+    runtime
+        .load_component(wasm, &load_wasm_module(path)?)
+        .await?;
+    let implementation = runtime.describe_component(wasm, wasm)?;
+    println!("{}", serde_json::to_string_pretty(&implementation)?);
+    Ok(())
+}
+
+async fn inspect_component(path: &Path, json: bool) -> Result<(), anyhow::Error> {
+    let wasm = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid component path: {:?}", path))?;
+    let mut runtime = Runtime::new();
+    runtime
+        .load_component(wasm, &load_wasm_module(path)?)
+        .await?;
+    let description = runtime
+        .node_description(wasm)
+        .ok_or_else(|| anyhow::anyhow!("Component {:?} has no describe export", path))?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&description)?);
+        return Ok(());
+    }
+
+    println!("{} {}", description.name, description.version);
+    if !description.description.is_empty() {
+        println!("{}", description.description);
+    }
+    if let Some(author) = &description.author {
+        println!("Author: {}", author);
+    }
+    if !description.categories.is_empty() {
+        println!("Categories: {}", description.categories.join(", "));
+    }
+    for (title, ports) in [
+        ("INPUT", &description.inputs),
+        ("OUTPUT", &description.outputs),
+        ("STATE", &description.state),
+    ] {
+        if ports.is_empty() {
+            continue;
+        }
+        let rows: Vec<Vec<String>> = ports
+            .iter()
+            .map(|port| {
+                vec![
+                    port.name.clone(),
+                    port.r#type.clone(),
+                    port.default
+                        .as_ref()
+                        .map(Value::to_string)
+                        .unwrap_or_default(),
+                    port.doc.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+        println!();
+        print_table(&[title, "TYPE", "DEFAULT", "DOC"], &rows);
+    }
+    Ok(())
+}
+
+async fn compose(
+    graph_path: &Path,
+    components_dirs: &[PathBuf],
+    output: Option<&Path>,
+) -> Result<(), anyhow::Error> {
+    let graph_dir = graph_dir(graph_path);
+    let config = load_graph_config(graph_path, components_dirs).await?;
+    let graph = Graph::new(&config)?;
+
+    let mut components = HashMap::new();
+    for (node_type, node_implementation) in &config.node_implementations {
+        let component_path =
+            node_component(node_type, node_implementation, graph_dir, components_dirs)?;
+        eprintln!("Loading component: {} ({:?})", node_type, component_path);
+        components.insert(node_type.clone(), load_wasm_module(&component_path)?);
+    }
+
+    let composed = compose_graph(&graph, &components)?;
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| graph_path.with_extension("wasm"));
+    std::fs::write(&output, &composed)?;
+    println!(
+        "Composed {} nodes into {:?} ({} bytes)",
+        graph.graph().node_count(),
+        output,
+        composed.len()
+    );
+    Ok(())
+}
+
+async fn run_graph(
+    graph_path: &Path,
+    components_dirs: &[PathBuf],
+    output: OutputFormat,
+    caches: Caches,
+    mode: RunMode,
+    plan_options: PlanOptions,
+    mut runtime_config: RuntimeConfig,
+) -> Result<(), anyhow::Error> {
     // Load graph from graph config file
     let total_start_time = Instant::now();
     let start_time = Instant::now();
-    println!("Load graph...");
-    let config_path = "compiled/sum-graph/graph.json";
-    let config = load_config(config_path)?;
-    let graph = Graph::new(&config)?;
-    println!("Time taken: {} ms\n", start_time.elapsed().as_millis());
+    eprintln!("Load graph...");
+    let config_path = graph_path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid graph path: {:?}", graph_path))?;
+    let graph_dir = graph_dir(graph_path);
+    let mut config = expand_subgraphs(&load_config(config_path)?, graph_dir)?;
+    resolve_preopens(&mut config, graph_dir);
+    eprintln!("Time taken: {} ms\n", start_time.elapsed().as_millis());
 
-    // Load node implementations to plugins
+    // Load node implementations to runtime, declaring the ports the graph leaves out
     let start_time = Instant::now();
-    println!("Load Plugins...");
-    let plugins = get_plugins_from_path("compiled/sum-graph")?;
-    let graph_node_implementations =
-        config
-            .clone()
-            .node_implementations
-            .into_iter()
-            .map(|(node_id, node_implementation)| {
-                let plugin_path = plugins.iter().find(|path| {
-                    path.file_stem().unwrap().to_str().unwrap() == node_implementation.wasm
-                });
-                (node_id, plugin_path)
-            });
-    println!("Plugins: {:?}", graph_node_implementations);
-    println!("Time taken: {} ms\n", start_time.elapsed().as_millis());
+    eprintln!("Load node implementations...");
+    // Fuel metering and epoch interruption slow down every node, so they are only enabled
+    // for graphs with fuel or timeout limits.
+    runtime_config.enforce_limits = config
+        .nodes
+        .values()
+        .filter_map(|node| node.limits)
+        .chain(
+            config
+                .node_implementations
+                .values()
+                .filter_map(|implementation| implementation.limits),
+        )
+        .any(|limits| limits.needs_enforcement());
+    let mut runtime = Runtime::from_config(runtime_config)?;
+    if let Some(cache) = caches.components {
+        runtime = runtime.with_component_cache(cache);
+    }
+    if let Some(cache) = caches.results {
+        runtime = runtime.with_result_cache(cache);
+    }
+    for (node_type, node_implementation) in &config.node_implementations {
+        load_node_implementation(
+            &mut runtime,
+            node_type,
+            node_implementation,
+            graph_dir,
+            components_dirs,
+        )
+        .await?;
+    }
+    let descriptions = loaded_descriptions(&runtime, &config);
+    fill_implementations(&mut config, &descriptions);
+    for (node_type, node_implementation) in &config.node_implementations {
+        for mismatch in runtime.verify_component(node_type, node_implementation)? {
+            eprintln!("Warning: {}: {}", node_type, mismatch);
+        }
+    }
+    eprintln!("Time taken: {} ms\n", start_time.elapsed().as_millis());
 
     // Create a graph execution plan
     let start_time = Instant::now();
-    println!("Create execution plan...");
-    let execution_plan = ExecutionPlan::from_graph(&graph);
-    println!("Execution Plan: {:?}", execution_plan);
-    println!("Time taken: {} ms\n", start_time.elapsed().as_millis());
-
-    // Create graph runtime
-    let start_time = Instant::now();
-    println!("Create runtime...");
-    let mut runtime = Runtime::new();
-    println!("Time taken: {} ms\n", start_time.elapsed().as_millis());
+    eprintln!("Create execution plan...");
+    let graph = Graph::new(&config)?;
+    if let Err(GraphError::TypeMismatchError(mismatches)) =
+        graph.check_types(&plan_options.type_rules)
+    {
+        for mismatch in &mismatches {
+            eprintln!("Error: type mismatch: {}", mismatch);
+        }
+        return Err(anyhow::anyhow!(
+            "Graph has {} type mismatch(es)",
+            mismatches.len()
+        ));
+    }
+    let mut execution_plan = ExecutionPlan::from_graph(&graph)?;
+    if let Some(jobs) = plan_options.jobs {
+        execution_plan = execution_plan.with_max_concurrency(jobs);
+    }
+    eprintln!("Execution Plan: {:?}", execution_plan.levels());
+    eprintln!("Time taken: {} ms\n", start_time.elapsed().as_millis());
 
-    // Load node implementations to runtime
-    let start_time = Instant::now();
-    println!("Load node implementations...");
-    for (node_id, plugin_path) in graph_node_implementations {
-        let plugin_path = plugin_path
-            .ok_or_else(|| anyhow::anyhow!("Plugin not found for node_id: {}", node_id))?;
-        let wasm_module_buffer = load_wasm_module(plugin_path)?;
-        println!("Loading component: {}", node_id);
-        runtime
-            .load_component(&node_id, &wasm_module_buffer)
-            .await?;
+    match mode {
+        RunMode::Once => {}
+        RunMode::Call(inputs) => {
+            eprintln!("Call graph...");
+            return call_graph(&execution_plan, &runtime, &inputs, output).await;
+        }
+        RunMode::Stream(options) => {
+            eprintln!("Stream plan...");
+            return stream_graph(&execution_plan, &runtime, &options, output).await;
+        }
+        RunMode::Batch(rows_path) => {
+            eprintln!("Execute batch...");
+            return run_batch(&execution_plan, &runtime, &rows_path, output).await;
+        }
     }
-    println!("Time taken: {} ms\n", start_time.elapsed().as_millis());
 
     // Execute graph
     let start_time = Instant::now();
-    println!("Execute plan...");
-    let results = execution_plan.execute(&mut runtime).await?;
-    println!("Results: {:?}", results);
-    println!("Time taken: {} ms\n", start_time.elapsed().as_millis());
-
-    // After graph experiment done
-    println!(
+    eprintln!("Execute plan...");
+    let result = execution_plan.execute(&mut runtime).await?;
+    eprintln!("Time taken: {} ms\n", start_time.elapsed().as_millis());
+    eprintln!(
         "Total Time taken: {} ms",
         total_start_time.elapsed().as_millis()
     );
+
+    match output {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&result_to_json(&result)?)?
+        ),
+        OutputFormat::Table => {
+            print_results_table(&execution_plan, &result.outputs);
+            print_logs(&execution_plan, &result.logs);
+        }
+        OutputFormat::Csv => print_csv(
+            &["node", "port", "value"],
+            &output_rows(&execution_plan, &result.outputs),
+        ),
+    }
+    Ok(())
+}
+
+/// Executes the plan on the given graph inputs and prints the graph outputs.
+async fn call_graph(
+    execution_plan: &ExecutionPlan,
+    runtime: &Runtime,
+    inputs: &Map<String, Value>,
+    output: OutputFormat,
+) -> Result<(), anyhow::Error> {
+    let start_time = Instant::now();
+    let outputs = execution_plan.call(runtime, inputs).await?;
+    eprintln!("Time taken: {} ms\n", start_time.elapsed().as_millis());
+
+    let rows: Vec<Vec<String>> = execution_plan
+        .output_names()
+        .into_iter()
+        .filter_map(|name| Some(vec![name.to_string(), outputs.get(name)?.to_string()]))
+        .collect();
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&outputs)?),
+        OutputFormat::Table => print_table(&["OUTPUT", "VALUE"], &rows),
+        OutputFormat::Csv => print_csv(&["output", "value"], &rows),
+    }
+    Ok(())
+}
+
+/// Executes the plan for every row of a JSON Lines file and prints one result per row.
+///
+/// Fails after printing all results if any row failed.
+async fn run_batch(
+    execution_plan: &ExecutionPlan,
+    runtime: &Runtime,
+    rows_path: &Path,
+    output: OutputFormat,
+) -> Result<(), anyhow::Error> {
+    let content = std::fs::read_to_string(rows_path)?;
+    let mut rows = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: Map<String, Value> = serde_json::from_str(line).map_err(|err| {
+            anyhow::anyhow!("{}:{}: {}", rows_path.display(), line_number + 1, err)
+        })?;
+        let row = BatchRow::from_json(&row, execution_plan).map_err(|err| {
+            anyhow::anyhow!("{}:{}: {}", rows_path.display(), line_number + 1, err)
+        })?;
+        rows.push(row);
+    }
+
+    let start_time = Instant::now();
+    let results = execution_plan.execute_batch(runtime, &rows).await?;
+    eprintln!(
+        "Executed {} row(s) in {} ms\n",
+        results.len(),
+        start_time.elapsed().as_millis()
+    );
+
+    // Columns are the output ports of every node in plan order.
+    let mut columns = Vec::new();
+    for node_id in execution_plan.levels().into_iter().flatten() {
+        let mut ports: Vec<&String> = results
+            .iter()
+            .filter_map(|result| result.result.as_ref().ok())
+            .filter_map(|outputs| outputs.outputs.get(node_id))
+            .flat_map(|outputs| outputs.keys())
+            .collect();
+        ports.sort();
+        ports.dedup();
+        columns.extend(ports.into_iter().map(|port| (node_id, port.clone())));
+    }
+
+    match output {
+        OutputFormat::Json => {
+            for result in &results {
+                let mut json = Map::new();
+                json.insert("row".to_string(), Value::from(result.row));
+                match &result.result {
+                    Ok(row) => {
+                        json.insert("outputs".to_string(), outputs_to_json(&row.outputs));
+                        json.insert("logs".to_string(), serde_json::to_value(&row.logs)?);
+                    }
+                    Err(err) => {
+                        json.insert("error".to_string(), Value::String(err.to_string()));
+                    }
+                }
+                println!("{}", Value::Object(json));
+            }
+        }
+        OutputFormat::Table | OutputFormat::Csv => {
+            let header: Vec<String> = std::iter::once("row".to_string())
+                .chain(
+                    columns
+                        .iter()
+                        .map(|(node_id, port)| format!("{}.{}", node_id, port)),
+                )
+                .chain(std::iter::once("error".to_string()))
+                .collect();
+            let header: Vec<&str> = header.iter().map(String::as_str).collect();
+            let rows: Vec<Vec<String>> = results
+                .iter()
+                .map(|result| {
+                    let outputs = result.result.as_ref().ok();
+                    let mut fields = vec![result.row.to_string()];
+                    for (node_id, port) in &columns {
+                        let value = outputs
+                            .and_then(|row| row.outputs.get(*node_id))
+                            .and_then(|outputs| outputs.get(port))
+                            .map(|value| val_to_json_untyped(value).to_string());
+                        fields.push(value.unwrap_or_default());
+                    }
+                    let error = result.result.as_ref().err().map(ToString::to_string);
+                    fields.push(error.unwrap_or_default());
+                    fields
+                })
+                .collect();
+            if matches!(output, OutputFormat::Csv) {
+                print_csv(&header, &rows);
+            } else {
+                print_table(&header, &rows);
+            }
+        }
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| result.result.is_err())
+        .count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} row(s) failed", failed, results.len());
+    }
+    Ok(())
+}
+
+/// Streams the plan and prints every event as soon as it was processed.
+async fn stream_graph(
+    execution_plan: &ExecutionPlan,
+    runtime: &Runtime,
+    options: &StreamOptions,
+    output: OutputFormat,
+) -> Result<(), anyhow::Error> {
+    let (sender, mut receiver) = mpsc::channel(options.channel_capacity);
+    let print_events = async {
+        while let Some(event) = receiver.next().await {
+            print_event(&event, output)?;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    let (result, printed) = join(
+        execution_plan.execute_stream(runtime, options, sender),
+        print_events,
+    )
+    .await;
+    result?;
+    printed
+}
+
+fn print_event(event: &StreamEvent, output: OutputFormat) -> Result<(), serde_json::Error> {
+    let mut ports: Vec<_> = event.outputs.iter().collect();
+    ports.sort_by(|a, b| a.0.cmp(b.0));
+    match output {
+        OutputFormat::Json => {
+            let mut json = Map::new();
+            json.insert("sequence".to_string(), Value::from(event.sequence));
+            json.insert("node".to_string(), Value::String(event.node_id.clone()));
+            let outputs: Map<String, Value> = ports
+                .into_iter()
+                .map(|(port, value)| (port.clone(), val_to_json_untyped(value)))
+                .collect();
+            json.insert("outputs".to_string(), Value::Object(outputs));
+            json.insert("logs".to_string(), serde_json::to_value(&event.logs)?);
+            println!("{}", Value::Object(json));
+        }
+        OutputFormat::Csv => {
+            for (port, value) in ports {
+                let fields = [
+                    event.sequence.to_string(),
+                    event.node_id.clone(),
+                    port.clone(),
+                    val_to_json_untyped(value).to_string(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                println!("{}", fields.join(","));
+            }
+        }
+        OutputFormat::Table => {
+            let outputs: Vec<_> = ports
+                .into_iter()
+                .map(|(port, value)| format!("{}={}", port, val_to_json_untyped(value)))
+                .collect();
+            println!(
+                "#{} {} {}",
+                event.sequence,
+                event.node_id,
+                outputs.join(" ")
+            );
+            for line in &event.logs.stdout {
+                println!("  {}", line);
+            }
+            for line in &event.logs.stderr {
+                println!("  stderr: {}", line);
+            }
+        }
+    }
     Ok(())
 }
+
+/// Parses a graph input of the form `name=value`, where the value is JSON or a plain string.
+fn parse_input(input: &str) -> Result<(String, Value), String> {
+    let (name, value) = input
+        .split_once('=')
+        .ok_or_else(|| format!("invalid input <{}>, expected NAME=VALUE", input))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    Ok((name.trim().to_string(), value))
+}
+
+/// Parses durations like `250ms`, `2s` or `1m`. Plain numbers are milliseconds.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration <{}>", value))?;
+    match unit.trim() {
+        "" | "ms" => Ok(Duration::from_millis(amount)),
+        "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount * 60)),
+        unit => Err(format!("unknown duration unit <{}>, use ms, s or m", unit)),
+    }
+}
+
+fn result_to_json(result: &ExecutionResult) -> Result<Value, serde_json::Error> {
+    let mut json = Map::new();
+    json.insert("run_id".to_string(), Value::String(result.run_id.clone()));
+    json.insert("outputs".to_string(), outputs_to_json(&result.outputs));
+    json.insert("logs".to_string(), serde_json::to_value(&result.logs)?);
+    Ok(json.into())
+}
+
+fn outputs_to_json(outputs: &HashMap<String, PortValues>) -> Value {
+    outputs
+        .iter()
+        .map(|(node_id, outputs)| {
+            let outputs: Map<String, Value> = outputs
+                .iter()
+                .map(|(port, value)| (port.clone(), val_to_json_untyped(value)))
+                .collect();
+            (node_id.clone(), Value::Object(outputs))
+        })
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+/// Rows of node id, port and JSON encoded value of all outputs, in plan order.
+fn output_rows(
+    execution_plan: &ExecutionPlan,
+    results: &HashMap<String, PortValues>,
+) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    for node_id in execution_plan.levels().into_iter().flatten() {
+        let Some(outputs) = results.get(node_id) else {
+            continue;
+        };
+        let mut ports: Vec<_> = outputs.iter().collect();
+        ports.sort_by(|a, b| a.0.cmp(b.0));
+        for (port, value) in ports {
+            rows.push(vec![
+                node_id.to_string(),
+                port.clone(),
+                val_to_json_untyped(value).to_string(),
+            ]);
+        }
+    }
+    rows
+}
+
+fn print_results_table(execution_plan: &ExecutionPlan, results: &HashMap<String, PortValues>) {
+    print_table(
+        &["NODE", "PORT", "VALUE"],
+        &output_rows(execution_plan, results),
+    );
+}
+
+/// Prints rows as left-aligned columns.
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let header: Vec<String> = header.iter().map(|column| column.to_string()).collect();
+    let mut widths: Vec<usize> = header.iter().map(String::len).collect();
+    for row in rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(rows) {
+        let columns: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| format!("{:<width$}", column, width = width))
+            .collect();
+        println!("{}", columns.join("  ").trim_end());
+    }
+}
+
+fn print_csv(header: &[&str], rows: &[Vec<String>]) {
+    println!("{}", header.join(","));
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        println!("{}", fields.join(","));
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn print_logs(execution_plan: &ExecutionPlan, logs: &HashMap<String, NodeLogs>) {
+    for node_id in execution_plan.levels().into_iter().flatten() {
+        let Some(node_logs) = logs.get(node_id).filter(|node_logs| !node_logs.is_empty()) else {
+            continue;
+        };
+        println!("\nLogs of {}:", node_id);
+        for line in &node_logs.stdout {
+            println!("  {}", line);
+        }
+        for line in &node_logs.stderr {
+            println!("  stderr: {}", line);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Runs graphs through the `cillio-cli` binary.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use serde_json::{json, Value};
+
+/// A node with a `u32` output `value` of 1.
+const SOURCE: &str = r#"(component
+    (core module $m (func (export "process") (result i32) i32.const 1))
+    (core instance $i (instantiate $m))
+    (type $r (record (field "value" u32)))
+    (export $r' "r" (type $r))
+    (func $f (result $r') (canon lift (core func $i "process")))
+    (export "process" (func $f))
+)"#;
+
+/// A node with a `u32` port `value` that returns its input plus one.
+const INCREMENT: &str = r#"(component
+    (core module $m
+        (func (export "process") (param i32) (result i32)
+            local.get 0
+            i32.const 1
+            i32.add))
+    (core instance $i (instantiate $m))
+    (type $r (record (field "value" u32)))
+    (export $r' "r" (type $r))
+    (func $f (param "inputs" $r') (result $r') (canon lift (core func $i "process")))
+    (export "process" (func $f))
+)"#;
+
+/// A node that traps.
+const FAIL: &str = r#"(component
+    (core module $m (func (export "process") (param i32) (result i32) unreachable))
+    (core instance $i (instantiate $m))
+    (type $r (record (field "value" u32)))
+    (export $r' "r" (type $r))
+    (func $f (param "inputs" $r') (result $r') (canon lift (core func $i "process")))
+    (export "process" (func $f))
+)"#;
+
+/// Creates an empty directory for a test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cillio-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a graph of a source node `a` feeding `b` and `b` feeding `c`, both of the node
+/// type `last`, next to an `increment` component.
+fn write_graph(dir: &Path, last: &str) -> PathBuf {
+    let ports = json!({"input": {"value": "int"}, "output": {"value": "int"}});
+    let mut increment = ports.clone();
+    increment["wasm"] = json!("increment");
+    let mut fail = ports;
+    fail["wasm"] = json!("fail.wasm");
+    let graph = json!({
+        "node_implementations": {
+            "source": {"output": {"value": "int"}, "wasm": "source"},
+            "increment": increment,
+            "fail": fail
+        },
+        "nodes": {
+            "a": {"type": "source"},
+            "b": {"type": "increment"},
+            "c": {"type": last}
+        },
+        "edges": [{"from": "a", "to": "b"}, {"from": "b", "to": "c"}]
+    });
+    fs::create_dir_all(dir).unwrap();
+    fs::write(dir.join("increment.wasm"), INCREMENT).unwrap();
+    fs::write(dir.join("fail.wasm"), FAIL).unwrap();
+    let path = dir.join("graph.json");
+    fs::write(&path, graph.to_string()).unwrap();
+    path
+}
+
+fn run(graph: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cillio-cli"))
+        .arg("run")
+        .arg(graph)
+        .arg("--no-cache")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_resolves_components() {
+    let dir = test_dir("resolve");
+    let graph = write_graph(&dir.join("graph"), "increment");
+    let components_dir = dir.join("components");
+    fs::create_dir_all(&components_dir).unwrap();
+    fs::write(components_dir.join("source.wasm"), SOURCE).unwrap();
+
+    // `increment` is found next to the graph, `source` only in the components directory.
+    let output = run(&graph, &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Component <source> of node type <source> not found"));
+
+    let components_dir = components_dir.to_str().unwrap();
+    let output = run(
+        &graph,
+        &["--components-dir", components_dir, "--output", "csv"],
+    );
+    assert!(stdout(&output).contains("c,value,3"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_output_formats() {
+    let dir = test_dir("output");
+    let graph = write_graph(&dir, "increment");
+    fs::write(dir.join("source.wasm"), SOURCE).unwrap();
+
+    let json: Value = serde_json::from_str(&stdout(&run(&graph, &["--output", "json"]))).unwrap();
+    assert_eq!(
+        json["outputs"],
+        json!({"a": {"value": 1}, "b": {"value": 2}, "c": {"value": 3}})
+    );
+
+    let table = stdout(&run(&graph, &["--output", "table"]));
+    let rows: Vec<Vec<&str>> = table
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ["NODE", "PORT", "VALUE"],
+            ["a", "value", "1"],
+            ["b", "value", "2"],
+            ["c", "value", "3"],
+        ]
+    );
+
+    let csv = stdout(&run(&graph, &["--output", "csv"]));
+    assert_eq!(csv, "node,port,value\na,value,1\nb,value,2\nc,value,3\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_node_failure_exits_with_error() {
+    let dir = test_dir("failure");
+    let graph = write_graph(&dir, "fail");
+    fs::write(dir.join("source.wasm"), SOURCE).unwrap();
+
+    let output = run(&graph, &[]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
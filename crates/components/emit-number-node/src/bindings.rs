@@ -75,6 +75,60 @@ pub(crate) use __export_world_emit_number_cabi;
 struct _RetArea([::core::mem::MaybeUninit<u8>; 16]);
 static mut _RET_AREA: _RetArea = _RetArea([::core::mem::MaybeUninit::uninit(); 16]);
 #[allow(dead_code)]
+pub mod cillio {
+    #[allow(dead_code)]
+    pub mod node {
+        #[allow(dead_code, clippy::all)]
+        pub mod host {
+            #[used]
+            #[doc(hidden)]
+            #[cfg(target_arch = "wasm32")]
+            static __FORCE_SECTION_REF: fn() =
+                super::super::super::__link_custom_section_describing_imports;
+            use super::super::super::_rt;
+            /// The `state` of the node as configured in the graph, encoded as UTF-8 JSON bytes.
+            pub type State = _rt::Vec<u8>;
+            #[allow(unused_unsafe, clippy::all)]
+            /// Returns the state of the node instance being executed, if it has any.
+            pub fn get_state() -> Option<State> {
+                unsafe {
+                    #[repr(align(4))]
+                    struct RetArea([::core::mem::MaybeUninit<u8>; 12]);
+                    let mut ret_area = RetArea([::core::mem::MaybeUninit::uninit(); 12]);
+                    let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "cillio:node/host@0.1.0")]
+                    extern "C" {
+                        #[link_name = "get-state"]
+                        fn wit_import(_: *mut u8);
+                    }
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    fn wit_import(_: *mut u8) {
+                        unreachable!()
+                    }
+                    wit_import(ptr0);
+                    let l1 = i32::from(*ptr0.add(0).cast::<u8>());
+                    match l1 {
+                        0 => None,
+                        1 => {
+                            let e = {
+                                let l2 = *ptr0.add(4).cast::<*mut u8>();
+                                let l3 = *ptr0.add(8).cast::<usize>();
+                                let len4 = l3;
+
+                                _rt::Vec::from_raw_parts(l2.cast(), len4, len4)
+                            };
+                            Some(e)
+                        }
+                        _ => _rt::invalid_enum_discriminant(),
+                    }
+                }
+            }
+        }
+    }
+}
+#[allow(dead_code)]
 pub mod cillio_node {
     #[allow(dead_code)]
     pub mod emit_number {
@@ -123,6 +177,7 @@ pub mod cillio_node {
 }
 mod _rt {
     pub use alloc_crate::string::String;
+    pub use alloc_crate::vec::Vec;
 
     #[cfg(target_arch = "wasm32")]
     pub fn run_ctors_once() {
@@ -156,6 +211,13 @@ mod _rt {
         let layout = alloc::Layout::from_size_align_unchecked(size, align);
         alloc::dealloc(ptr as *mut u8, layout);
     }
+    pub unsafe fn invalid_enum_discriminant<T>() -> T {
+        if cfg!(debug_assertions) {
+            panic!("invalid enum discriminant")
+        } else {
+            core::hint::unreachable_unchecked()
+        }
+    }
     extern crate alloc as alloc_crate;
     pub use alloc_crate::alloc;
 }
@@ -1,13 +1,24 @@
 #[allow(warnings)]
 mod bindings;
 
+use bindings::cillio::node::host::get_state;
 use bindings::{Error, Guest, Outputs};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct State {
+    number: f32,
+}
 
 struct Component;
 
 impl Guest for Component {
     fn process() -> Result<Outputs, Error> {
-        let out = Outputs { number: 42.0 };
+        let state: State = cillio_guest::decode_state(get_state())
+            .map_err(|err| Error::ProcessError(err.to_string()))?;
+        let out = Outputs {
+            number: state.number,
+        };
         Ok(out)
     }
 }
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+/// WASI capabilities granted to a node.
+///
+/// Everything not listed is denied: nodes see no directories, no environment variables,
+/// no arguments, frozen clocks and a closed stdin. Stdout and stderr are captured by the
+/// runtime unless configured otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeCapabilities {
+    /// Host directories made available to the node.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preopens: Vec<Preopen>,
+    /// Names of host environment variables passed through to the node.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+    /// Command line arguments of the node.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub stdin: StdioPolicy,
+    #[serde(default = "StdioPolicy::capture")]
+    pub stdout: StdioPolicy,
+    #[serde(default = "StdioPolicy::capture")]
+    pub stderr: StdioPolicy,
+    /// Whether the node can read the host's wall and monotonic clocks.
+    #[serde(default)]
+    pub clocks: bool,
+}
+
+impl Default for NodeCapabilities {
+    fn default() -> Self {
+        Self {
+            preopens: Vec::new(),
+            env: Vec::new(),
+            args: Vec::new(),
+            stdin: StdioPolicy::Deny,
+            stdout: StdioPolicy::Capture,
+            stderr: StdioPolicy::Capture,
+            clocks: false,
+        }
+    }
+}
+
+/// A host directory mounted into a node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Preopen {
+    /// Directory on the host, relative paths are resolved against the graph file.
+    pub host: String,
+    /// Path under which the node sees the directory.
+    pub guest: String,
+    #[serde(default)]
+    pub access: DirAccess,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirAccess {
+    #[default]
+    ReadOnly,
+    ReadWrite,
+}
+
+/// How a standard stream of a node is connected.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StdioPolicy {
+    /// The stream is closed: reads see EOF and writes are discarded.
+    #[default]
+    Deny,
+    /// The stream is the host process' stream.
+    Inherit,
+    /// Output is collected into the node's logs. Treated like `deny` for stdin.
+    Capture,
+}
+
+impl StdioPolicy {
+    fn capture() -> Self {
+        StdioPolicy::Capture
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_deny_by_default() {
+        let capabilities: NodeCapabilities =
+            serde_json::from_str(r#"{"stderr": "inherit"}"#).unwrap();
+        assert_eq!(capabilities.stdin, StdioPolicy::Deny);
+        assert_eq!(capabilities.stdout, StdioPolicy::Capture);
+        assert_eq!(capabilities.stderr, StdioPolicy::Inherit);
+        assert!(capabilities.preopens.is_empty() && !capabilities.clocks);
+        assert_eq!(
+            serde_json::from_str::<NodeCapabilities>("{}").unwrap(),
+            NodeCapabilities::default()
+        );
+
+        let preopen: Preopen =
+            serde_json::from_str(r#"{"host": "data", "guest": "/data"}"#).unwrap();
+        assert_eq!(preopen.access, DirAccess::ReadOnly);
+    }
+}
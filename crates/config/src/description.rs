@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{GraphConfig, NodeImplementationData};
+
+/// What a node tells about itself, e.g. through the `describe` export of its component.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeDescription {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub inputs: Vec<PortDescription>,
+    #[serde(default)]
+    pub outputs: Vec<PortDescription>,
+    /// Keys of the node state.
+    #[serde(default)]
+    pub state: Vec<PortDescription>,
+}
+
+/// A port, or a key of the node state, of a [`NodeDescription`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PortDescription {
+    pub name: String,
+    /// The port type as written in `node_implementations`, see [`crate::PortType`].
+    pub r#type: String,
+    /// Value of an input port of a generic node that is not connected. Otherwise it only
+    /// documents a sensible value, e.g. for state keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+}
+
+impl NodeDescription {
+    /// Declares the ports, and state keys, that the implementation leaves undeclared.
+    pub fn fill_implementation(&self, implementation: &mut NodeImplementationData) {
+        let declared = |ports: &[PortDescription]| -> HashMap<String, String> {
+            ports
+                .iter()
+                .map(|port| (port.name.clone(), port.r#type.clone()))
+                .collect()
+        };
+        if implementation.input.is_none() {
+            implementation.input = Some(declared(&self.inputs));
+        }
+        if implementation.output.is_none() {
+            implementation.output = Some(declared(&self.outputs));
+        }
+        if implementation.state.is_none() && !self.state.is_empty() {
+            implementation.state = Some(declared(&self.state));
+        }
+    }
+}
+
+/// Declares the ports, and state keys, that the node implementations of a config leave
+/// undeclared from the descriptions of their node types.
+///
+/// Node types without a description are kept as they are.
+pub fn fill_implementations(
+    config: &mut GraphConfig,
+    descriptions: &HashMap<String, NodeDescription>,
+) {
+    for (node_type, implementation) in &mut config.node_implementations {
+        if let Some(description) = descriptions.get(node_type) {
+            description.fill_implementation(implementation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_fill_implementation() {
+        let description: NodeDescription = serde_json::from_value(json!({
+            "name": "scale",
+            "version": "1.0.0",
+            "inputs": [{"name": "value", "type": "float", "doc": "Value to scale"}],
+            "outputs": [{"name": "value", "type": "float"}],
+            "state": [{"name": "factor", "type": "float", "default": 1.0}]
+        }))
+        .unwrap();
+        let mut implementation: NodeImplementationData = serde_json::from_value(json!({
+            "input": {"value": "int"},
+            "wasm": "scale"
+        }))
+        .unwrap();
+        description.fill_implementation(&mut implementation);
+
+        // Declared ports are kept.
+        assert_eq!(implementation.input.unwrap()["value"], "int");
+        assert_eq!(implementation.output.unwrap()["value"], "float");
+        assert_eq!(implementation.state.unwrap()["factor"], "float");
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{ConfigError, GraphConfig};
+
+/// Serialization formats a [`GraphConfig`] can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigFormat {
+    Json,
+    Json5,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Determines the format from the file extension of `path`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        extension.parse().ok()
+    }
+
+    /// Guesses the format from the content of a config file.
+    ///
+    /// Content starting with `{`, after whitespace and `//` or `/* */` comments, is JSON if
+    /// it parses as JSON, and JSON5 if it has such comments or is not a YAML flow mapping.
+    /// Other content is TOML if it parses as a TOML document, and YAML otherwise.
+    pub fn detect(content: &str) -> Self {
+        let has_comments = content.lines().any(|line| {
+            let line = line.trim_start();
+            line.starts_with("//") || line.starts_with("/*")
+        });
+        if skip_comments(content).starts_with('{') {
+            if serde_json::from_str::<serde_json::Value>(content).is_ok() {
+                ConfigFormat::Json
+            } else if has_comments || serde_yaml::from_str::<serde_yaml::Value>(content).is_err() {
+                ConfigFormat::Json5
+            } else {
+                ConfigFormat::Yaml
+            }
+        } else if content.parse::<toml::Table>().is_ok() {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Yaml
+        }
+    }
+
+    pub fn parse(&self, content: &str) -> Result<GraphConfig, ConfigError> {
+        let config = match self {
+            ConfigFormat::Json => serde_json::from_str(content)?,
+            ConfigFormat::Json5 => json5::from_str(content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+            ConfigFormat::Toml => toml::from_str(content)?,
+        };
+        Ok(config)
+    }
+
+    pub fn serialize(&self, config: &GraphConfig) -> Result<String, ConfigError> {
+        let content = match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(config)
+                .map_err(|err| ConfigError::SerializeError(err.to_string()))?,
+            ConfigFormat::Json5 => json5::to_string(config)
+                .map_err(|err| ConfigError::SerializeError(err.to_string()))?,
+            ConfigFormat::Yaml => serde_yaml::to_string(config)
+                .map_err(|err| ConfigError::SerializeError(err.to_string()))?,
+            ConfigFormat::Toml => toml::to_string_pretty(config)
+                .map_err(|err| ConfigError::SerializeError(err.to_string()))?,
+        };
+        Ok(content)
+    }
+}
+
+/// Skips the whitespace and JSON5 comments `content` starts with.
+fn skip_comments(mut content: &str) -> &str {
+    loop {
+        content = content.trim_start();
+        if let Some(rest) = content.strip_prefix("//") {
+            content = rest.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(rest) = content.strip_prefix("/*") {
+            content = rest.split_once("*/").map_or("", |(_, rest)| rest);
+        } else {
+            return content;
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ConfigFormat::Json),
+            "json5" => Ok(ConfigFormat::Json5),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            "toml" => Ok(ConfigFormat::Toml),
+            _ => Err(ConfigError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Json5 => "json5",
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Toml => "toml",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -1,21 +1,105 @@
+mod capabilities;
+mod description;
+mod format;
+mod port_type;
+mod subgraph;
+mod validate;
+
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
 use thiserror::Error;
 
+pub use capabilities::{DirAccess, NodeCapabilities, Preopen, StdioPolicy};
+pub use description::{fill_implementations, NodeDescription, PortDescription};
+pub use format::ConfigFormat;
+pub use port_type::{PortType, PortTypeError, TypeRules};
+pub use subgraph::{expand_subgraphs, expand_subgraphs_except, SubgraphData, SUBGRAPH_SEPARATOR};
+pub use validate::{
+    validate, validate_file, validate_file_with, Diagnostic, DiagnosticKind, Location,
+};
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct NodeImplementationData {
     pub input: Option<HashMap<String, String>>,
     pub output: Option<HashMap<String, String>>,
     pub state: Option<HashMap<String, String>>,
-    pub wasm: String,
+    /// Component implementing the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wasm: Option<String>,
+    /// Graph file implementing a composite node instead of a component. Its graph inputs
+    /// and outputs are the ports of the node, see [`expand_subgraphs`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graph: Option<String>,
+    /// Native node registered in the runtime, e.g. `std/add`, implementing the node
+    /// instead of a component.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<NodeLimits>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<NodeCapabilities>,
+    /// Whether the outputs only depend on the inputs and the state, which allows caching
+    /// results across runs. Nodes with side effects declare `false`.
+    #[serde(default = "default_deterministic", skip_serializing_if = "is_true")]
+    pub deterministic: bool,
+}
+
+fn default_deterministic() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct NodeData {
     pub r#type: String,
     pub state: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<NodeLimits>,
+    /// Replaces the capabilities of the node's implementation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<NodeCapabilities>,
+    /// States a source node takes on successive ticks in streaming mode, which ends after
+    /// the last one. Without a sequence the node is polled with its `state` on every tick.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<Vec<serde_json::Value>>,
+}
+
+/// Execution limits of a single `process` call of a node.
+///
+/// Limits set on a node take precedence over the ones set on its implementation.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeLimits {
+    /// Fuel budget, roughly the number of executed WebAssembly instructions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<u64>,
+    /// Wall-clock timeout in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Maximum size of a linear memory in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_bytes: Option<usize>,
+}
+
+impl NodeLimits {
+    /// Returns these limits with unset limits taken from `fallback`.
+    pub fn or(self, fallback: NodeLimits) -> NodeLimits {
+        NodeLimits {
+            fuel: self.fuel.or(fallback.fuel),
+            timeout_ms: self.timeout_ms.or(fallback.timeout_ms),
+            max_memory_bytes: self.max_memory_bytes.or(fallback.max_memory_bytes),
+        }
+    }
+
+    /// Whether a fuel or timeout limit is set, which the runtime only enforces if
+    /// configured to.
+    pub fn needs_enforcement(&self) -> bool {
+        self.fuel.is_some() || self.timeout_ms.is_some()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -26,11 +110,29 @@ pub struct EdgeData {
     pub to_port: Option<String>,
 }
 
+/// A node port exposed as an input or output of the whole graph.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GraphPortData {
+    pub node: String,
+    /// May be omitted if the node declares a single port on that side.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct GraphConfig {
     pub node_implementations: HashMap<String, NodeImplementationData>,
     pub nodes: HashMap<String, NodeData>,
     pub edges: Vec<EdgeData>,
+    /// Inputs of the graph, each feeding an input port of a node.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub inputs: HashMap<String, GraphPortData>,
+    /// Outputs of the graph, each reading an output port of a node.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub outputs: HashMap<String, GraphPortData>,
+    /// Composite nodes replaced by the nodes of their subgraph, keyed by node id.
+    #[serde(skip)]
+    pub subgraphs: HashMap<String, SubgraphData>,
 }
 
 #[derive(Error, Debug)]
@@ -40,14 +142,48 @@ pub enum ConfigError {
 
     #[error("Failed to parse JSON: {0}")]
     JsonParseError(#[from] serde_json::Error),
+
+    #[error("Failed to parse JSON5: {0}")]
+    Json5ParseError(#[from] json5::Error),
+
+    #[error("Failed to parse YAML: {0}")]
+    YamlParseError(#[from] serde_yaml::Error),
+
+    #[error("Failed to parse TOML: {0}")]
+    TomlParseError(#[from] toml::de::Error),
+
+    #[error("Failed to serialize config: {0}")]
+    SerializeError(String),
+
+    #[error("Unknown config format: {0}")]
+    UnknownFormat(String),
+
+    #[error("Failed to expand subgraph: {0}")]
+    SubgraphError(String),
 }
 
+/// Loads a graph config, detecting its format from the file extension or, failing that,
+/// from the file content.
 pub fn load_config(path: &str) -> Result<GraphConfig, ConfigError> {
     let mut file = File::open(path)?;
     let mut config_str = String::new();
     file.read_to_string(&mut config_str)?;
-    let config: GraphConfig = serde_json::from_str(&config_str)?;
-    Ok(config)
+    let format = ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::detect(&config_str));
+    format.parse(&config_str)
+}
+
+/// Writes a graph config to `path`. Without an explicit format it is taken from the file
+/// extension and defaults to JSON.
+pub fn save_config(
+    config: &GraphConfig,
+    path: &str,
+    format: Option<ConfigFormat>,
+) -> Result<(), ConfigError> {
+    let format = format
+        .or_else(|| ConfigFormat::from_path(path))
+        .unwrap_or(ConfigFormat::Json);
+    fs::write(path, format.serialize(config)?)?;
+    Ok(())
 }
 
 pub fn print_config(config: &GraphConfig) {
@@ -64,4 +200,80 @@ pub fn print_config(config: &GraphConfig) {
     for edge in &config.edges {
         println!("  {:?}", edge);
     }
+    if !config.inputs.is_empty() {
+        println!("Inputs:");
+        for (key, value) in &config.inputs {
+            println!("  {}: {:?}", key, value);
+        }
+    }
+    if !config.outputs.is_empty() {
+        println!("Outputs:");
+        for (key, value) in &config.outputs {
+            println!("  {}: {:?}", key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset_path(file: &str) -> String {
+        format!(
+            "{}/../../assets/sum_graph/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            file
+        )
+    }
+
+    #[test]
+    fn test_load_yaml_matches_json() {
+        let json = load_config(&asset_path("sum_graph.json")).unwrap();
+        let yaml = load_config(&asset_path("sum_graph.yaml")).unwrap();
+        assert_eq!(json, yaml);
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        let config = load_config(&asset_path("sum_graph.json")).unwrap();
+        for format in [
+            ConfigFormat::Json,
+            ConfigFormat::Json5,
+            ConfigFormat::Yaml,
+            ConfigFormat::Toml,
+        ] {
+            let content = format.serialize(&config).unwrap();
+            assert_eq!(format.parse(&content).unwrap(), config);
+            let detected = ConfigFormat::detect(&content);
+            assert_eq!(detected.parse(&content).unwrap(), config, "{}", detected);
+        }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ConfigFormat::from_path("graph.yml"),
+            Some(ConfigFormat::Yaml)
+        );
+        assert_eq!(
+            ConfigFormat::from_path("graph.TOML"),
+            Some(ConfigFormat::Toml)
+        );
+        assert_eq!(ConfigFormat::from_path("graph"), None);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let yaml = "nodes:\n  a:\n    type: \"x\"\n    state:\n      formula: \"y = 2\"\n";
+        assert_eq!(ConfigFormat::detect(yaml), ConfigFormat::Yaml);
+        let toml = "edges = []\n\n[nodes.a]\ntype = \"x\"\n";
+        assert_eq!(ConfigFormat::detect(toml), ConfigFormat::Toml);
+        let json5 = "// A graph\n/* with comments */\n{ nodes: {}, edges: [] }\n";
+        assert_eq!(ConfigFormat::detect(json5), ConfigFormat::Json5);
+        let json5 = r"{ nodes: { a: { type: 'x', state: { label: 'it\'s' } } } }";
+        assert_eq!(ConfigFormat::detect(json5), ConfigFormat::Json5);
+        let flow_mapping = "{nodes: {a: {type: x}}, edges: []}\n";
+        assert_eq!(ConfigFormat::detect(flow_mapping), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::detect("{a: 1}"), ConfigFormat::Yaml);
+    }
 }
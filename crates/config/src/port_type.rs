@@ -0,0 +1,281 @@
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid port type <{input}>: {message}")]
+pub struct PortTypeError {
+    pub input: String,
+    pub message: String,
+}
+
+/// The type of a value flowing through a node port.
+///
+/// Port types are written as strings in `node_implementations`, e.g. `float`,
+/// `list<int>`, `option<string>` (or `string?`) and `record<x: float, y: float>`.
+/// `number` is accepted as an alias of `float`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PortType {
+    Any,
+    Bool,
+    Int,
+    Float,
+    String,
+    List(Box<PortType>),
+    Option(Box<PortType>),
+    Record(Vec<(String, PortType)>),
+}
+
+/// Implicit conversions allowed when connecting ports of different types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeRules {
+    widenings: Vec<(PortType, PortType)>,
+}
+
+impl TypeRules {
+    /// Rules without any implicit widening.
+    pub fn strict() -> Self {
+        Self {
+            widenings: Vec::new(),
+        }
+    }
+
+    pub fn with_widening(mut self, from: PortType, to: PortType) -> Self {
+        self.widenings.push((from, to));
+        self
+    }
+
+    pub fn allows_widening(&self, from: &PortType, to: &PortType) -> bool {
+        self.widenings
+            .iter()
+            .any(|(widen_from, widen_to)| widen_from == from && widen_to == to)
+    }
+}
+
+impl Default for TypeRules {
+    fn default() -> Self {
+        TypeRules::strict().with_widening(PortType::Int, PortType::Float)
+    }
+}
+
+impl PortType {
+    pub fn is_optional(&self) -> bool {
+        matches!(self, PortType::Option(_))
+    }
+
+    /// Returns whether a value of this type may be fed into a port of type `target`.
+    pub fn is_assignable_to(&self, target: &PortType, rules: &TypeRules) -> bool {
+        match (self, target) {
+            (PortType::Any, _) | (_, PortType::Any) => true,
+            _ if self == target || rules.allows_widening(self, target) => true,
+            (PortType::Option(source), PortType::Option(target)) => {
+                source.is_assignable_to(target, rules)
+            }
+            (source, PortType::Option(target)) => source.is_assignable_to(target, rules),
+            (PortType::List(source), PortType::List(target)) => {
+                source.is_assignable_to(target, rules)
+            }
+            (PortType::Record(source), PortType::Record(target)) => {
+                target.iter().all(|(name, target_type)| {
+                    match source.iter().find(|(source_name, _)| source_name == name) {
+                        Some((_, source_type)) => source_type.is_assignable_to(target_type, rules),
+                        None => target_type.is_optional(),
+                    }
+                })
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for PortType {
+    type Err = PortTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            input: s,
+            offset: 0,
+        };
+        let port_type = parser.parse_type()?;
+        parser.skip_whitespace();
+        if parser.offset < s.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+        Ok(port_type)
+    }
+}
+
+impl fmt::Display for PortType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortType::Any => write!(f, "any"),
+            PortType::Bool => write!(f, "bool"),
+            PortType::Int => write!(f, "int"),
+            PortType::Float => write!(f, "float"),
+            PortType::String => write!(f, "string"),
+            PortType::List(inner) => write!(f, "list<{}>", inner),
+            PortType::Option(inner) => write!(f, "option<{}>", inner),
+            PortType::Record(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(name, field_type)| format!("{}: {}", name, field_type))
+                    .collect();
+                write!(f, "record<{}>", fields.join(", "))
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> PortTypeError {
+        PortTypeError {
+            input: self.input.to_string(),
+            message: format!("{} at offset {}", message, self.offset),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.offset..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.offset += token.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: char) -> Result<(), PortTypeError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", token)))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<&'a str, PortTypeError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected identifier"));
+        }
+        self.offset += len;
+        Ok(&rest[..len])
+    }
+
+    fn parse_type(&mut self) -> Result<PortType, PortTypeError> {
+        let start = self.offset;
+        let port_type = match self.identifier()? {
+            "any" => PortType::Any,
+            "bool" | "boolean" => PortType::Bool,
+            "int" | "integer" | "s8" | "s16" | "s32" | "s64" | "u8" | "u16" | "u32" | "u64" => {
+                PortType::Int
+            }
+            "float" | "number" | "f32" | "f64" => PortType::Float,
+            "string" | "char" => PortType::String,
+            "list" => {
+                self.expect('<')?;
+                let inner = self.parse_type()?;
+                self.expect('>')?;
+                PortType::List(Box::new(inner))
+            }
+            "option" => {
+                self.expect('<')?;
+                let inner = self.parse_type()?;
+                self.expect('>')?;
+                PortType::Option(Box::new(inner))
+            }
+            "record" => {
+                self.expect('<')?;
+                let mut fields = Vec::new();
+                if !self.eat('>') {
+                    loop {
+                        let name = self.identifier()?.to_string();
+                        self.expect(':')?;
+                        fields.push((name, self.parse_type()?));
+                        if self.eat('>') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                PortType::Record(fields)
+            }
+            _ => {
+                self.offset = start;
+                self.skip_whitespace();
+                return Err(self.error("unknown type"));
+            }
+        };
+        if self.eat('?') {
+            return Ok(PortType::Option(Box::new(port_type)));
+        }
+        Ok(port_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> PortType {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_types() {
+        assert_eq!(parse("number"), PortType::Float);
+        assert_eq!(parse("list<int>"), PortType::List(Box::new(PortType::Int)));
+        assert_eq!(parse("string?"), parse("option<string>"));
+        assert_eq!(
+            parse("record<x: float, tags: list<string>>"),
+            PortType::Record(vec![
+                ("x".to_string(), PortType::Float),
+                (
+                    "tags".to_string(),
+                    PortType::List(Box::new(PortType::String))
+                ),
+            ])
+        );
+        assert!("list<".parse::<PortType>().is_err());
+        assert!("matrix".parse::<PortType>().is_err());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for s in ["any", "option<list<float>>", "record<a: int, b: bool>"] {
+            assert_eq!(parse(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_assignability() {
+        let rules = TypeRules::default();
+        assert!(parse("int").is_assignable_to(&parse("float"), &rules));
+        assert!(!parse("int").is_assignable_to(&parse("float"), &TypeRules::strict()));
+        assert!(!parse("float").is_assignable_to(&parse("int"), &rules));
+        assert!(parse("float").is_assignable_to(&parse("float?"), &rules));
+        assert!(parse("string").is_assignable_to(&parse("any"), &rules));
+        assert!(parse("list<int>").is_assignable_to(&parse("list<float>"), &rules));
+        assert!(parse("record<a: int, b: string>")
+            .is_assignable_to(&parse("record<a: float, c: bool?>"), &rules));
+        assert!(!parse("record<a: string>").is_assignable_to(&parse("record<b: string>"), &rules));
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::{load_config, ConfigError, EdgeData, GraphConfig, GraphPortData};
+
+/// Separates the id of a composite node from the ids of the nodes of its subgraph.
+pub const SUBGRAPH_SEPARATOR: char = '/';
+
+/// A composite node that was replaced by the nodes of its subgraph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubgraphData {
+    /// Type of the composite node.
+    pub r#type: String,
+    /// Path of the subgraph file, relative to the directory of the expanded config.
+    pub graph: String,
+}
+
+/// Replaces composite nodes, whose implementation refers to a `graph` file instead of a
+/// `wasm` component, with the nodes and edges of that graph.
+///
+/// The nodes of a subgraph are renamed to `<composite node>/<node>`, and edges and graph
+/// ports referring to a composite node are rewired to the node ports its subgraph declares
+/// as graph inputs and outputs. Subgraphs are expanded recursively. Relative paths in a
+/// subgraph are resolved against its own directory, and `base_dir` is the directory of
+/// `config`.
+pub fn expand_subgraphs(config: &GraphConfig, base_dir: &Path) -> Result<GraphConfig, ConfigError> {
+    expand_subgraphs_except(config, base_dir, &HashSet::new())
+}
+
+/// Like [`expand_subgraphs`], but keeps the composite nodes in `collapsed` as single nodes.
+pub fn expand_subgraphs_except(
+    config: &GraphConfig,
+    base_dir: &Path,
+    collapsed: &HashSet<String>,
+) -> Result<GraphConfig, ConfigError> {
+    let mut expander = Expander {
+        collapsed,
+        stack: Vec::new(),
+    };
+    expander.expand(config.clone(), base_dir, "")
+}
+
+struct Expander<'a> {
+    collapsed: &'a HashSet<String>,
+    /// Subgraph files being expanded, to detect subgraphs that contain themselves.
+    stack: Vec<PathBuf>,
+}
+
+impl Expander<'_> {
+    /// Expands the composite nodes of `config`, whose node ids are prefixed with `prefix`
+    /// in the outermost config.
+    fn expand(
+        &mut self,
+        mut config: GraphConfig,
+        dir: &Path,
+        prefix: &str,
+    ) -> Result<GraphConfig, ConfigError> {
+        let mut composites: Vec<_> = config
+            .nodes
+            .iter()
+            .filter(|(node_id, _)| !self.collapsed.contains(&format!("{}{}", prefix, node_id)))
+            .filter_map(|(node_id, node)| {
+                let implementation = config.node_implementations.get(&node.r#type)?;
+                Some((node_id.clone(), implementation.graph.clone()?))
+            })
+            .collect();
+        composites.sort();
+
+        for (node_id, graph) in composites {
+            let path = dir.join(&graph);
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            if self.stack.contains(&canonical) {
+                return Err(ConfigError::SubgraphError(format!(
+                    "Subgraph {:?} of node <{}> contains itself",
+                    path, node_id
+                )));
+            }
+            let subgraph = load_config(&path.to_string_lossy())?;
+            let subgraph_dir = path.parent().unwrap_or(dir);
+            self.stack.push(canonical);
+            let subgraph = self.expand(
+                subgraph,
+                subgraph_dir,
+                &format!("{}{}{}", prefix, node_id, SUBGRAPH_SEPARATOR),
+            );
+            self.stack.pop();
+
+            let relative_dir = Path::new(&graph).parent().unwrap_or(Path::new(""));
+            inline_subgraph(&mut config, &node_id, &graph, subgraph?, relative_dir)?;
+        }
+
+        // Implementations of composite nodes that are expanded everywhere are not needed.
+        let used_types: HashSet<_> = config
+            .nodes
+            .values()
+            .map(|node| node.r#type.clone())
+            .collect();
+        config.node_implementations.retain(|name, implementation| {
+            implementation.graph.is_none() || used_types.contains(name)
+        });
+        Ok(config)
+    }
+}
+
+/// Replaces the composite node `node_id` of `config` with the nodes of its expanded
+/// `subgraph`, which was loaded from `graph` in `relative_dir`.
+fn inline_subgraph(
+    config: &mut GraphConfig,
+    node_id: &str,
+    graph: &str,
+    subgraph: GraphConfig,
+    relative_dir: &Path,
+) -> Result<(), ConfigError> {
+    let composite = config.nodes.remove(node_id).unwrap();
+    let inner_id = |inner: &str| format!("{}{}{}", node_id, SUBGRAPH_SEPARATOR, inner);
+
+    // Implementations are shared with the outer config unless they differ.
+    let mut renamed_types = HashMap::new();
+    for (name, mut implementation) in subgraph.node_implementations {
+        implementation.wasm = implementation.wasm.map(|wasm| rebase(&wasm, relative_dir));
+        implementation.graph = implementation
+            .graph
+            .map(|graph| rebase(&graph, relative_dir));
+        for preopen in implementation
+            .capabilities
+            .iter_mut()
+            .flat_map(|c| &mut c.preopens)
+        {
+            preopen.host = rebase(&preopen.host, relative_dir);
+        }
+        let name_in_config = match config.node_implementations.get(&name) {
+            Some(existing) if *existing != implementation => inner_id(&name),
+            _ => name.clone(),
+        };
+        config
+            .node_implementations
+            .insert(name_in_config.clone(), implementation);
+        renamed_types.insert(name, name_in_config);
+    }
+
+    let states = match &composite.state {
+        Some(serde_json::Value::Object(states)) => states.clone(),
+        None => Default::default(),
+        Some(other) => {
+            return Err(ConfigError::SubgraphError(format!(
+                "State of composite node <{}> must be an object keyed by inner node id, got {}",
+                node_id, other
+            )))
+        }
+    };
+    if let Some(unknown) = states
+        .keys()
+        .find(|inner| !subgraph.nodes.contains_key(*inner))
+    {
+        return Err(ConfigError::SubgraphError(format!(
+            "State of composite node <{}> overrides node <{}>, which {:?} does not contain",
+            node_id, unknown, graph
+        )));
+    }
+    for (inner, mut node) in subgraph.nodes {
+        node.r#type = renamed_types
+            .get(&node.r#type)
+            .cloned()
+            .unwrap_or(node.r#type);
+        // The state of a composite node overrides the states of its inner nodes by id.
+        if let Some(state) = states.get(&inner) {
+            node.state = Some(state.clone());
+        }
+        if let Some(limits) = composite.limits {
+            node.limits = Some(node.limits.unwrap_or_default().or(limits));
+        }
+        // Preopens of inner nodes are relative to the subgraph, the inherited ones of the
+        // composite node already to the outer graph.
+        for preopen in node.capabilities.iter_mut().flat_map(|c| &mut c.preopens) {
+            preopen.host = rebase(&preopen.host, relative_dir);
+        }
+        if node.capabilities.is_none() {
+            node.capabilities = composite.capabilities.clone();
+        }
+        config.nodes.insert(inner_id(&inner), node);
+    }
+
+    for edge in &mut config.edges {
+        if edge.to == node_id {
+            let port = composite_port(&subgraph.inputs, node_id, edge.to_port.as_ref(), "input")?;
+            edge.to = inner_id(&port.node);
+            edge.to_port = port.port.clone();
+        }
+        if edge.from == node_id {
+            let port = composite_port(
+                &subgraph.outputs,
+                node_id,
+                edge.from_port.as_ref(),
+                "output",
+            )?;
+            edge.from = inner_id(&port.node);
+            edge.from_port = port.port.clone();
+        }
+    }
+    config
+        .edges
+        .extend(subgraph.edges.into_iter().map(|edge| EdgeData {
+            from: inner_id(&edge.from),
+            to: inner_id(&edge.to),
+            ..edge
+        }));
+
+    for (graph_ports, composite_ports, direction) in [
+        (&mut config.inputs, &subgraph.inputs, "input"),
+        (&mut config.outputs, &subgraph.outputs, "output"),
+    ] {
+        for graph_port in graph_ports.values_mut() {
+            if graph_port.node == node_id {
+                let port = composite_port(
+                    composite_ports,
+                    node_id,
+                    graph_port.port.as_ref(),
+                    direction,
+                )?;
+                graph_port.node = inner_id(&port.node);
+                graph_port.port = port.port.clone();
+            }
+        }
+    }
+
+    config.subgraphs.insert(
+        node_id.to_string(),
+        SubgraphData {
+            r#type: composite.r#type.clone(),
+            graph: graph.to_string(),
+        },
+    );
+    for (inner, data) in subgraph.subgraphs {
+        config.subgraphs.insert(
+            inner_id(&inner),
+            SubgraphData {
+                graph: rebase(&data.graph, relative_dir),
+                ..data
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Resolves a port of a composite node to the graph port of its subgraph it stands for.
+///
+/// A composite node with a single port on that side may be referred to without a port.
+fn composite_port<'a>(
+    ports: &'a HashMap<String, GraphPortData>,
+    node_id: &str,
+    port: Option<&String>,
+    direction: &str,
+) -> Result<&'a GraphPortData, ConfigError> {
+    let port = match port {
+        Some(port) => ports.get(port),
+        None if ports.len() == 1 => ports.values().next(),
+        None => None,
+    };
+    port.ok_or_else(|| {
+        let mut declared: Vec<_> = ports.keys().collect();
+        declared.sort();
+        ConfigError::SubgraphError(format!(
+            "Cannot resolve {} port {:?} of composite node <{}> (declared: {:?})",
+            direction, port, node_id, declared
+        ))
+    })
+}
+
+/// Makes a path relative to a subgraph's directory relative to the outer config instead.
+fn rebase(path: &str, relative_dir: &Path) -> String {
+    if Path::new(path).is_absolute() || relative_dir.as_os_str().is_empty() {
+        path.to_string()
+    } else {
+        relative_dir.join(path).to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../../assets/composite_graph")
+    }
+
+    #[test]
+    fn test_expand_subgraphs() {
+        let config =
+            load_config(&asset_dir().join("composite_graph.json").to_string_lossy()).unwrap();
+        let expanded = expand_subgraphs(&config, &asset_dir()).unwrap();
+
+        let mut node_ids: Vec<_> = expanded.nodes.keys().map(String::as_str).collect();
+        node_ids.sort();
+        assert_eq!(
+            node_ids,
+            [
+                "log",
+                "sum/adder",
+                "sum/emitter_1",
+                "sum/emitter_2",
+                "sum/log"
+            ]
+        );
+        assert_eq!(
+            expanded.nodes["sum/emitter_2"].state,
+            Some(serde_json::json!({"number": 40}))
+        );
+        assert!(expanded.edges.contains(&EdgeData {
+            from: "sum/adder".to_string(),
+            to: "log".to_string(),
+            from_port: Some("sum".to_string()),
+            to_port: None,
+        }));
+        assert!(!expanded.node_implementations.contains_key("sum-graph"));
+        // The log component of the subgraph lives in another directory.
+        assert_eq!(expanded.nodes["sum/log"].r#type, "sum/log-number");
+        assert_eq!(
+            expanded.node_implementations["add-numbers"].wasm.as_deref(),
+            Some("../sum_graph/cillio_addition_node")
+        );
+        assert_eq!(expanded.subgraphs["sum"].r#type, "sum-graph");
+        assert_eq!(
+            expanded.outputs["total"],
+            GraphPortData {
+                node: "sum/adder".to_string(),
+                port: Some("sum".to_string()),
+            }
+        );
+
+        let collapsed = HashSet::from(["sum".to_string()]);
+        let expanded = expand_subgraphs_except(&config, &asset_dir(), &collapsed).unwrap();
+        assert_eq!(expanded, config);
+    }
+
+    fn composite_config(composite: serde_json::Value) -> GraphConfig {
+        serde_json::from_value(serde_json::json!({
+            "node_implementations": {},
+            "nodes": {"c": composite},
+            "edges": []
+        }))
+        .unwrap()
+    }
+
+    fn subgraph() -> GraphConfig {
+        serde_json::from_value(serde_json::json!({
+            "node_implementations": {"source": {"output": {"value": "int"}, "wasm": "source"}},
+            "nodes": {
+                "own": {
+                    "type": "source",
+                    "capabilities": {"preopens": [{"host": "data", "guest": "/data"}]}
+                },
+                "inherited": {"type": "source"}
+            },
+            "edges": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_inline_subgraph_capabilities() {
+        let mut config = composite_config(serde_json::json!({
+            "type": "sub",
+            "capabilities": {"preopens": [{"host": "outer", "guest": "/outer"}]}
+        }));
+        inline_subgraph(&mut config, "c", "sub.json", subgraph(), Path::new("sub")).unwrap();
+        let host = |node: &str| {
+            config.nodes[node].capabilities.as_ref().unwrap().preopens[0]
+                .host
+                .clone()
+        };
+        assert_eq!(
+            host("c/own"),
+            Path::new("sub").join("data").to_string_lossy()
+        );
+        assert_eq!(host("c/inherited"), "outer");
+    }
+
+    #[test]
+    fn test_inline_subgraph_rejects_invalid_states() {
+        for state in [
+            serde_json::json!(1),
+            serde_json::json!({"typo": {"seed": 1}}),
+        ] {
+            let mut config = composite_config(serde_json::json!({"type": "sub", "state": state}));
+            let result =
+                inline_subgraph(&mut config, "c", "sub.json", subgraph(), Path::new("sub"));
+            assert!(
+                matches!(result, Err(ConfigError::SubgraphError(_))),
+                "{:?}",
+                result
+            );
+        }
+    }
+}
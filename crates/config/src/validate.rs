@@ -0,0 +1,1098 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    expand_subgraphs, fill_implementations, ConfigError, ConfigFormat, EdgeData, GraphConfig,
+    GraphPortData, NodeDescription, NodeImplementationData, PortType,
+};
+
+/// A 1-based line and column in a config source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    UnknownNode,
+    UnknownNodeType,
+    UnknownPort,
+    AmbiguousPort,
+    UnconnectedInput,
+    MultipleEdgesToInput,
+    UnknownStateKey,
+    InvalidPortType,
+    InvalidImplementation,
+}
+
+/// A single problem found in a [`GraphConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    /// Path of the offending entry, e.g. `nodes.adder.type` or `edges[1].to_port`.
+    pub path: String,
+    pub location: Option<Location>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(f, "{}: {} ({})", location, self.message, self.path),
+            None => write!(f, "{} ({})", self.message, self.path),
+        }
+    }
+}
+
+/// Locates config entries in the source text a config was parsed from.
+///
+/// The lookup is format agnostic: keys are matched whether they are quoted JSON keys,
+/// bare YAML/JSON5 keys or TOML keys and table headers. Brackets outside strings and
+/// comments, TOML table headers and YAML indentation tell apart keys at different depths.
+struct SourceMap<'a> {
+    source: &'a str,
+    /// Offsets of the brackets outside strings, comments and TOML table headers.
+    brackets: Vec<(usize, u8)>,
+    /// Offsets of the TOML table headers, e.g. `[nodes.adder]` or `[[edges]]`.
+    table_headers: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    fn new(source: &'a str) -> Self {
+        let bytes = source.as_bytes();
+        let mut brackets = Vec::new();
+        let mut table_headers = Vec::new();
+        let mut depth = 0usize;
+        let mut index = 0;
+        while index < bytes.len() {
+            let previous = index.checked_sub(1).map(|index| bytes[index]);
+            let separated = previous.is_none_or(|byte| {
+                byte.is_ascii_whitespace() || matches!(byte, b'{' | b'[' | b',')
+            });
+            match bytes[index] {
+                quote @ (b'"' | b'\'') if starts_value(bytes, index) => {
+                    index += 1;
+                    while index < bytes.len() && !matches!(bytes[index], b'\n') {
+                        if bytes[index] == quote {
+                            break;
+                        }
+                        if quote == b'"' && bytes[index] == b'\\' {
+                            index += 1;
+                        }
+                        index += 1;
+                    }
+                }
+                b'#' if separated => index = line_end(bytes, index),
+                b'/' if separated && bytes.get(index + 1) == Some(&b'/') => {
+                    index = line_end(bytes, index)
+                }
+                b'/' if separated && bytes.get(index + 1) == Some(&b'*') => {
+                    index = source[index + 2..]
+                        .find("*/")
+                        .map_or(bytes.len(), |end| index + end + 3);
+                }
+                b'[' if depth == 0 && previous.is_none_or(|byte| byte == b'\n') => {
+                    table_headers.push(index);
+                    index = line_end(bytes, index);
+                }
+                byte @ (b'{' | b'[') => {
+                    depth += 1;
+                    brackets.push((index, byte));
+                }
+                byte @ (b'}' | b']') => {
+                    depth = depth.saturating_sub(1);
+                    brackets.push((index, byte));
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        Self {
+            source,
+            brackets,
+            table_headers,
+        }
+    }
+
+    /// Finds the byte offset of the next occurrence of `key` used as a key at or after `from`.
+    fn find_key(&self, key: &str, from: usize) -> Option<usize> {
+        let bytes = self.source.as_bytes();
+        let mut offset = from;
+        while let Some(found) = self.source.get(offset..)?.find(key) {
+            let start = offset + found;
+            let end = start + key.len();
+            offset = start + 1;
+
+            let before = start.checked_sub(1).map(|index| bytes[index]);
+            let quote = match before {
+                Some(quote @ (b'"' | b'\'')) => Some(quote),
+                Some(byte) if is_key_char(byte) => continue,
+                _ => None,
+            };
+            let mut after = end;
+            if let Some(quote) = quote {
+                if bytes.get(after) != Some(&quote) {
+                    continue;
+                }
+                after += 1;
+            } else if bytes.get(after).copied().is_some_and(is_key_char) {
+                continue;
+            }
+            while bytes
+                .get(after)
+                .is_some_and(|byte| *byte == b' ' || *byte == b'\t')
+            {
+                after += 1;
+            }
+            if matches!(bytes.get(after), Some(b':' | b'=' | b'.' | b']')) {
+                return Some(quote.map_or(start, |_| start - 1));
+            }
+        }
+        None
+    }
+
+    /// Finds the byte offset of the least nested occurrence of `key` used as a key, the
+    /// first one if several are nested alike.
+    fn find_top_key(&self, key: &str) -> Option<usize> {
+        let mut found: Option<(usize, (usize, usize))> = None;
+        let mut offset = 0;
+        while let Some(start) = self.find_key(key, offset) {
+            offset = start + key.len() + 1;
+            let nesting = self.nesting(start);
+            if found.is_none_or(|(_, least)| nesting < least) {
+                found = Some((start, nesting));
+            }
+        }
+        found.map(|(start, _)| start)
+    }
+
+    /// Returns the bracket depth of `offset`, counting TOML tables as one level, and the
+    /// indentation of its line.
+    fn nesting(&self, offset: usize) -> (usize, usize) {
+        let line_start = self.line_start(offset);
+        let in_table = !self.table_headers.contains(&line_start)
+            && self.table_headers.iter().any(|header| *header < line_start);
+        let depth = self.brackets[..self.brackets_before(offset)].iter().fold(
+            0usize,
+            |depth, (_, byte)| match byte {
+                b'{' | b'[' => depth + 1,
+                _ => depth.saturating_sub(1),
+            },
+        );
+        (
+            depth + usize::from(in_table),
+            indentation(&self.source[line_start..]),
+        )
+    }
+
+    /// Returns the byte range of the sequence item holding the key at `offset`: its
+    /// `{ ... }` mapping, its TOML `[[...]]` table or its YAML `- ...` block.
+    fn item_bounds(&self, offset: usize) -> (usize, usize) {
+        let split = self.brackets_before(offset);
+        let mut open = Vec::new();
+        for &(position, byte) in &self.brackets[..split] {
+            match byte {
+                b'{' | b'[' => open.push((position, byte)),
+                _ => {
+                    open.pop();
+                }
+            }
+        }
+        if let Some(&(start, b'{')) = open.last() {
+            let mut depth = 0usize;
+            for &(position, byte) in &self.brackets[split..] {
+                match byte {
+                    b'{' | b'[' => depth += 1,
+                    _ if depth == 0 => return (start, position + 1),
+                    _ => depth -= 1,
+                }
+            }
+            return (start, self.source.len());
+        }
+
+        let line_start = self.line_start(offset);
+        if let Some(&header) = self
+            .table_headers
+            .iter()
+            .rfind(|header| **header <= line_start)
+        {
+            let end = self
+                .table_headers
+                .iter()
+                .find(|next| **next > header)
+                .map_or(self.source.len(), |next| *next);
+            return (header, end);
+        }
+
+        // A YAML block item starts at the closest `-` left of the key on its line or above.
+        let column = offset - line_start;
+        let mut start = line_start;
+        let marker = loop {
+            let indent = indentation(&self.source[start..]);
+            if indent < column && self.source[start + indent..].starts_with('-') {
+                break indent;
+            }
+            if start == 0 || (indent < column && start != line_start) {
+                return (line_start, self.source.len());
+            }
+            start = self.line_start(start - 1);
+        };
+        let mut end = line_end(self.source.as_bytes(), start) + 1;
+        while end < self.source.len() {
+            let line = &self.source[end..];
+            let content = line.trim_start_matches([' ', '\t']);
+            let blank = content.starts_with(['\n', '\r', '#']);
+            if !blank && line.len() - content.len() <= marker {
+                break;
+            }
+            end = line_end(self.source.as_bytes(), end) + 1;
+        }
+        (start + marker, end.min(self.source.len()))
+    }
+
+    /// Finds the byte offset of the last key of `path`, searching the first key at the top
+    /// level and each other key after the previous.
+    fn find_path(&self, path: &[&str]) -> Option<usize> {
+        let (first, rest) = path.split_first()?;
+        let mut found = self.find_top_key(first)?;
+        let mut offset = found + first.len() + 1;
+        for key in rest {
+            found = self.find_key(key, offset)?;
+            offset = found + key.len() + 1;
+        }
+        Some(found)
+    }
+
+    fn locate(&self, path: &[&str]) -> Option<Location> {
+        self.find_path(path).map(|offset| self.location(offset))
+    }
+
+    /// Locates `field` of the edge at `index`, falling back to the first key of the edge.
+    fn locate_edge(&self, index: usize, field: Option<&str>) -> Option<Location> {
+        let mut offset = self.find_top_key("edges")? + "edges".len();
+        let mut edge = None;
+        for _ in 0..=index {
+            let first = [self.find_key("from", offset), self.find_key("to", offset)]
+                .into_iter()
+                .flatten()
+                .min()?;
+            let (start, end) = self.item_bounds(first);
+            offset = end.max(first + 1);
+            edge = Some((first, start, end));
+        }
+        let (first, start, end) = edge?;
+        let offset = field
+            .and_then(|field| self.find_key(field, start))
+            .filter(|offset| *offset < end)
+            .unwrap_or(first);
+        Some(self.location(offset))
+    }
+
+    /// Returns the number of brackets before `offset`.
+    fn brackets_before(&self, offset: usize) -> usize {
+        self.brackets
+            .partition_point(|(position, _)| *position < offset)
+    }
+
+    fn line_start(&self, offset: usize) -> usize {
+        self.source[..offset]
+            .rfind('\n')
+            .map_or(0, |index| index + 1)
+    }
+
+    fn location(&self, offset: usize) -> Location {
+        let before = &self.source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let column = before[line_start..].chars().count() + 1;
+        Location { line, column }
+    }
+}
+
+/// Returns the offset of the newline ending the line holding `offset`, or the length of
+/// `bytes` on the last line.
+fn line_end(bytes: &[u8], offset: usize) -> usize {
+    bytes[offset..]
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .map_or(bytes.len(), |end| offset + end)
+}
+
+/// Returns the number of spaces and tabs a line starts with.
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+/// Whether the quote at `index` opens a string rather than being part of a bare YAML
+/// scalar, e.g. the apostrophe in `label: it's`.
+fn starts_value(bytes: &[u8], index: usize) -> bool {
+    let before = bytes[..index]
+        .iter()
+        .rev()
+        .find(|byte| !matches!(byte, b' ' | b'\t'));
+    before.is_none_or(|byte| matches!(byte, b'\n' | b':' | b'{' | b'[' | b',' | b'=' | b'-'))
+}
+
+/// Resolves the `direction` port `port` of a node, defaulting to its only declared port.
+///
+/// `what` names the entry referring to the port in the message of an ambiguous port.
+fn resolve_port(
+    node_id: &str,
+    port: Option<&String>,
+    declared: Option<&HashMap<String, String>>,
+    direction: &str,
+    what: &str,
+) -> Result<String, (DiagnosticKind, String)> {
+    let mut declared_ports: Vec<_> = declared
+        .map(|ports| ports.keys().collect())
+        .unwrap_or_default();
+    declared_ports.sort();
+    match port {
+        Some(port) if declared_ports.contains(&port) => Ok(port.clone()),
+        Some(port) => Err((
+            DiagnosticKind::UnknownPort,
+            format!(
+                "Node <{}> has no {} port <{}> (declared: {:?})",
+                node_id, direction, port, declared_ports
+            ),
+        )),
+        None if declared_ports.len() == 1 => Ok(declared_ports[0].clone()),
+        None if declared_ports.is_empty() => Err((
+            DiagnosticKind::AmbiguousPort,
+            format!("Node <{}> has no {} ports", node_id, direction),
+        )),
+        None => Err((
+            DiagnosticKind::AmbiguousPort,
+            format!(
+                "{} must name an {} port of <{}> (declared: {:?})",
+                what, direction, node_id, declared_ports
+            ),
+        )),
+    }
+}
+
+fn is_key_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-'
+}
+
+struct Validator<'a> {
+    config: &'a GraphConfig,
+    source_map: Option<SourceMap<'a>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    fn report(
+        &mut self,
+        kind: DiagnosticKind,
+        message: String,
+        path: String,
+        location: Option<Location>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            kind,
+            message,
+            path,
+            location,
+        });
+    }
+
+    fn locate(&self, path: &[&str]) -> Option<Location> {
+        self.source_map.as_ref()?.locate(path)
+    }
+
+    fn locate_edge(&self, index: usize, field: Option<&str>) -> Option<Location> {
+        self.source_map.as_ref()?.locate_edge(index, field)
+    }
+
+    /// Returns the implementation of a node unless it is a composite node, whose ports are
+    /// only known once its subgraph is expanded.
+    fn implementation(&self, node_id: &str) -> Option<&'a NodeImplementationData> {
+        let node = self.config.nodes.get(node_id)?;
+        self.config
+            .node_implementations
+            .get(&node.r#type)
+            .filter(|implementation| implementation.graph.is_none())
+    }
+
+    fn validate_implementations(&mut self) {
+        let mut implementations: Vec<_> = self.config.node_implementations.iter().collect();
+        implementations.sort_by_key(|(name, _)| *name);
+        for (name, implementation) in implementations {
+            let sources = [
+                ("wasm", &implementation.wasm),
+                ("graph", &implementation.graph),
+                ("native", &implementation.native),
+            ];
+            let set: Vec<_> = sources
+                .iter()
+                .filter(|(_, source)| source.is_some())
+                .map(|(key, _)| format!("<{}>", key))
+                .collect();
+            let message = match set.len() {
+                0 => "sets none of <wasm>, <graph> and <native>".to_string(),
+                1 => continue,
+                _ => format!("sets more than one of {}", set.join(", ")),
+            };
+            let location = self.locate(&["node_implementations", name]);
+            self.report(
+                DiagnosticKind::InvalidImplementation,
+                format!("Node implementation <{}> {}", name, message),
+                format!("node_implementations.{}", name),
+                location,
+            );
+        }
+    }
+
+    fn validate_port_types(&mut self) {
+        let mut implementations: Vec<_> = self.config.node_implementations.iter().collect();
+        implementations.sort_by_key(|(name, _)| *name);
+        for (name, implementation) in implementations {
+            for (section, ports) in [
+                ("input", &implementation.input),
+                ("output", &implementation.output),
+                ("state", &implementation.state),
+            ] {
+                let mut ports: Vec<_> = ports.iter().flatten().collect();
+                ports.sort();
+                for (port, r#type) in ports {
+                    let Err(err) = r#type.parse::<PortType>() else {
+                        continue;
+                    };
+                    let location = self.locate(&["node_implementations", name, section, port]);
+                    self.report(
+                        DiagnosticKind::InvalidPortType,
+                        err.to_string(),
+                        format!("node_implementations.{}.{}.{}", name, section, port),
+                        location,
+                    );
+                }
+            }
+        }
+    }
+
+    fn validate_nodes(&mut self) {
+        let mut node_ids: Vec<_> = self.config.nodes.keys().collect();
+        node_ids.sort();
+        for node_id in node_ids {
+            let node = &self.config.nodes[node_id];
+            let Some(implementation) = self.config.node_implementations.get(&node.r#type) else {
+                let location = self.locate(&["nodes", node_id, "type"]);
+                self.report(
+                    DiagnosticKind::UnknownNodeType,
+                    format!(
+                        "Node <{}> has type <{}> without a node implementation",
+                        node_id, node.r#type
+                    ),
+                    format!("nodes.{}.type", node_id),
+                    location,
+                );
+                continue;
+            };
+            if implementation.graph.is_some() {
+                continue;
+            }
+
+            let Some(serde_json::Value::Object(state)) = &node.state else {
+                continue;
+            };
+            let declared_state = implementation.state.as_ref();
+            let mut keys: Vec<_> = state.keys().collect();
+            keys.sort();
+            for key in keys {
+                if declared_state.is_some_and(|declared| declared.contains_key(key)) {
+                    continue;
+                }
+                let location = self.locate(&["nodes", node_id, "state", key]);
+                self.report(
+                    DiagnosticKind::UnknownStateKey,
+                    format!(
+                        "State key <{}> of node <{}> is not declared by <{}>",
+                        key, node_id, node.r#type
+                    ),
+                    format!("nodes.{}.state.{}", node_id, key),
+                    location,
+                );
+            }
+        }
+    }
+
+    /// Resolves the port an edge end refers to, reporting unknown or ambiguous ports.
+    fn resolve_port(
+        &mut self,
+        index: usize,
+        node_id: &str,
+        port: Option<&String>,
+        declared: Option<&HashMap<String, String>>,
+        field: &str,
+    ) -> Option<String> {
+        let direction = if field == "to_port" {
+            "input"
+        } else {
+            "output"
+        };
+        match resolve_port(node_id, port, declared, direction, "Edge") {
+            Ok(port) => Some(port),
+            Err((kind, message)) => {
+                // Unknown ports point at the port, missing ones at the edge.
+                let location = self.locate_edge(
+                    index,
+                    (kind == DiagnosticKind::UnknownPort).then_some(field),
+                );
+                self.report(
+                    kind,
+                    message,
+                    format!("edges[{}].{}", index, field),
+                    location,
+                );
+                None
+            }
+        }
+    }
+
+    /// Checks the graph inputs and outputs and returns the node input ports fed by graph
+    /// inputs.
+    fn validate_graph_ports(&mut self) -> HashSet<(String, String)> {
+        let mut fed_inputs = HashSet::new();
+        for (section, ports, direction) in [
+            ("inputs", &self.config.inputs, "input"),
+            ("outputs", &self.config.outputs, "output"),
+        ] {
+            let mut ports: Vec<_> = ports.iter().collect();
+            ports.sort_by_key(|(name, _)| *name);
+            for (name, GraphPortData { node, port }) in ports {
+                if !self.config.nodes.contains_key(node) {
+                    let location = self.locate(&[section, name, "node"]);
+                    self.report(
+                        DiagnosticKind::UnknownNode,
+                        format!(
+                            "Graph {} <{}> references unknown node <{}>",
+                            direction, name, node
+                        ),
+                        format!("{}.{}.node", section, name),
+                        location,
+                    );
+                    continue;
+                }
+                let Some(implementation) = self.implementation(node) else {
+                    continue;
+                };
+                let declared = if direction == "input" {
+                    implementation.input.as_ref()
+                } else {
+                    implementation.output.as_ref()
+                };
+                let what = format!("Graph {} <{}>", direction, name);
+                match resolve_port(node, port.as_ref(), declared, direction, &what) {
+                    Ok(port) if direction == "input" => {
+                        fed_inputs.insert((node.clone(), port));
+                    }
+                    Ok(_) => {}
+                    Err((kind, message)) => {
+                        let location = self
+                            .locate(&[section, name, "port"])
+                            .or_else(|| self.locate(&[section, name]));
+                        self.report(
+                            kind,
+                            message,
+                            format!("{}.{}.port", section, name),
+                            location,
+                        );
+                    }
+                }
+            }
+        }
+        fed_inputs
+    }
+
+    fn validate_edges(&mut self, fed_inputs: &HashSet<(String, String)>) {
+        let mut connected_inputs: HashMap<(String, String), Vec<usize>> = HashMap::new();
+
+        for (index, edge) in self.config.edges.iter().enumerate() {
+            let EdgeData {
+                from,
+                to,
+                from_port,
+                to_port,
+            } = edge;
+
+            let mut endpoints_known = true;
+            for (node_id, field) in [(from, "from"), (to, "to")] {
+                if !self.config.nodes.contains_key(node_id) {
+                    endpoints_known = false;
+                    let location = self.locate_edge(index, Some(field));
+                    self.report(
+                        DiagnosticKind::UnknownNode,
+                        format!("Edge references unknown node <{}>", node_id),
+                        format!("edges[{}].{}", index, field),
+                        location,
+                    );
+                }
+            }
+            if !endpoints_known {
+                continue;
+            }
+
+            if let Some(implementation) = self.implementation(from) {
+                self.resolve_port(
+                    index,
+                    from,
+                    from_port.as_ref(),
+                    implementation.output.as_ref(),
+                    "from_port",
+                );
+            }
+            if let Some(implementation) = self.implementation(to) {
+                if let Some(port) = self.resolve_port(
+                    index,
+                    to,
+                    to_port.as_ref(),
+                    implementation.input.as_ref(),
+                    "to_port",
+                ) {
+                    connected_inputs
+                        .entry((to.clone(), port))
+                        .or_default()
+                        .push(index);
+                }
+            }
+        }
+
+        let mut inputs: Vec<_> = connected_inputs.iter().collect();
+        inputs.sort();
+        for ((node_id, port), edges) in inputs {
+            if edges.len() < 2 {
+                continue;
+            }
+            for &index in &edges[1..] {
+                let location = self.locate_edge(index, Some("to_port"));
+                self.report(
+                    DiagnosticKind::MultipleEdgesToInput,
+                    format!(
+                        "Input port <{}> of node <{}> is fed by {} edges",
+                        port,
+                        node_id,
+                        edges.len()
+                    ),
+                    format!("edges[{}].to_port", index),
+                    location,
+                );
+            }
+        }
+
+        let mut node_ids: Vec<_> = self.config.nodes.keys().collect();
+        node_ids.sort();
+        for node_id in node_ids {
+            let Some(inputs) = self.implementation(node_id).and_then(|i| i.input.as_ref()) else {
+                continue;
+            };
+            let mut ports: Vec<_> = inputs.iter().collect();
+            ports.sort();
+            for (port, r#type) in ports {
+                let is_optional = r#type
+                    .parse::<PortType>()
+                    .map_or(true, |port_type| port_type.is_optional());
+                let input = (node_id.clone(), port.clone());
+                if is_optional
+                    || connected_inputs.contains_key(&input)
+                    || fed_inputs.contains(&input)
+                {
+                    continue;
+                }
+                let location = self.locate(&["nodes", node_id]);
+                self.report(
+                    DiagnosticKind::UnconnectedInput,
+                    format!(
+                        "Required input port <{}> of node <{}> is not connected",
+                        port, node_id
+                    ),
+                    format!("nodes.{}", node_id),
+                    location,
+                );
+            }
+        }
+    }
+}
+
+/// Checks a graph config for semantic problems and reports all of them at once.
+///
+/// When the `source` the config was parsed from is given, diagnostics point at the
+/// line and column of the offending entry.
+pub fn validate(config: &GraphConfig, source: Option<&str>) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        config,
+        source_map: source.map(SourceMap::new),
+        diagnostics: Vec::new(),
+    };
+    validator.validate_implementations();
+    validator.validate_port_types();
+    validator.validate_nodes();
+    let fed_inputs = validator.validate_graph_ports();
+    validator.validate_edges(&fed_inputs);
+    validator.diagnostics
+}
+
+/// Loads the config at `path`, expands its subgraphs and validates it against its source.
+///
+/// Diagnostics about nodes of subgraphs carry no location.
+pub fn validate_file(path: &str) -> Result<Vec<Diagnostic>, ConfigError> {
+    validate_file_with(path, &HashMap::new())
+}
+
+/// Like [`validate_file`], after declaring the ports the node implementations leave to the
+/// given descriptions of their node types, see [`fill_implementations`].
+pub fn validate_file_with(
+    path: &str,
+    descriptions: &HashMap<String, NodeDescription>,
+) -> Result<Vec<Diagnostic>, ConfigError> {
+    let source = fs::read_to_string(path)?;
+    let format = ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::detect(&source));
+    let mut config = format.parse(&source)?;
+    fill_implementations(&mut config, descriptions);
+    let mut diagnostics = validate(&config, Some(&source));
+    if diagnostics.is_empty() {
+        let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        let mut expanded = expand_subgraphs(&config, base_dir)?;
+        fill_implementations(&mut expanded, descriptions);
+        diagnostics = validate(&expanded, Some(&source));
+    }
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BROKEN_GRAPH: &str = r#"{
+  "node_implementations": {
+    "emit-number": {
+      "state": { "number": "number" },
+      "output": { "number": "number" },
+      "wasm": "cillio_emit_number_node"
+    },
+    "add-numbers": {
+      "input": { "a": "number", "b": "number", "c": "number?", "e": "matrix" },
+      "output": { "sum": "number" },
+      "wasm": "cillio_addition_node"
+    }
+  },
+  "nodes": {
+    "emitter": { "type": "emit-number", "state": { "number": 1, "count": 2 } },
+    "adder": { "type": "add-numbers" },
+    "log": { "type": "log-number" }
+  },
+  "edges": [
+    { "from": "emitter", "to": "adder", "to_port": "a" },
+    { "from": "emitter", "to": "adder", "to_port": "a" },
+    { "from": "emitter", "to": "adder", "to_port": "d" },
+    { "from": "adder", "to": "missing" }
+  ]
+}"#;
+
+    fn find(diagnostics: &[Diagnostic], kind: DiagnosticKind) -> Vec<&Diagnostic> {
+        diagnostics.iter().filter(|d| d.kind == kind).collect()
+    }
+
+    #[test]
+    fn test_reports_all_problems() {
+        let config: GraphConfig = serde_json::from_str(BROKEN_GRAPH).unwrap();
+        let diagnostics = validate(&config, Some(BROKEN_GRAPH));
+
+        let unknown_type = find(&diagnostics, DiagnosticKind::UnknownNodeType);
+        assert_eq!(unknown_type.len(), 1);
+        assert_eq!(unknown_type[0].path, "nodes.log.type");
+        assert_eq!(
+            unknown_type[0].location,
+            Some(Location {
+                line: 17,
+                column: 14
+            })
+        );
+
+        let unknown_state = find(&diagnostics, DiagnosticKind::UnknownStateKey);
+        assert_eq!(unknown_state.len(), 1);
+        assert_eq!(unknown_state[0].path, "nodes.emitter.state.count");
+        assert_eq!(
+            unknown_state[0].location,
+            Some(Location {
+                line: 15,
+                column: 65
+            })
+        );
+
+        let unknown_port = find(&diagnostics, DiagnosticKind::UnknownPort);
+        assert_eq!(unknown_port.len(), 1);
+        assert_eq!(unknown_port[0].path, "edges[2].to_port");
+        assert_eq!(
+            unknown_port[0].location,
+            Some(Location {
+                line: 22,
+                column: 41
+            })
+        );
+
+        let multiple = find(&diagnostics, DiagnosticKind::MultipleEdgesToInput);
+        assert_eq!(multiple.len(), 1);
+        assert_eq!(multiple[0].path, "edges[1].to_port");
+
+        let unconnected = find(&diagnostics, DiagnosticKind::UnconnectedInput);
+        assert_eq!(unconnected.len(), 1);
+        assert!(unconnected[0].message.contains("<b>"));
+
+        let invalid_type = find(&diagnostics, DiagnosticKind::InvalidPortType);
+        assert_eq!(invalid_type.len(), 1);
+        assert_eq!(
+            invalid_type[0].path,
+            "node_implementations.add-numbers.input.e"
+        );
+        assert_eq!(
+            invalid_type[0].location,
+            Some(Location {
+                line: 9,
+                column: 64
+            })
+        );
+
+        let unknown_node = find(&diagnostics, DiagnosticKind::UnknownNode);
+        assert_eq!(unknown_node.len(), 1);
+        assert_eq!(
+            unknown_node[0].location,
+            Some(Location {
+                line: 23,
+                column: 24
+            })
+        );
+    }
+
+    #[test]
+    fn test_asset_graphs_are_valid() {
+        for file in [
+            "sum_graph/sum_graph.json",
+            "sum_graph/sum_graph.yaml",
+            "composite_graph/composite_graph.json",
+        ] {
+            let path = format!("{}/../../assets/{}", env!("CARGO_MANIFEST_DIR"), file);
+            assert_eq!(validate_file(&path).unwrap(), vec![]);
+        }
+    }
+
+    #[test]
+    fn test_graph_ports() {
+        let source = r#"{
+  "node_implementations": {
+    "add": {
+      "input": { "a": "number", "b": "number" },
+      "output": { "sum": "number" },
+      "wasm": "add"
+    }
+  },
+  "nodes": { "adder": { "type": "add" } },
+  "edges": [],
+  "inputs": {
+    "x": { "node": "adder", "port": "a" },
+    "y": { "node": "adder" },
+    "z": { "node": "missing" }
+  },
+  "outputs": { "sum": { "node": "adder" } }
+}"#;
+        let config: GraphConfig = serde_json::from_str(source).unwrap();
+        let diagnostics = validate(&config, Some(source));
+
+        let kinds: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.kind, d.path.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (DiagnosticKind::AmbiguousPort, "inputs.y.port"),
+                (DiagnosticKind::UnknownNode, "inputs.z.node"),
+                // Input `a` is fed by graph input `x`.
+                (DiagnosticKind::UnconnectedInput, "nodes.adder"),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].location,
+            Some(Location {
+                line: 13,
+                column: 5
+            })
+        );
+        assert!(diagnostics[2].message.contains("<b>"));
+    }
+
+    #[test]
+    fn test_validates_described_ports() {
+        let source = r#"{
+  "node_implementations": {
+    "emit": { "wasm": "emit" },
+    "log": { "input": { "value": "float" }, "output": {}, "wasm": "log" }
+  },
+  "nodes": { "emitter": { "type": "emit" }, "logger": { "type": "log" } },
+  "edges": [{ "from": "emitter", "to": "logger" }]
+}"#;
+        let mut config: GraphConfig = serde_json::from_str(source).unwrap();
+        let diagnostics = validate(&config, Some(source));
+        assert_eq!(
+            find(&diagnostics, DiagnosticKind::AmbiguousPort)[0].path,
+            "edges[0].from_port"
+        );
+
+        let description: NodeDescription = serde_json::from_value(serde_json::json!({
+            "name": "emit",
+            "version": "1.0.0",
+            "outputs": [{ "name": "value", "type": "float" }]
+        }))
+        .unwrap();
+        fill_implementations(
+            &mut config,
+            &HashMap::from([("emit".to_string(), description)]),
+        );
+        assert_eq!(validate(&config, Some(source)), vec![]);
+    }
+
+    #[test]
+    fn test_locates_yaml_keys() {
+        let source = "nodes:\n  adder:\n    type: \"add\"\n";
+        let source_map = SourceMap::new(source);
+        assert_eq!(
+            source_map.locate(&["nodes", "adder", "type"]),
+            Some(Location { line: 3, column: 5 })
+        );
+    }
+
+    #[test]
+    fn test_locates_json_edges() {
+        // Node state holding `edges` and `from` keys, and edges with `to` before `from`.
+        let source = r#"{
+  "nodes": {
+    "a": { "type": "t", "state": { "edges": [{ "from": "x", "to": "y" }] } }
+  },
+  "edges": [
+    { "to": "b", "from": "a" },
+    { "to_port": "p", "to": "missing", "from": "a" }
+  ]
+}"#;
+        let source_map = SourceMap::new(source);
+        assert_eq!(
+            source_map.locate_edge(0, Some("from")),
+            Some(Location {
+                line: 6,
+                column: 18
+            })
+        );
+        assert_eq!(
+            source_map.locate_edge(1, Some("to")),
+            Some(Location {
+                line: 7,
+                column: 23
+            })
+        );
+        assert_eq!(
+            source_map.locate_edge(1, Some("to_port")),
+            Some(Location { line: 7, column: 7 })
+        );
+        assert_eq!(
+            source_map.locate_edge(0, Some("to_port")),
+            Some(Location { line: 6, column: 7 })
+        );
+    }
+
+    #[test]
+    fn test_locates_yaml_edges() {
+        let source = "\
+nodes:
+  a:
+    type: t
+    state:
+      edges: 1
+      from: it's
+edges:
+  - to: b
+    from: a
+  - from: a
+    to: missing
+    to_port: p
+";
+        let source_map = SourceMap::new(source);
+        assert_eq!(
+            source_map.locate_edge(0, Some("from")),
+            Some(Location { line: 9, column: 5 })
+        );
+        assert_eq!(
+            source_map.locate_edge(1, Some("to")),
+            Some(Location {
+                line: 11,
+                column: 5
+            })
+        );
+        assert_eq!(
+            source_map.locate_edge(0, Some("to_port")),
+            Some(Location { line: 8, column: 5 })
+        );
+        assert_eq!(
+            source_map.locate_edge(1, Some("to_port")),
+            Some(Location {
+                line: 12,
+                column: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_locates_toml_edges() {
+        let source = r#"
+[nodes.a]
+type = "t"
+
+[nodes.a.state]
+edges = 1
+
+[[edges]]
+to = "b"
+from = "a"
+
+[[edges]]
+from = "a"
+to = "missing"
+"#;
+        let source_map = SourceMap::new(source);
+        assert_eq!(
+            source_map.locate_edge(0, Some("from")),
+            Some(Location {
+                line: 10,
+                column: 1
+            })
+        );
+        assert_eq!(
+            source_map.locate_edge(1, Some("to")),
+            Some(Location {
+                line: 14,
+                column: 1
+            })
+        );
+        assert_eq!(
+            source_map.locate(&["nodes", "a", "type"]),
+            Some(Location { line: 3, column: 1 })
+        );
+
+        let source = "edges = [{ to = \"b\", from = \"a\" }, { from = \"a\", to = \"c\" }]\n";
+        let source_map = SourceMap::new(source);
+        assert_eq!(
+            source_map.locate_edge(1, Some("to")),
+            Some(Location {
+                line: 1,
+                column: 50
+            })
+        );
+    }
+}
@@ -1,9 +1,14 @@
-use cillio_config::GraphConfig;
+use cillio_config::{
+    GraphConfig, GraphPortData, NodeImplementationData, PortType, SubgraphData, TypeRules,
+    SUBGRAPH_SEPARATOR,
+};
 use petgraph::{
-    dot::{Config, Dot},
-    graph::{DiGraph, NodeIndex},
+    algo::tarjan_scc,
+    graph::{DiGraph, EdgeIndex, NodeIndex},
+    visit::EdgeRef,
 };
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+use std::fmt;
 use thiserror::Error;
 
 use crate::{Node, Edge};
@@ -15,12 +20,40 @@ pub enum GraphError {
 
     #[error("Graph structure error: {0}")]
     GraphStructureError(String),
+
+    #[error("Type mismatch: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    TypeMismatchError(Vec<TypeMismatch>),
+}
+
+/// An edge connecting ports whose declared types are not compatible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeMismatch {
+    pub from_node: String,
+    pub from_port: String,
+    pub from_type: PortType,
+    pub to_node: String,
+    pub to_port: String,
+    pub to_type: PortType,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} ({}) cannot feed {}.{} ({})",
+            self.from_node, self.from_port, self.from_type, self.to_node, self.to_port, self.to_type
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Graph {
     graph: DiGraph<Node, Edge>,
     node_map: HashMap<String, NodeIndex>,
+    node_implementations: HashMap<String, NodeImplementationData>,
+    inputs: HashMap<String, GraphPortData>,
+    outputs: HashMap<String, GraphPortData>,
+    subgraphs: HashMap<String, SubgraphData>,
 }
 
 impl Graph {
@@ -47,33 +80,235 @@ impl Graph {
             graph.add_edge(*from_index, *to_index, edge_data);
         }
 
-        Ok(Self { graph, node_map })
+        for graph_port in config.inputs.values().chain(config.outputs.values()) {
+            if !node_map.contains_key(&graph_port.node) {
+                return Err(GraphError::NodeNotFoundError(graph_port.node.clone()));
+            }
+        }
+
+        Ok(Self {
+            graph,
+            node_map,
+            node_implementations: config.node_implementations.clone(),
+            inputs: config.inputs.clone(),
+            outputs: config.outputs.clone(),
+            subgraphs: config.subgraphs.clone(),
+        })
+    }
+
+    /// Returns the implementation declared for the type of the node at `index`.
+    pub fn node_implementation(&self, index: NodeIndex) -> Option<&NodeImplementationData> {
+        let node = self.graph.node_weight(index)?;
+        self.node_implementations.get(&node.data.r#type)
+    }
+
+    /// Resolves the output and input port names of an edge.
+    ///
+    /// An edge without an explicit port refers to the only port declared on that side.
+    pub fn edge_ports(&self, edge: EdgeIndex) -> (Option<String>, Option<String>) {
+        let Some((from, to)) = self.graph.edge_endpoints(edge) else {
+            return (None, None);
+        };
+        let edge_data = &self.graph[edge];
+        (
+            self.resolve_port(from, &edge_data.from_port, true),
+            self.resolve_port(to, &edge_data.to_port, false),
+        )
+    }
+
+    /// Resolves the name of the node port a graph input or output refers to.
+    ///
+    /// A graph port without an explicit port refers to the only port the node declares.
+    pub fn graph_port(&self, graph_port: &GraphPortData, output: bool) -> Option<String> {
+        let index = *self.node_map.get(&graph_port.node)?;
+        self.resolve_port(index, &graph_port.port, output)
+    }
+
+    fn resolve_port(
+        &self,
+        index: NodeIndex,
+        port: &Option<String>,
+        output: bool,
+    ) -> Option<String> {
+        port.clone().or_else(|| {
+            let implementation = self.node_implementation(index)?;
+            let declared = if output {
+                implementation.output.as_ref()
+            } else {
+                implementation.input.as_ref()
+            };
+            match declared {
+                Some(ports) if ports.len() == 1 => ports.keys().next().cloned(),
+                _ => None,
+            }
+        })
+    }
+
+    /// Checks that every edge connects an output to an input of a compatible type.
+    ///
+    /// Edges whose ports or types cannot be resolved are skipped; those are reported by
+    /// `cillio_config::validate`.
+    pub fn check_types(&self, rules: &TypeRules) -> Result<(), GraphError> {
+        let port_type = |index: NodeIndex, port: &str, output: bool| {
+            let implementation = self.node_implementation(index)?;
+            let ports = if output {
+                implementation.output.as_ref()
+            } else {
+                implementation.input.as_ref()
+            };
+            ports?.get(port)?.parse::<PortType>().ok()
+        };
+
+        let mut mismatches = Vec::new();
+        for edge in self.graph.edge_references() {
+            let (Some(from_port), Some(to_port)) = self.edge_ports(edge.id()) else {
+                continue;
+            };
+            let (Some(from_type), Some(to_type)) = (
+                port_type(edge.source(), &from_port, true),
+                port_type(edge.target(), &to_port, false),
+            ) else {
+                continue;
+            };
+            if !from_type.is_assignable_to(&to_type, rules) {
+                mismatches.push(TypeMismatch {
+                    from_node: self.graph[edge.source()].id.clone(),
+                    from_port,
+                    from_type,
+                    to_node: self.graph[edge.target()].id.clone(),
+                    to_port,
+                    to_type,
+                });
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(GraphError::TypeMismatchError(mismatches))
+        }
     }
 
     pub fn print_dot(&self) {
-        let dot = Dot::with_attr_getters(
-            &self.graph,
-            &[Config::EdgeNoLabel, Config::NodeNoLabel],
-            &|_, edge| {
-                let edge_data = edge.weight();
-                let mut labels = vec![];
-                if let Some(ref from_port) = edge_data.from_port {
-                    labels.push(format!("from: {}", from_port));
+        println!("{}", self.to_dot());
+    }
+
+    /// Renders the graph in the DOT language. Nodes of expanded subgraphs are grouped in
+    /// nested clusters labelled with their composite node.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        self.write_cluster(&mut dot, None, 1);
+        for edge in self.graph.edge_references() {
+            let edge_data = edge.weight();
+            let mut labels = vec![];
+            if let Some(ref from_port) = edge_data.from_port {
+                labels.push(format!("from: {}", from_port));
+            }
+            if let Some(ref to_port) = edge_data.to_port {
+                labels.push(format!("to: {}", to_port));
+            }
+            dot.push_str(&format!(
+                "    {} -> {} [ label=\"{}\"]\n",
+                edge.source().index(),
+                edge.target().index(),
+                labels.join("\\n")
+            ));
+        }
+        dot.push('}');
+        dot
+    }
+
+    /// Writes the nodes directly within `cluster`, or outside of any cluster, followed by
+    /// the clusters nested in it.
+    fn write_cluster(&self, dot: &mut String, cluster: Option<&str>, depth: usize) {
+        let indent = "    ".repeat(depth);
+        for index in self.graph.node_indices() {
+            let node = &self.graph[index];
+            if self.subgraph_of(&node.id) != cluster {
+                continue;
+            }
+            let mut label = format!("{}\\n{}", node.id, node.data.r#type);
+            if let Some(ref state) = node.data.state {
+                label.push_str(&format!("\\n{}", state).replace("\"", "\\\""));
+            }
+            dot.push_str(&format!("{}{} [ label=\"{}\"]\n", indent, index.index(), label));
+        }
+
+        let mut children: Vec<_> = self
+            .subgraphs
+            .iter()
+            .filter(|(node_id, _)| self.subgraph_of(node_id) == cluster)
+            .collect();
+        children.sort_by_key(|(node_id, _)| *node_id);
+        for (node_id, subgraph) in children {
+            dot.push_str(&format!("{}subgraph \"cluster_{}\" {{\n", indent, node_id));
+            dot.push_str(&format!(
+                "{}    label=\"{}\\n{}\"\n",
+                indent, node_id, subgraph.r#type
+            ));
+            self.write_cluster(dot, Some(node_id), depth + 1);
+            dot.push_str(&format!("{}}}\n", indent));
+        }
+    }
+
+    /// Returns the innermost expanded composite node that `node_id` was part of.
+    pub fn subgraph_of(&self, node_id: &str) -> Option<&str> {
+        let mut prefix = node_id;
+        while let Some((parent, _)) = prefix.rsplit_once(SUBGRAPH_SEPARATOR) {
+            if let Some((composite, _)) = self.subgraphs.get_key_value(parent) {
+                return Some(composite);
+            }
+            prefix = parent;
+        }
+        None
+    }
+
+    /// Finds the cycles of the graph, one per strongly connected component.
+    ///
+    /// Each cycle lists the node ids along the loop, starting and ending at the same node.
+    pub fn find_cycles(&self) -> Vec<Vec<String>> {
+        let mut cycles: Vec<_> = tarjan_scc(&self.graph)
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || self.graph.contains_edge(component[0], component[0])
+            })
+            .filter_map(|component| {
+                let start = *component.iter().min_by_key(|index| &self.graph[**index].id)?;
+                let members: HashSet<_> = component.into_iter().collect();
+                let path = self.find_loop(start, &members)?;
+                Some(path.into_iter().map(|index| self.graph[index].id.clone()).collect())
+            })
+            .collect();
+        cycles.sort();
+        cycles
+    }
+
+    /// Finds the shortest path from `start` back to itself within `members`.
+    fn find_loop(&self, start: NodeIndex, members: &HashSet<NodeIndex>) -> Option<Vec<NodeIndex>> {
+        let mut previous = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            for next in self.graph.neighbors(node) {
+                if !members.contains(&next) {
+                    continue;
                 }
-                if let Some(ref to_port) = edge_data.to_port {
-                    labels.push(format!("to: {}", to_port));
+                if next == start {
+                    let mut path = vec![start, node];
+                    let mut current = node;
+                    while let Some(&parent) = previous.get(&current) {
+                        path.push(parent);
+                        current = parent;
+                    }
+                    path.reverse();
+                    return Some(path);
                 }
-                format!("label=\"{}\"", labels.join("\\n"))
-            },
-            &|_, (_, node)| {
-                let mut label = format!("{}\\n{}", node.id, node.data.r#type);
-                if let Some(ref state) = node.data.state {
-                    label.push_str(&format!("\\n{}", state).replace("\"", "\\\""));
+                if let Entry::Vacant(entry) = previous.entry(next) {
+                    entry.insert(node);
+                    queue.push_back(next);
                 }
-                format!("label=\"{}\"", label)
-            },
-        );
-        println!("{:?}", dot);
+            }
+        }
+        None
     }
 
     pub fn node_map(&self) -> &HashMap<String, NodeIndex> {
@@ -83,4 +318,109 @@ impl Graph {
     pub fn graph(&self) -> &DiGraph<Node, Edge> {
         &self.graph
     }
+
+    pub fn node_implementations(&self) -> &HashMap<String, NodeImplementationData> {
+        &self.node_implementations
+    }
+
+    /// Returns the inputs of the graph by name.
+    pub fn inputs(&self) -> &HashMap<String, GraphPortData> {
+        &self.inputs
+    }
+
+    /// Returns the outputs of the graph by name.
+    pub fn outputs(&self) -> &HashMap<String, GraphPortData> {
+        &self.outputs
+    }
+
+    /// Returns the expanded composite nodes by node id.
+    pub fn subgraphs(&self) -> &HashMap<String, SubgraphData> {
+        &self.subgraphs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph_with_types(output: &str, input: &str) -> Graph {
+        let config: GraphConfig = serde_json::from_value(serde_json::json!({
+            "node_implementations": {
+                "source": { "output": { "value": output }, "wasm": "source" },
+                "sink": { "input": { "value": input }, "wasm": "sink" }
+            },
+            "nodes": {
+                "source": { "type": "source" },
+                "sink": { "type": "sink" }
+            },
+            "edges": [{ "from": "source", "to": "sink" }]
+        }))
+        .unwrap();
+        Graph::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_check_types() {
+        let rules = TypeRules::default();
+        assert!(graph_with_types("float", "number").check_types(&rules).is_ok());
+        assert!(graph_with_types("int", "float").check_types(&rules).is_ok());
+        assert!(graph_with_types("int", "float")
+            .check_types(&TypeRules::strict())
+            .is_err());
+
+        let Err(GraphError::TypeMismatchError(mismatches)) =
+            graph_with_types("string", "list<float>").check_types(&rules)
+        else {
+            panic!("expected a type mismatch");
+        };
+        assert_eq!(
+            mismatches[0].to_string(),
+            "source.value (string) cannot feed sink.value (list<float>)"
+        );
+    }
+
+    #[test]
+    fn test_dot_clusters_subgraphs() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../assets/composite_graph");
+        let config =
+            cillio_config::load_config(&dir.join("composite_graph.json").to_string_lossy())
+                .unwrap();
+        let graph = Graph::new(&cillio_config::expand_subgraphs(&config, &dir).unwrap()).unwrap();
+        assert_eq!(graph.subgraph_of("sum/adder"), Some("sum"));
+        assert_eq!(graph.subgraph_of("log"), None);
+
+        let dot = graph.to_dot();
+        let cluster = dot.find("subgraph \"cluster_sum\" {").unwrap();
+        let adder = dot.find("label=\"sum/adder\\nadd-numbers\"]").unwrap();
+        let log = dot.find("label=\"log\\nlog-number\"]").unwrap();
+        // Nodes outside of clusters come first.
+        assert!(log < cluster && cluster < adder);
+    }
+
+    #[test]
+    fn test_find_cycles() {
+        let config: GraphConfig = serde_json::from_value(serde_json::json!({
+            "node_implementations": {},
+            "nodes": {
+                "a": { "type": "t" },
+                "b": { "type": "t" },
+                "c": { "type": "t" },
+                "d": { "type": "t" }
+            },
+            "edges": [
+                { "from": "a", "to": "b" },
+                { "from": "b", "to": "c" },
+                { "from": "c", "to": "a" },
+                { "from": "c", "to": "d" },
+                { "from": "d", "to": "d" }
+            ]
+        }))
+        .unwrap();
+        let graph = Graph::new(&config).unwrap();
+        assert_eq!(
+            graph.find_cycles(),
+            vec![vec!["a", "b", "c", "a"], vec!["d", "d"]]
+        );
+    }
 }
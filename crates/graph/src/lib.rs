@@ -4,4 +4,4 @@ mod graph;
 
 pub use node::Node;
 pub use edge::Edge;
-pub use graph::{Graph, GraphError};
\ No newline at end of file
+pub use graph::{Graph, GraphError, TypeMismatch};
\ No newline at end of file
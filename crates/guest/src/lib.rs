@@ -0,0 +1,23 @@
+//! Helpers for node components running inside the cillio runtime.
+//!
+//! The host hands out node state through the `cillio:node/host.get-state` import as the
+//! node's configured `state` value, encoded as UTF-8 JSON bytes.
+
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("Node state is missing")]
+    MissingState,
+
+    #[error("Failed to decode node state: {0}")]
+    DecodeError(#[from] serde_json::Error),
+}
+
+/// Decodes the bytes returned by `get-state` into the node's state type.
+pub fn decode_state<T: DeserializeOwned>(state: Option<Vec<u8>>) -> Result<T, StateError> {
+    let state = state.ok_or(StateError::MissingState)?;
+    let state = serde_json::from_slice(&state)?;
+    Ok(state)
+}
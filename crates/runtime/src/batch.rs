@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use futures::future::try_join_all;
+use serde_json::{Map, Value};
+
+use crate::execution_plan::{new_run_id, ExecutionPlan, ExecutionStep};
+use crate::instance_pool::InstanceReuse;
+use crate::logs::NodeLogs;
+use crate::node_instance::NodeInstance;
+use crate::runtime_data::PortValues;
+use crate::{Runtime, RuntimeError};
+
+/// Overrides applied to a graph for one row of a batch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchRow {
+    /// Replacement states keyed by node id.
+    pub states: HashMap<String, Value>,
+    /// Input port values keyed by node id and port name. They take precedence over the
+    /// values routed along edges into the same ports.
+    pub inputs: HashMap<String, Map<String, Value>>,
+}
+
+impl BatchRow {
+    /// Reads a row for the given plan from a JSON object.
+    ///
+    /// A key naming a graph input feeds the port the input is declared for, like the inputs
+    /// of [`ExecutionPlan::call`]. Otherwise a `"node"` key replaces the state of that node,
+    /// and a `"node.port"` key feeds the value into an input port of that node. Node ids may
+    /// contain dots, a key is split after the longest node id it starts with.
+    pub fn from_json(row: &Map<String, Value>, plan: &ExecutionPlan) -> Result<Self, RuntimeError> {
+        let is_node = |node_id: &str| plan.steps().any(|step| step.node_id == node_id);
+        let mut batch_row = BatchRow::default();
+        for (key, value) in row {
+            if plan.input_names().contains(&key.as_str()) {
+                plan.feed_input(&mut batch_row, key, value)?;
+            } else if is_node(key) {
+                batch_row.states.insert(key.clone(), value.clone());
+            } else {
+                let (node_id, port) = key
+                    .rmatch_indices('.')
+                    .map(|(index, _)| (&key[..index], &key[index + 1..]))
+                    .find(|(node_id, _)| is_node(node_id))
+                    .ok_or_else(|| {
+                        RuntimeError::NodeNotFoundError(format!(
+                            "<{}> is neither a graph input nor a node or node port",
+                            key
+                        ))
+                    })?;
+                batch_row
+                    .inputs
+                    .entry(node_id.to_string())
+                    .or_default()
+                    .insert(port.to_string(), value.clone());
+            }
+        }
+        Ok(batch_row)
+    }
+}
+
+/// Outputs and logs of every node for one row of a batch.
+#[derive(Debug, Clone, Default)]
+pub struct RowOutputs {
+    pub outputs: HashMap<String, PortValues>,
+    pub logs: HashMap<String, NodeLogs>,
+}
+
+/// Result of one row of a batch.
+#[derive(Debug)]
+pub struct BatchResult {
+    /// Index of the row in the batch.
+    pub row: usize,
+    pub result: Result<RowOutputs, RuntimeError>,
+}
+
+/// Node instances of a batch worker, created on first use and reused for later rows.
+#[derive(Default)]
+struct WorkerInstances {
+    instances: HashMap<String, NodeInstance>,
+}
+
+impl WorkerInstances {
+    async fn get(
+        &mut self,
+        runtime: &Runtime,
+        step: &ExecutionStep<Value>,
+    ) -> Result<&mut NodeInstance, RuntimeError> {
+        if !self.instances.contains_key(&step.node_id) {
+            let instance = step
+                .acquire_instance(runtime, step.node_state.as_ref())
+                .await?;
+            self.instances.insert(step.node_id.clone(), instance);
+        }
+        Ok(self.instances.get_mut(&step.node_id).unwrap())
+    }
+
+    fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    /// Hands all instances back to the runtime.
+    fn release(self, runtime: &Runtime, plan: &ExecutionPlan) {
+        let mut instances = self.instances;
+        for step in plan.steps() {
+            if let Some(instance) = instances.remove(&step.node_id) {
+                step.release_instance(runtime, instance);
+            }
+        }
+    }
+}
+
+impl ExecutionPlan {
+    /// Executes the plan once per row and returns the results in row order.
+    ///
+    /// Rows are spread over up to the plan's maximum concurrency of workers. The workers
+    /// run concurrently on the calling task, interleaving whenever a node awaits, not on
+    /// threads of their own. Unless instances are [`InstanceReuse::Fresh`], each worker instantiates
+    /// every node once and reuses the instance for all of its rows, only replacing the state
+    /// between rows, so nodes keeping data in their linear memory see it across rows. A
+    /// failed row does not stop the batch; the instances of the worker are recreated for its
+    /// next row.
+    pub async fn execute_batch(
+        &self,
+        runtime: &Runtime,
+        rows: &[BatchRow],
+    ) -> Result<Vec<BatchResult>, anyhow::Error> {
+        for row in rows {
+            for node_id in row.states.keys().chain(row.inputs.keys()) {
+                if !self.steps().any(|step| step.node_id == *node_id) {
+                    return Err(RuntimeError::NodeNotFoundError(format!(
+                        "Node not found: {}",
+                        node_id
+                    ))
+                    .into());
+                }
+            }
+        }
+
+        let run_id = new_run_id();
+        let run_id = run_id.as_str();
+        let workers = self.max_concurrency().min(rows.len()).max(1);
+        let batches = (0..workers).map(|worker| async move {
+            let mut instances = WorkerInstances::default();
+            let mut results = Vec::new();
+            for (index, row) in rows.iter().enumerate().skip(worker).step_by(workers) {
+                let result = self.execute_row(runtime, &mut instances, row, run_id).await;
+                // A trap may leave an instance in an unusable state.
+                if result.is_err() || runtime.instance_reuse() == InstanceReuse::Fresh {
+                    instances.clear();
+                }
+                results.push(BatchResult { row: index, result });
+            }
+            instances.release(runtime, self);
+            Ok::<_, anyhow::Error>(results)
+        });
+        let mut results: Vec<_> = try_join_all(batches).await?.into_iter().flatten().collect();
+        results.sort_by_key(|result| result.row);
+        Ok(results)
+    }
+
+    async fn execute_row(
+        &self,
+        runtime: &Runtime,
+        instances: &mut WorkerInstances,
+        row: &BatchRow,
+        run_id: &str,
+    ) -> Result<RowOutputs, RuntimeError> {
+        let mut row_outputs = RowOutputs::default();
+        for step in self.steps() {
+            let input_ports = runtime.input_ports(&step.node_type)?;
+            let mut inputs = step.collect_inputs(&input_ports, &row_outputs.outputs)?;
+            let instance = instances.get(runtime, step).await?;
+            if let Some(fed_inputs) = row.inputs.get(&step.node_id) {
+                inputs.extend(instance.inputs_from_json(fed_inputs)?);
+            }
+            instance.set_state(row.states.get(&step.node_id).or(step.node_state.as_ref()))?;
+
+            let result = instance.process(&inputs).await;
+            let logs = instance.take_logs();
+            logs.emit(&step.node_id, &step.node_type, run_id);
+            let outputs = result?;
+            row_outputs.outputs.insert(step.node_id.clone(), outputs);
+            row_outputs.logs.insert(step.node_id.clone(), logs);
+        }
+        Ok(row_outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use serde_json::json;
+    use wasmtime::component::Val;
+
+    use super::*;
+    use crate::instance_pool::RuntimeConfig;
+    use crate::test_utils::{plan_from_json, runtime_with_components, INCREMENT};
+
+    #[test]
+    fn test_execute_batch() {
+        let plan = plan_from_json(json!({
+            "node_implementations": {
+                "increment": {
+                    "input": {"value": "int"},
+                    "output": {"value": "int"},
+                    "wasm": "increment"
+                }
+            },
+            "nodes": {"a": {"type": "increment"}, "b": {"type": "increment"}},
+            "edges": [{"from": "a", "to": "b"}]
+        }))
+        .with_max_concurrency(2);
+        let runtime =
+            runtime_with_components(RuntimeConfig::default(), &[("increment", INCREMENT)]);
+
+        let rows: Vec<_> = [json!({"a.value": 1}), json!({}), json!({"a.value": 10})]
+            .iter()
+            .map(|row| BatchRow::from_json(row.as_object().unwrap(), &plan).unwrap())
+            .collect();
+        let results = block_on(plan.execute_batch(&runtime, &rows)).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[0].result.as_ref().unwrap().outputs["b"]["value"],
+            Val::U32(3)
+        );
+        // The second row leaves the input of `a` unconnected.
+        assert!(results[1].result.is_err());
+        assert_eq!(
+            results[2].result.as_ref().unwrap().outputs["b"]["value"],
+            Val::U32(12)
+        );
+    }
+
+    #[test]
+    fn test_batch_row_from_json() {
+        let plan = plan_from_json(json!({
+            "node_implementations": {
+                "increment": {
+                    "input": {"value": "int"},
+                    "output": {"value": "int"},
+                    "wasm": "increment"
+                }
+            },
+            "nodes": {"src.a": {"type": "increment"}, "b": {"type": "increment"}},
+            "edges": [{"from": "src.a", "to": "b"}],
+            "inputs": {"x": {"node": "src.a", "port": "value"}}
+        }));
+        let row = |row: Value| BatchRow::from_json(row.as_object().unwrap(), &plan);
+
+        // A graph input and a port of a node with a dotted id feed the same port.
+        let expected = BatchRow {
+            inputs: HashMap::from([(
+                "src.a".to_string(),
+                json!({"value": 1}).as_object().unwrap().clone(),
+            )]),
+            ..Default::default()
+        };
+        assert_eq!(row(json!({"x": 1})).unwrap(), expected);
+        assert_eq!(row(json!({"src.a.value": 1})).unwrap(), expected);
+
+        let states = row(json!({"src.a": {"seed": 2}})).unwrap().states;
+        assert_eq!(
+            states,
+            HashMap::from([("src.a".to_string(), json!({"seed": 2}))])
+        );
+        assert!(row(json!({"src.value": 1})).is_err());
+        assert!(row(json!({"y": 1})).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+use cillio_config::{DirAccess, NodeCapabilities, StdioPolicy};
+use wasmtime_wasi::{
+    DirPerms, FilePerms, HostMonotonicClock, HostWallClock, WasiCtx, WasiCtxBuilder,
+};
+
+use crate::logs::OutputCapture;
+use crate::RuntimeError;
+
+/// Clock that always reports time zero, given to nodes without clock access.
+struct FrozenClock;
+
+impl HostWallClock for FrozenClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+impl HostMonotonicClock for FrozenClock {
+    fn resolution(&self) -> u64 {
+        1_000_000_000
+    }
+
+    fn now(&self) -> u64 {
+        0
+    }
+}
+
+/// Builds the WASI context of a node instance that grants exactly the given capabilities,
+/// together with the capture of its output streams.
+pub(crate) fn wasi_ctx(
+    node_id: &str,
+    capabilities: &NodeCapabilities,
+) -> Result<(WasiCtx, OutputCapture), RuntimeError> {
+    let mut builder = WasiCtxBuilder::new();
+    let mut capture = OutputCapture::default();
+
+    for preopen in &capabilities.preopens {
+        let (dir_perms, file_perms) = match preopen.access {
+            DirAccess::ReadOnly => (DirPerms::READ, FilePerms::READ),
+            DirAccess::ReadWrite => (DirPerms::all(), FilePerms::all()),
+        };
+        builder
+            .preopened_dir(&preopen.host, &preopen.guest, dir_perms, file_perms)
+            .map_err(|err| {
+                RuntimeError::NodeInitializationError(format!(
+                    "{}: cannot preopen <{}>: {:#}",
+                    node_id, preopen.host, err
+                ))
+            })?;
+    }
+
+    for name in &capabilities.env {
+        if let Ok(value) = std::env::var(name) {
+            builder.env(name, value);
+        }
+    }
+    builder.args(&capabilities.args);
+
+    if capabilities.stdin == StdioPolicy::Inherit {
+        builder.inherit_stdin();
+    }
+    match capabilities.stdout {
+        StdioPolicy::Deny => {}
+        StdioPolicy::Inherit => {
+            builder.inherit_stdout();
+        }
+        StdioPolicy::Capture => {
+            builder.stdout(capture.stdout_stream());
+        }
+    }
+    match capabilities.stderr {
+        StdioPolicy::Deny => {}
+        StdioPolicy::Inherit => {
+            builder.inherit_stderr();
+        }
+        StdioPolicy::Capture => {
+            builder.stderr(capture.stderr_stream());
+        }
+    }
+
+    if !capabilities.clocks {
+        builder.wall_clock(FrozenClock);
+        builder.monotonic_clock(FrozenClock);
+    }
+
+    Ok((builder.build(), capture))
+}
+
+#[cfg(test)]
+mod tests {
+    use cillio_config::Preopen;
+    use futures::executor::block_on;
+    use wasmtime::component::Val;
+    use wasmtime_wasi::bindings::filesystem::preopens::Host as _;
+    use wasmtime_wasi::bindings::filesystem::types::{
+        DescriptorFlags, ErrorCode, HostDescriptor, OpenFlags, PathFlags,
+    };
+
+    use super::*;
+    use crate::runtime_data::PortValues;
+    use crate::test_utils::TIMED;
+    use crate::{Runtime, ServerWasiView};
+
+    /// Returns the times the timed node saw with the given capabilities.
+    fn timed_outputs(capabilities: &NodeCapabilities) -> (Val, Val) {
+        let mut runtime = Runtime::new();
+        block_on(runtime.load_component("timed", &TIMED.as_bytes().to_vec())).unwrap();
+        let mut instance = block_on(runtime.initialize_node::<()>(
+            "node",
+            "timed",
+            None,
+            Default::default(),
+            capabilities,
+        ))
+        .unwrap();
+        let mut outputs = block_on(instance.process(&PortValues::new())).unwrap();
+        (
+            outputs.remove("start").unwrap(),
+            outputs.remove("end").unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_clocks_are_frozen_without_grant() {
+        let (start, end) = timed_outputs(&NodeCapabilities::default());
+        assert_eq!((start, end), (Val::U64(0), Val::U64(0)));
+
+        let clocks = NodeCapabilities {
+            clocks: true,
+            ..Default::default()
+        };
+        match timed_outputs(&clocks) {
+            (Val::U64(start), Val::U64(end)) => assert!(0 < start && start <= end),
+            other => panic!("unexpected outputs: {:?}", other),
+        }
+    }
+
+    /// Opens `path` in the only preopened directory, holding `data.txt`, of a node with the
+    /// given access, creating the file if it does not exist.
+    fn open_preopened(access: DirAccess, path: &str, flags: DescriptorFlags) -> Option<ErrorCode> {
+        let dir = std::env::temp_dir().join(format!(
+            "cillio-preopen-test-{:?}-{}",
+            access,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("data.txt"), "data").unwrap();
+        let capabilities = NodeCapabilities {
+            preopens: vec![Preopen {
+                host: dir.to_str().unwrap().to_string(),
+                guest: "/data".to_string(),
+                access,
+            }],
+            ..Default::default()
+        };
+        let (ctx, capture) = wasi_ctx("node", &capabilities).unwrap();
+        let mut view = ServerWasiView::new("node", None, None, ctx, capture);
+        let open_flags = if dir.join(path).exists() {
+            OpenFlags::empty()
+        } else {
+            OpenFlags::CREATE
+        };
+        let (descriptor, guest) = view.get_directories().unwrap().remove(0);
+        assert_eq!(guest, "/data");
+        let result = block_on(view.open_at(
+            descriptor,
+            PathFlags::empty(),
+            path.to_string(),
+            open_flags,
+            flags,
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.err().map(|err| err.downcast().unwrap())
+    }
+
+    #[test]
+    fn test_read_only_preopen_rejects_writes() {
+        let read_only = DirAccess::ReadOnly;
+        assert_eq!(
+            open_preopened(read_only, "data.txt", DescriptorFlags::READ),
+            None
+        );
+        assert_eq!(
+            open_preopened(read_only, "data.txt", DescriptorFlags::WRITE),
+            Some(ErrorCode::NotPermitted)
+        );
+        assert_eq!(
+            open_preopened(read_only, "new.txt", DescriptorFlags::WRITE),
+            Some(ErrorCode::NotPermitted)
+        );
+        assert_eq!(
+            open_preopened(DirAccess::ReadWrite, "new.txt", DescriptorFlags::WRITE),
+            None
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use directories::ProjectDirs;
+use sha2::{Digest, Sha256};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+const CACHE_DIR_ENV: &str = "CILLIO_CACHE_DIR";
+const ENTRY_EXTENSION: &str = "cwasm";
+
+/// Returns the root of all caches, `CILLIO_CACHE_DIR` or the user's cache directory.
+pub fn cache_root() -> PathBuf {
+    if let Some(dir) = std::env::var_os(CACHE_DIR_ENV) {
+        PathBuf::from(dir)
+    } else if let Some(project_dirs) = ProjectDirs::from("io", "cillio", "cillio") {
+        project_dirs.cache_dir().to_path_buf()
+    } else {
+        PathBuf::from(".").join(".cache")
+    }
+}
+
+/// On-disk cache of compiled components.
+///
+/// Entries are keyed by the SHA-256 of the component bytes and the engine's compatibility
+/// hash, so changing the engine configuration never reuses an entry. Entries written by a
+/// different wasmtime version fail to deserialize; they are treated as misses and replaced.
+#[derive(Debug, Clone)]
+pub struct ComponentCache {
+    dir: PathBuf,
+}
+
+/// Number and total size of the entries in a cache directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub size_bytes: u64,
+}
+
+impl ComponentCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the `components` directory in the [`cache_root`].
+    pub fn default_dir() -> PathBuf {
+        Self::dir_in(&cache_root())
+    }
+
+    /// Returns the `components` directory in the given cache root.
+    pub fn dir_in(root: &Path) -> PathBuf {
+        root.join("components")
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, engine: &Engine, bytes: &[u8]) -> PathBuf {
+        let mut engine_hasher = DefaultHasher::new();
+        engine
+            .precompile_compatibility_hash()
+            .hash(&mut engine_hasher);
+
+        let mut hasher = Sha256::new();
+        hasher.update(bytes);
+        hasher.update(engine_hasher.finish().to_le_bytes());
+        self.dir
+            .join(format!("{:x}", hasher.finalize()))
+            .with_extension(ENTRY_EXTENSION)
+    }
+
+    /// Loads the compiled component for `bytes`, or `None` on a cache miss.
+    ///
+    /// Entries that cannot be deserialized are removed.
+    pub fn load(&self, engine: &Engine, bytes: &[u8]) -> Option<Component> {
+        let path = self.entry_path(engine, bytes);
+        if !path.is_file() {
+            return None;
+        }
+        // SAFETY: entries are only written by `store` from `Component::serialize` output and
+        // are moved into place atomically, so the file is a complete artifact of wasmtime.
+        match unsafe { Component::deserialize_file(engine, &path) } {
+            Ok(component) => {
+                let _ = touch(&path);
+                Some(component)
+            }
+            Err(_) => {
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Stores the compiled `component` for `bytes`.
+    pub fn store(&self, engine: &Engine, bytes: &[u8], component: &Component) -> io::Result<()> {
+        let serialized = component.serialize().map_err(io::Error::other)?;
+        fs::create_dir_all(&self.dir)?;
+        let path = self.entry_path(engine, bytes);
+        let temp_path = path.with_extension(format!("{}.{}", ENTRY_EXTENSION, std::process::id()));
+        fs::write(&temp_path, serialized)?;
+        fs::rename(&temp_path, &path)
+    }
+
+    pub fn stats(&self) -> io::Result<CacheStats> {
+        cache_stats(&self.dir, ENTRY_EXTENSION)
+    }
+
+    /// Removes all entries and returns what was removed.
+    pub fn clear(&self) -> io::Result<CacheStats> {
+        remove_entries(&self.dir, ENTRY_EXTENSION, Duration::ZERO)
+    }
+
+    /// Removes entries not used within `max_age` and returns what was removed.
+    pub fn gc(&self, max_age: Duration) -> io::Result<CacheStats> {
+        remove_entries(&self.dir, ENTRY_EXTENSION, max_age)
+    }
+}
+
+/// Lists the cache entries with the given extension in `dir`.
+pub(crate) fn cache_entries(dir: &Path, extension: &str) -> io::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    Ok(fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect())
+}
+
+pub(crate) fn cache_stats(dir: &Path, extension: &str) -> io::Result<CacheStats> {
+    let mut stats = CacheStats::default();
+    for path in cache_entries(dir, extension)? {
+        stats.entries += 1;
+        stats.size_bytes += fs::metadata(&path)?.len();
+    }
+    Ok(stats)
+}
+
+/// Marks an entry as used by updating its modification time.
+pub(crate) fn touch(path: &Path) -> io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Removes the entries last modified more than `max_age` ago.
+pub(crate) fn remove_entries(
+    dir: &Path,
+    extension: &str,
+    max_age: Duration,
+) -> io::Result<CacheStats> {
+    let mut removed = CacheStats::default();
+    for path in cache_entries(dir, extension)? {
+        let metadata = fs::metadata(&path)?;
+        let age = metadata.modified()?.elapsed().unwrap_or(Duration::ZERO);
+        if age >= max_age {
+            fs::remove_file(&path)?;
+            removed.entries += 1;
+            removed.size_bytes += metadata.len();
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::Config;
+
+    fn engine() -> Engine {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        Engine::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_store_load_and_clear() {
+        let dir = std::env::temp_dir().join(format!("cillio-cache-test-{}", std::process::id()));
+        let cache = ComponentCache::new(&dir);
+        let engine = engine();
+        let bytes = b"(component)".to_vec();
+
+        assert!(cache.load(&engine, &bytes).is_none());
+        let component = Component::new(&engine, &bytes).unwrap();
+        cache.store(&engine, &bytes, &component).unwrap();
+        assert!(cache.load(&engine, &bytes).is_some());
+        assert_eq!(cache.stats().unwrap().entries, 1);
+
+        // Unreadable entries are misses and get removed.
+        fs::write(cache.entry_path(&engine, &bytes), b"corrupted").unwrap();
+        assert!(cache.load(&engine, &bytes).is_none());
+        assert_eq!(cache.stats().unwrap().entries, 0);
+
+        cache.store(&engine, &bytes, &component).unwrap();
+        assert_eq!(cache.clear().unwrap().entries, 1);
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1043 @@
+//! Compiles a graph into a single self-contained component.
+//!
+//! Every node component is instantiated once per node and wired with wasm-compose to a
+//! generated orchestrator component. The orchestrator calls the `process` export of every
+//! node in topological order, passes outputs along the edges of the graph and exports the
+//! `cillio:graph/graph` interface of `wit/graph/world.wit`. Nodes importing
+//! `cillio:node/host` are connected to a generated component returning their state.
+//!
+//! Port values are passed through the memory of the orchestrator, so only ports of scalar
+//! types (booleans, integers, floats and chars) can be connected, and every input port of a
+//! node must be connected. Other imports of the node components, such as WASI, are left
+//! to the host of the composed component.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use cillio_graph::{Graph, Node};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use thiserror::Error;
+use wasm_compose::graph::{Component, CompositionGraph, EncodeOptions};
+use wasmparser::component_types::{ComponentDefinedType, ComponentEntityType, ComponentValType};
+use wasmparser::types::TypesRef;
+use wasmparser::{PrimitiveValType, Validator};
+
+use crate::execution_plan::{ExecutionPlan, PlanError};
+
+/// Interface through which nodes read their state.
+pub const HOST_INTERFACE: &str = "cillio:node/host@0.1.0";
+/// Interface exported by a composed graph.
+pub const GRAPH_INTERFACE: &str = "cillio:graph/graph@0.1.0";
+
+/// Bytes at the start of the orchestrator memory holding the results of `compute` and
+/// `print`.
+const HEADER_SIZE: u32 = 32;
+const PAGE_SIZE: u32 = 65536;
+
+#[derive(Error, Debug)]
+pub enum ComposeError {
+    #[error(transparent)]
+    PlanError(#[from] PlanError),
+
+    #[error("No component for node type <{0}>")]
+    MissingComponentError(String),
+
+    #[error("Cannot compose node <{0}>: {1}")]
+    UnsupportedNodeError(String, String),
+
+    #[error("Failed to compose components: {0}")]
+    CompositionError(#[from] anyhow::Error),
+}
+
+/// Composes the node components of `graph`, keyed by node type, into one component
+/// exporting `cillio:graph/graph`.
+///
+/// `compute` runs every node once and fails with `computation-error` when a node returns
+/// an error, and `print` returns the graph in DOT format.
+pub fn compose_graph(
+    graph: &Graph,
+    components: &HashMap<String, Vec<u8>>,
+) -> Result<Vec<u8>, ComposeError> {
+    let order: Vec<String> = ExecutionPlan::from_graph(graph)?
+        .levels()
+        .into_iter()
+        .flatten()
+        .map(str::to_string)
+        .collect();
+
+    let mut validator = Validator::new();
+    let mut composition = CompositionGraph::new();
+    let mut component_ids = HashMap::new();
+    let mut node_types: Vec<_> = order
+        .iter()
+        .map(|node_id| graph_node(graph, node_id).data().r#type.clone())
+        .collect();
+    node_types.sort();
+    node_types.dedup();
+    for (index, node_type) in node_types.iter().enumerate() {
+        let bytes = components
+            .get(node_type)
+            .ok_or_else(|| ComposeError::MissingComponentError(node_type.clone()))?;
+        let component = Component::from_bytes(
+            &mut validator,
+            format!("component{}", index),
+            bytes.as_slice(),
+        )?;
+        component_ids.insert(node_type.clone(), composition.add_component(component)?);
+    }
+
+    // Describe the process function of every node and where its ports live in memory.
+    let mut nodes: Vec<ComposedNode> = Vec::new();
+    let mut slot = HEADER_SIZE;
+    for node_id in &order {
+        let component = composition
+            .get_component(component_ids[&graph_node(graph, node_id).data().r#type])
+            .unwrap();
+        let node = ComposedNode::new(node_id, component, slot)
+            .map_err(|reason| ComposeError::UnsupportedNodeError(node_id.clone(), reason))?;
+        slot = node.slot + node.slot_size;
+        nodes.push(node);
+    }
+
+    let dot = graph.to_dot();
+    let orchestrator = orchestrator_wat(graph, &nodes, slot, dot.as_bytes())?;
+    let orchestrator = composition.add_component(Component::from_bytes(
+        &mut validator,
+        "graph",
+        orchestrator.into_bytes(),
+    )?)?;
+    let orchestrator_instance = composition.instantiate(orchestrator)?;
+
+    for (index, node) in nodes.iter().enumerate() {
+        let component_id = component_ids[&graph_node(graph, &node.id).data().r#type];
+        let instance = composition.instantiate(component_id)?;
+        let component = composition.get_component(component_id).unwrap();
+        let host_import = component
+            .import_by_name(HOST_INTERFACE)
+            .map(|(import, _)| import);
+        let (process, _, _) = component.export_by_name("process").unwrap();
+
+        if let Some(host_import) = host_import {
+            let state = graph_node(graph, &node.id)
+                .data()
+                .state
+                .as_ref()
+                .map(serde_json::to_vec)
+                .transpose()
+                .map_err(anyhow::Error::from)?;
+            let provider = composition.add_component(Component::from_bytes(
+                &mut validator,
+                format!("state{}", index),
+                state_provider_wat(state.as_deref()).into_bytes(),
+            )?)?;
+            let provider_instance = composition.instantiate(provider)?;
+            let (host_export, _, _) = composition
+                .get_component(provider)
+                .unwrap()
+                .export_by_name(HOST_INTERFACE)
+                .unwrap();
+            composition.connect(provider_instance, Some(host_export), instance, host_import)?;
+        }
+
+        let (node_import, _) = composition
+            .get_component(orchestrator)
+            .unwrap()
+            .import_by_name(&format!("node{}", index))
+            .unwrap();
+        composition.connect(instance, Some(process), orchestrator_instance, node_import)?;
+    }
+
+    Ok(composition.encode(EncodeOptions {
+        define_components: true,
+        export: Some(orchestrator_instance),
+        validate: true,
+    })?)
+}
+
+fn graph_node<'a>(graph: &'a Graph, node_id: &str) -> &'a Node {
+    &graph.graph()[graph.node_map()[node_id]]
+}
+
+/// A node called by the orchestrator.
+struct ComposedNode {
+    id: String,
+    /// Name of the parameter of the process function, if it takes inputs.
+    param_name: Option<String>,
+    param: Option<ValType>,
+    result: Option<ValType>,
+    /// Input ports, in the order of the fields of the parameter record.
+    inputs: Vec<(String, ValType)>,
+    /// Output ports with their offsets in the slot of the node.
+    outputs: Vec<(String, ValType, u32)>,
+    /// Whether the process function returns a `result` or an `option` of an error, whose
+    /// discriminant at the start of the slot is non-zero on failure.
+    fallible: bool,
+    /// Memory of the orchestrator the results of the node are written to.
+    slot: u32,
+    slot_size: u32,
+}
+
+impl ComposedNode {
+    fn new(id: &str, component: &Component, slot: u32) -> Result<Self, String> {
+        let types = component.types();
+        let process = match types.component_entity_type_of_export("process") {
+            Some(ComponentEntityType::Func(process)) => &types[process],
+            _ => return Err("the component has no process function".to_string()),
+        };
+
+        let (param_name, param, inputs) = match &*process.params {
+            [] => (None, None, Vec::new()),
+            [(name, ty)] => match val_type(types, ty)? {
+                ValType::Record(fields) => {
+                    let param = ValType::Record(fields.clone());
+                    (Some(name.to_string()), Some(param), fields)
+                }
+                _ => return Err("the inputs of the process function are not a record".into()),
+            },
+            _ => return Err("the process function takes more than one parameter".into()),
+        };
+        if let Some((port, _)) = inputs.iter().find(|(_, ty)| ty.load().is_none()) {
+            return Err(format!("input port <{}> is not of a scalar type", port));
+        }
+        if param.as_ref().is_some_and(|param| param.flat().len() > 16) {
+            return Err("the process function takes too many inputs".to_string());
+        }
+
+        let result = process
+            .result
+            .as_ref()
+            .map(|ty| val_type(types, ty))
+            .transpose()?;
+        let record_fields = |ty: &ValType, offset: u32| match ty {
+            ValType::Record(fields) => Ok(record_layout(fields)
+                .into_iter()
+                .zip(fields)
+                .map(|(field_offset, (name, ty))| (name.clone(), ty.clone(), offset + field_offset))
+                .collect()),
+            _ => Err("the outputs of the process function are not a record".to_string()),
+        };
+        let (outputs, fallible) = match &result {
+            None => (Vec::new(), false),
+            Some(ValType::Result(ok, err)) => {
+                let cases = [ok.as_deref(), err.as_deref()];
+                let outputs = match ok {
+                    Some(ok) => record_fields(ok, payload_offset(&cases))?,
+                    None => Vec::new(),
+                };
+                (outputs, true)
+            }
+            Some(ValType::Option(_)) => (Vec::new(), true),
+            Some(ty) => (record_fields(ty, 0)?, false),
+        };
+
+        let (size, _) = result.as_ref().map_or((0, 1), ValType::size_align);
+        Ok(Self {
+            id: id.to_string(),
+            param_name,
+            param,
+            result,
+            inputs,
+            outputs,
+            fallible,
+            slot,
+            slot_size: align_to(size.max(8), 8),
+        })
+    }
+
+    fn core_params(&self) -> Vec<CoreType> {
+        let mut params = self.param.as_ref().map(ValType::flat).unwrap_or_default();
+        if self.returns_pointer() {
+            params.push(CoreType::I32);
+        }
+        params
+    }
+
+    fn core_result(&self) -> Option<CoreType> {
+        match self.result.as_ref().map(ValType::flat).as_deref() {
+            Some([core_type]) => Some(*core_type),
+            _ => None,
+        }
+    }
+
+    /// Whether results are written to a pointer passed as the last parameter, because they
+    /// do not fit in a single core value.
+    fn returns_pointer(&self) -> bool {
+        self.result.as_ref().is_some_and(|ty| ty.flat().len() > 1)
+    }
+}
+
+/// Generates the component calling the nodes in order and exporting `cillio:graph/graph`.
+fn orchestrator_wat(
+    graph: &Graph,
+    nodes: &[ComposedNode],
+    slots_end: u32,
+    dot: &[u8],
+) -> Result<String, ComposeError> {
+    let dot_offset = align_to(slots_end, 8);
+    let heap = align_to(dot_offset + dot.len() as u32, 8);
+    let pages = heap / PAGE_SIZE + 1;
+    let positions: HashMap<&str, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (node.id.as_str(), index))
+        .collect();
+
+    let mut types = TypeWriter::default();
+    let mut imports = String::new();
+    let mut core_imports = String::new();
+    let mut body = String::new();
+    let mut shims = String::new();
+    let mut lowered = String::new();
+    let mut fixup_imports = String::new();
+    let mut fixup_exports = String::new();
+    for (index, node) in nodes.iter().enumerate() {
+        types.prefix = format!("node{}", index);
+        let param = match (&node.param_name, &node.param) {
+            (Some(name), Some(ty)) => format!(" (param {:?} {})", name, types.reference(ty)),
+            _ => String::new(),
+        };
+        let result = match &node.result {
+            Some(ty) => format!(" (result {})", types.reference(ty)),
+            None => String::new(),
+        };
+        writeln!(
+            imports,
+            "  (import \"node{index}\" (func $node{index}{param}{result}))"
+        )
+        .unwrap();
+
+        let signature = core_signature(&node.core_params(), node.core_result());
+        writeln!(
+            core_imports,
+            "    (import \"nodes\" \"node{index}\" (func $node{index} {signature}))"
+        )
+        .unwrap();
+        let arguments: String = (0..node.core_params().len())
+            .map(|param| format!(" (local.get {})", param))
+            .collect();
+        writeln!(
+            shims,
+            "    (func (export \"node{index}\") {signature} {arguments} (call_indirect {signature} (i32.const {index})))"
+        )
+        .unwrap();
+        writeln!(
+            lowered,
+            "  (core func $node{index}-lowered (canon lower (func $node{index}) (memory $graph \"memory\") (realloc (func $graph \"realloc\"))))"
+        )
+        .unwrap();
+        writeln!(
+            fixup_imports,
+            "    (import \"\" \"node{index}\" (func $node{index} {signature}))"
+        )
+        .unwrap();
+        write!(
+            fixup_exports,
+            " (export \"node{index}\" (func $node{index}-lowered))"
+        )
+        .unwrap();
+
+        // Read every input from the slot of the node its edge comes from.
+        let mut arguments = String::new();
+        for (port, ty) in &node.inputs {
+            let (source, offset) = input_source(graph, nodes, &positions, node, port, ty)
+                .map_err(|reason| ComposeError::UnsupportedNodeError(node.id.clone(), reason))?;
+            write!(
+                arguments,
+                " ({} (i32.const {}))",
+                ty.load().unwrap(),
+                source.slot + offset
+            )
+            .unwrap();
+        }
+        writeln!(body, "        ;; {}", node.id).unwrap();
+        match node.core_result() {
+            Some(core_type) => writeln!(
+                body,
+                "        ({}.store (i32.const {}) (call $node{index}{arguments}))",
+                core_type.name(),
+                node.slot
+            ),
+            None if node.returns_pointer() => writeln!(
+                body,
+                "        (call $node{index}{arguments} (i32.const {}))",
+                node.slot
+            ),
+            None => writeln!(body, "        (call $node{index}{arguments})"),
+        }
+        .unwrap();
+        if node.fallible {
+            writeln!(
+                body,
+                "        (br_if $failed (i32.load8_u (i32.const {})))",
+                node.slot
+            )
+            .unwrap();
+        }
+    }
+
+    let count = nodes.len();
+    let elements: String = (0..count).map(|index| format!(" $node{}", index)).collect();
+    let types = types.wat;
+    let dot_len = dot.len();
+    let dot = wat_string(dot);
+    Ok(format!(
+        r#"(component
+{types}{imports}  (core module $graph-module
+{core_imports}    (memory (export "memory") {pages})
+    (global $heap (mut i32) (i32.const {heap}))
+    (data (i32.const {dot_offset}) "{dot}")
+    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+      (local $ptr i32)
+      (local.set $ptr
+        (i32.and
+          (i32.add (global.get $heap) (i32.sub (local.get 2) (i32.const 1)))
+          (i32.sub (i32.const 0) (local.get 2))))
+      (global.set $heap (i32.add (local.get $ptr) (local.get 3)))
+      (if (i32.gt_u (global.get $heap) (i32.mul (memory.size) (i32.const {PAGE_SIZE})))
+        (then
+          (if (i32.eq
+                (memory.grow
+                  (i32.add
+                    (i32.div_u
+                      (i32.sub (global.get $heap) (i32.mul (memory.size) (i32.const {PAGE_SIZE})))
+                      (i32.const {PAGE_SIZE}))
+                    (i32.const 1)))
+                (i32.const -1))
+            (then unreachable))))
+      (local.get $ptr))
+    (func (export "compute") (result i32)
+      (global.set $heap (i32.const {heap}))
+      (block $failed
+{body}        (i32.store8 (i32.const 0) (i32.const 0))
+        (return (i32.const 0)))
+      ;; Err(computation-error)
+      (i32.store8 (i32.const 0) (i32.const 1))
+      (i32.store8 (i32.const 4) (i32.const 0))
+      (i32.const 0))
+    (func (export "print") (result i32)
+      (i32.store8 (i32.const 16) (i32.const 0))
+      (i32.store (i32.const 20) (i32.const {dot_offset}))
+      (i32.store (i32.const 24) (i32.const {dot_len}))
+      (i32.const 16)))
+  (core module $imports-module
+    (table (export "$imports") {count} funcref)
+{shims}  )
+  (core instance $imports (instantiate $imports-module))
+  (core instance $graph (instantiate $graph-module (with "nodes" (instance $imports))))
+{lowered}  (core module $fixup-module
+    (import "" "$imports" (table {count} funcref))
+{fixup_imports}    (elem (i32.const 0) func{elements}))
+  (core instance (instantiate $fixup-module
+    (with "" (instance (export "$imports" (table $imports "$imports")){fixup_exports}))))
+  (type $error (variant (case "computation-error") (case "resource-error") (case "other" string)))
+  (export $error' "error" (type $error))
+  (func $compute (result (result (error $error')))
+    (canon lift (core func $graph "compute") (memory $graph "memory")))
+  (func $print (result (result string (error $error')))
+    (canon lift (core func $graph "print") (memory $graph "memory")))
+  (instance $graph-instance
+    (export "error" (type $error'))
+    (export "compute" (func $compute))
+    (export "print" (func $print)))
+  (export "{GRAPH_INTERFACE}" (instance $graph-instance))
+)"#
+    ))
+}
+
+/// Finds the slot of the node and the offset of the output feeding the input `port`.
+fn input_source<'a>(
+    graph: &Graph,
+    nodes: &'a [ComposedNode],
+    positions: &HashMap<&str, usize>,
+    node: &ComposedNode,
+    port: &str,
+    ty: &ValType,
+) -> Result<(&'a ComposedNode, u32), String> {
+    let digraph = graph.graph();
+    let index = graph.node_map()[&node.id];
+    let edge = digraph
+        .edges_directed(index, Direction::Incoming)
+        .filter(|edge| {
+            let (_, to_port) = graph.edge_ports(edge.id());
+            match to_port {
+                Some(to_port) => to_port == port,
+                None => node.inputs.len() == 1,
+            }
+        })
+        .last()
+        .ok_or_else(|| format!("input port <{}> is not connected", port))?;
+
+    let source = &nodes[positions[digraph[edge.source()].id.as_str()]];
+    let (from_port, _) = graph.edge_ports(edge.id());
+    let output = match from_port {
+        Some(from_port) => source
+            .outputs
+            .iter()
+            .find(|(name, _, _)| *name == from_port),
+        None if source.outputs.len() == 1 => source.outputs.first(),
+        None => None,
+    };
+    let (name, output_ty, offset) = output.ok_or_else(|| {
+        format!(
+            "cannot resolve the output port of <{}> feeding input port <{}>",
+            source.id, port
+        )
+    })?;
+    if output_ty != ty {
+        return Err(format!(
+            "output port <{}> of <{}> and input port <{}> have different types",
+            name, source.id, port
+        ));
+    }
+    Ok((source, *offset))
+}
+
+/// Generates a component exporting `cillio:node/host` whose `get-state` returns `state`.
+fn state_provider_wat(state: Option<&[u8]>) -> String {
+    let (is_some, state) = match state {
+        Some(state) => (1, state),
+        None => (0, &[][..]),
+    };
+    let pages = (16 + state.len() as u32) / PAGE_SIZE + 1;
+    let len = state.len();
+    let state = wat_string(state);
+    format!(
+        r#"(component
+  (core module $m
+    (memory (export "memory") {pages})
+    (data (i32.const 16) "{state}")
+    (func (export "get-state") (result i32)
+      (i32.store8 (i32.const 0) (i32.const {is_some}))
+      (i32.store (i32.const 4) (i32.const 16))
+      (i32.store (i32.const 8) (i32.const {len}))
+      (i32.const 0)))
+  (core instance $i (instantiate $m))
+  (type $state (list u8))
+  (func $get-state (result (option $state))
+    (canon lift (core func $i "get-state") (memory $i "memory")))
+  (instance $host
+    (export "state" (type $state))
+    (export "get-state" (func $get-state)))
+  (export "{HOST_INTERFACE}" (instance $host))
+)"#
+    )
+}
+
+/// Escapes bytes for a WAT string literal.
+fn wat_string(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(escaped, "\\{:02x}", byte).unwrap(),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => write!(escaped, "\\{:02x}", byte).unwrap(),
+        }
+    }
+    escaped
+}
+
+fn core_signature(params: &[CoreType], result: Option<CoreType>) -> String {
+    let mut signature = String::from("(param");
+    for param in params {
+        write!(signature, " {}", param.name()).unwrap();
+    }
+    signature.push(')');
+    if let Some(result) = result {
+        write!(signature, " (result {})", result.name()).unwrap();
+    }
+    signature
+}
+
+/// Writes the component types the orchestrator imports its node functions with.
+///
+/// Records, variants, enums and flags must be named to be used in imports, so each of them
+/// is imported as a type equal to its definition.
+#[derive(Default)]
+struct TypeWriter {
+    wat: String,
+    count: usize,
+    prefix: String,
+}
+
+impl TypeWriter {
+    fn reference(&mut self, ty: &ValType) -> String {
+        match ty {
+            ValType::Primitive(primitive) => primitive_name(*primitive).to_string(),
+            ValType::List(element) => {
+                let element = self.reference(element);
+                self.define(format!("(list {})", element), false)
+            }
+            ValType::Tuple(elements) => {
+                let elements: Vec<_> = elements.iter().map(|ty| self.reference(ty)).collect();
+                self.define(format!("(tuple {})", elements.join(" ")), false)
+            }
+            ValType::Option(some) => {
+                let some = self.reference(some);
+                self.define(format!("(option {})", some), false)
+            }
+            ValType::Result(ok, err) => {
+                let mut definition = String::from("(result");
+                if let Some(ok) = ok {
+                    write!(definition, " {}", self.reference(ok)).unwrap();
+                }
+                if let Some(err) = err {
+                    write!(definition, " (error {})", self.reference(err)).unwrap();
+                }
+                definition.push(')');
+                self.define(definition, false)
+            }
+            ValType::Record(fields) => {
+                let mut definition = String::from("(record");
+                for (name, ty) in fields {
+                    write!(definition, " (field {:?} {})", name, self.reference(ty)).unwrap();
+                }
+                definition.push(')');
+                self.define(definition, true)
+            }
+            ValType::Variant(cases) => {
+                let mut definition = String::from("(variant");
+                for (name, ty) in cases {
+                    match ty {
+                        Some(ty) => {
+                            write!(definition, " (case {:?} {})", name, self.reference(ty))
+                        }
+                        None => write!(definition, " (case {:?})", name),
+                    }
+                    .unwrap();
+                }
+                definition.push(')');
+                self.define(definition, true)
+            }
+            ValType::Enum(names) | ValType::Flags(names) => {
+                let kind = if matches!(ty, ValType::Enum(_)) {
+                    "enum"
+                } else {
+                    "flags"
+                };
+                let names: Vec<_> = names.iter().map(|name| format!("{:?}", name)).collect();
+                self.define(format!("({} {})", kind, names.join(" ")), true)
+            }
+        }
+    }
+
+    fn define(&mut self, definition: String, named: bool) -> String {
+        let index = self.count;
+        self.count += 1;
+        writeln!(self.wat, "  (type $t{} {})", index, definition).unwrap();
+        if !named {
+            return format!("$t{}", index);
+        }
+        writeln!(
+            self.wat,
+            "  (import \"{}-type{}\" (type $t{}' (eq $t{})))",
+            self.prefix, index, index, index
+        )
+        .unwrap();
+        format!("$t{}'", index)
+    }
+}
+
+fn primitive_name(primitive: PrimitiveValType) -> &'static str {
+    match primitive {
+        PrimitiveValType::Bool => "bool",
+        PrimitiveValType::S8 => "s8",
+        PrimitiveValType::U8 => "u8",
+        PrimitiveValType::S16 => "s16",
+        PrimitiveValType::U16 => "u16",
+        PrimitiveValType::S32 => "s32",
+        PrimitiveValType::U32 => "u32",
+        PrimitiveValType::S64 => "s64",
+        PrimitiveValType::U64 => "u64",
+        PrimitiveValType::F32 => "f32",
+        PrimitiveValType::F64 => "f64",
+        PrimitiveValType::Char => "char",
+        PrimitiveValType::String => "string",
+        PrimitiveValType::ErrorContext => "error-context",
+    }
+}
+
+/// A component value type, as far as the orchestrator needs to pass it around.
+#[derive(Debug, Clone, PartialEq)]
+enum ValType {
+    Primitive(PrimitiveValType),
+    Record(Vec<(String, ValType)>),
+    Variant(Vec<(String, Option<ValType>)>),
+    List(Box<ValType>),
+    Tuple(Vec<ValType>),
+    Flags(Vec<String>),
+    Enum(Vec<String>),
+    Option(Box<ValType>),
+    Result(Option<Box<ValType>>, Option<Box<ValType>>),
+}
+
+fn val_type(types: TypesRef, ty: &ComponentValType) -> Result<ValType, String> {
+    let id = match ty {
+        ComponentValType::Primitive(PrimitiveValType::ErrorContext) => {
+            return Err("error contexts are not supported".to_string())
+        }
+        ComponentValType::Primitive(primitive) => return Ok(ValType::Primitive(*primitive)),
+        ComponentValType::Type(id) => *id,
+    };
+    let boxed = |ty: &ComponentValType| val_type(types, ty).map(Box::new);
+    Ok(match &types[id] {
+        ComponentDefinedType::Primitive(PrimitiveValType::ErrorContext) => {
+            return Err("error contexts are not supported".to_string())
+        }
+        ComponentDefinedType::Primitive(primitive) => ValType::Primitive(*primitive),
+        ComponentDefinedType::Record(record) => ValType::Record(
+            record
+                .fields
+                .iter()
+                .map(|(name, ty)| Ok((name.to_string(), val_type(types, ty)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        ComponentDefinedType::Variant(variant) => ValType::Variant(
+            variant
+                .cases
+                .iter()
+                .map(|(name, case)| {
+                    let ty = case.ty.as_ref().map(|ty| val_type(types, ty)).transpose()?;
+                    Ok((name.to_string(), ty))
+                })
+                .collect::<Result<_, String>>()?,
+        ),
+        ComponentDefinedType::List(element) => ValType::List(boxed(element)?),
+        ComponentDefinedType::Tuple(tuple) => ValType::Tuple(
+            tuple
+                .types
+                .iter()
+                .map(|ty| val_type(types, ty))
+                .collect::<Result<_, String>>()?,
+        ),
+        ComponentDefinedType::Flags(names) => {
+            ValType::Flags(names.iter().map(|name| name.to_string()).collect())
+        }
+        ComponentDefinedType::Enum(names) => {
+            ValType::Enum(names.iter().map(|name| name.to_string()).collect())
+        }
+        ComponentDefinedType::Option(some) => ValType::Option(boxed(some)?),
+        ComponentDefinedType::Result { ok, err } => ValType::Result(
+            ok.as_ref().map(boxed).transpose()?,
+            err.as_ref().map(boxed).transpose()?,
+        ),
+        _ => return Err("resources, futures and streams are not supported".to_string()),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CoreType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl CoreType {
+    fn name(self) -> &'static str {
+        match self {
+            CoreType::I32 => "i32",
+            CoreType::I64 => "i64",
+            CoreType::F32 => "f32",
+            CoreType::F64 => "f64",
+        }
+    }
+
+    /// The core type able to hold both types, for flattening variant payloads.
+    fn join(self, other: CoreType) -> CoreType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (CoreType::I32, CoreType::F32) | (CoreType::F32, CoreType::I32) => CoreType::I32,
+            _ => CoreType::I64,
+        }
+    }
+}
+
+// Layout and flattening follow the canonical ABI of the component model.
+impl ValType {
+    fn size_align(&self) -> (u32, u32) {
+        match self {
+            ValType::Primitive(primitive) => match primitive {
+                PrimitiveValType::Bool | PrimitiveValType::S8 | PrimitiveValType::U8 => (1, 1),
+                PrimitiveValType::S16 | PrimitiveValType::U16 => (2, 2),
+                PrimitiveValType::S64 | PrimitiveValType::U64 | PrimitiveValType::F64 => (8, 8),
+                PrimitiveValType::String => (8, 4),
+                _ => (4, 4),
+            },
+            ValType::List(_) => (8, 4),
+            ValType::Record(fields) => tuple_size_align(fields.iter().map(|(_, ty)| ty)),
+            ValType::Tuple(elements) => tuple_size_align(elements),
+            ValType::Flags(names) => match names.len() {
+                0 => (0, 1),
+                1..=8 => (1, 1),
+                9..=16 => (2, 2),
+                n => (4 * n.div_ceil(32) as u32, 4),
+            },
+            _ => {
+                let cases = self.cases();
+                let (_, payload_align) = payload_size_align(&cases);
+                let align = discriminant_size(cases.len()).max(payload_align);
+                let size = payload_offset(&cases) + payload_size_align(&cases).0;
+                (align_to(size, align), align)
+            }
+        }
+    }
+
+    fn flat(&self) -> Vec<CoreType> {
+        match self {
+            ValType::Primitive(primitive) => match primitive {
+                PrimitiveValType::S64 | PrimitiveValType::U64 => vec![CoreType::I64],
+                PrimitiveValType::F32 => vec![CoreType::F32],
+                PrimitiveValType::F64 => vec![CoreType::F64],
+                PrimitiveValType::String => vec![CoreType::I32, CoreType::I32],
+                _ => vec![CoreType::I32],
+            },
+            ValType::List(_) => vec![CoreType::I32, CoreType::I32],
+            ValType::Record(fields) => fields.iter().flat_map(|(_, ty)| ty.flat()).collect(),
+            ValType::Tuple(elements) => elements.iter().flat_map(ValType::flat).collect(),
+            ValType::Flags(names) => vec![CoreType::I32; names.len().div_ceil(32)],
+            _ => {
+                let mut flat: Vec<CoreType> = Vec::new();
+                for case in self.cases().into_iter().flatten() {
+                    for (index, core_type) in case.flat().into_iter().enumerate() {
+                        match flat.get_mut(index) {
+                            Some(joined) => *joined = joined.join(core_type),
+                            None => flat.push(core_type),
+                        }
+                    }
+                }
+                flat.insert(0, CoreType::I32);
+                flat
+            }
+        }
+    }
+
+    /// Payloads of the cases of a variant, option, result or enum.
+    fn cases(&self) -> Vec<Option<&ValType>> {
+        match self {
+            ValType::Variant(cases) => cases.iter().map(|(_, ty)| ty.as_ref()).collect(),
+            ValType::Enum(names) => vec![None; names.len()],
+            ValType::Option(some) => vec![None, Some(some)],
+            ValType::Result(ok, err) => vec![ok.as_deref(), err.as_deref()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Instruction loading a value of this scalar type from memory.
+    fn load(&self) -> Option<&'static str> {
+        match self {
+            ValType::Primitive(primitive) => Some(match primitive {
+                PrimitiveValType::Bool | PrimitiveValType::U8 => "i32.load8_u",
+                PrimitiveValType::S8 => "i32.load8_s",
+                PrimitiveValType::U16 => "i32.load16_u",
+                PrimitiveValType::S16 => "i32.load16_s",
+                PrimitiveValType::S32 | PrimitiveValType::U32 | PrimitiveValType::Char => {
+                    "i32.load"
+                }
+                PrimitiveValType::S64 | PrimitiveValType::U64 => "i64.load",
+                PrimitiveValType::F32 => "f32.load",
+                PrimitiveValType::F64 => "f64.load",
+                _ => return None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn align_to(offset: u32, align: u32) -> u32 {
+    offset.div_ceil(align) * align
+}
+
+fn tuple_size_align<'a>(elements: impl IntoIterator<Item = &'a ValType>) -> (u32, u32) {
+    let mut size = 0;
+    let mut align = 1;
+    for element in elements {
+        let (element_size, element_align) = element.size_align();
+        size = align_to(size, element_align) + element_size;
+        align = align.max(element_align);
+    }
+    (align_to(size, align), align)
+}
+
+/// Offsets of the fields of a record.
+fn record_layout(fields: &[(String, ValType)]) -> Vec<u32> {
+    let mut offset = 0;
+    fields
+        .iter()
+        .map(|(_, ty)| {
+            let (size, align) = ty.size_align();
+            let field_offset = align_to(offset, align);
+            offset = field_offset + size;
+            field_offset
+        })
+        .collect()
+}
+
+fn discriminant_size(cases: usize) -> u32 {
+    match cases {
+        0..=256 => 1,
+        257..=65536 => 2,
+        _ => 4,
+    }
+}
+
+fn payload_size_align(cases: &[Option<&ValType>]) -> (u32, u32) {
+    cases
+        .iter()
+        .flatten()
+        .map(|ty| ty.size_align())
+        .fold((0, 1), |(size, align), (case_size, case_align)| {
+            (size.max(case_size), align.max(case_align))
+        })
+}
+
+fn payload_offset(cases: &[Option<&ValType>]) -> u32 {
+    align_to(discriminant_size(cases.len()), payload_size_align(cases).1)
+}
+
+#[cfg(test)]
+mod tests {
+    use cillio_config::GraphConfig;
+    use serde_json::json;
+    use wasmtime::component::{Linker, Val};
+    use wasmtime::{Engine, Store};
+
+    use super::*;
+    use crate::test_utils::INCREMENT;
+
+    /// Emits the length of its state.
+    const STATE_LENGTH: &str = r#"(component
+        (import "cillio:node/host@0.1.0" (instance $host
+            (type $s (list u8))
+            (export "state" (type $st (eq $s)))
+            (type $o (option $st))
+            (export "get-state" (func (result $o)))))
+        (core module $m
+            (import "host" "get-state" (func $get-state (param i32)))
+            (memory (export "memory") 1)
+            (global $heap (mut i32) (i32.const 1024))
+            (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                (global.get $heap)
+                (global.set $heap (i32.add (global.get $heap) (local.get 3))))
+            (func (export "process") (result i32)
+                (call $get-state (i32.const 16))
+                (i32.load (i32.const 24))))
+        (core module $shim
+            (table (export "$imports") 1 funcref)
+            (func (export "get-state") (param i32)
+                (call_indirect (param i32) (local.get 0) (i32.const 0))))
+        (core instance $s (instantiate $shim))
+        (core instance $i (instantiate $m (with "host" (instance $s))))
+        (core func $get-state (canon lower (func $host "get-state")
+            (memory $i "memory") (realloc (func $i "realloc"))))
+        (core module $fixup
+            (import "" "$imports" (table 1 funcref))
+            (import "" "get-state" (func $f (param i32)))
+            (elem (i32.const 0) func $f))
+        (core instance (instantiate $fixup (with "" (instance
+            (export "$imports" (table $s "$imports"))
+            (export "get-state" (func $get-state))))))
+        (type $r (record (field "value" u32)))
+        (export $r' "r" (type $r))
+        (func $f (result $r') (canon lift (core func $i "process")))
+        (export "process" (func $f))
+    )"#;
+
+    /// Fails unless its input is 13.
+    const EXPECT_13: &str = r#"(component
+        (core module $m
+            (memory (export "memory") 1)
+            (func (export "process") (param i32) (result i32)
+                (i32.store8 (i32.const 0) (i32.ne (local.get 0) (i32.const 13)))
+                (i32.store8 (i32.const 4) (i32.const 0))
+                (i32.store (i32.const 8) (i32.const 64))
+                (i32.store (i32.const 12) (i32.const 0))
+                (i32.const 0)))
+        (core instance $i (instantiate $m))
+        (type $r (record (field "value" u32)))
+        (export $r' "r" (type $r))
+        (type $e (variant (case "process-error" string)))
+        (export $e' "error" (type $e))
+        (func $f (param "in" $r') (result (result (error $e')))
+            (canon lift (core func $i "process") (memory $i "memory")))
+        (export "process" (func $f))
+    )"#;
+
+    fn compose(state: serde_json::Value) -> Vec<u8> {
+        let config: GraphConfig = serde_json::from_value(json!({
+            "node_implementations": {
+                "state-length": {"output": {"value": "int"}, "wasm": "state-length"},
+                "increment": {
+                    "input": {"value": "int"},
+                    "output": {"value": "int"},
+                    "wasm": "increment"
+                },
+                "expect-13": {"input": {"value": "int"}, "wasm": "expect-13"}
+            },
+            "nodes": {
+                "a": {"type": "state-length", "state": state},
+                "b": {"type": "increment"},
+                "c": {"type": "expect-13"}
+            },
+            "edges": [{"from": "a", "to": "b"}, {"from": "b", "to": "c"}]
+        }))
+        .unwrap();
+        let components = HashMap::from([
+            ("state-length".to_string(), STATE_LENGTH.as_bytes().to_vec()),
+            ("increment".to_string(), INCREMENT.as_bytes().to_vec()),
+            ("expect-13".to_string(), EXPECT_13.as_bytes().to_vec()),
+        ]);
+        compose_graph(&Graph::new(&config).unwrap(), &components).unwrap()
+    }
+
+    fn call(composed: &[u8], function: &str) -> Val {
+        let engine = Engine::default();
+        let component = wasmtime::component::Component::new(&engine, composed).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine)
+            .instantiate(&mut store, &component)
+            .unwrap();
+        let func = instance
+            .exports(&mut store)
+            .instance(GRAPH_INTERFACE)
+            .unwrap()
+            .func(function)
+            .unwrap();
+        let mut results = [Val::Bool(false)];
+        func.call(&mut store, &[], &mut results).unwrap();
+        results[0].clone()
+    }
+
+    #[test]
+    fn test_compose_graph() {
+        // `{"value":1}` is 11 bytes long, incremented to 12 and not accepted by `c`.
+        let composed = compose(json!({"value": 1}));
+        assert_eq!(
+            call(&composed, "compute"),
+            Val::Result(Err(Some(Box::new(Val::Variant(
+                "computation-error".to_string(),
+                None
+            )))))
+        );
+
+        let composed = compose(json!({"value": 10}));
+        assert_eq!(call(&composed, "compute"), Val::Result(Ok(None)));
+        let Val::Result(Ok(Some(dot))) = call(&composed, "print") else {
+            panic!("print failed");
+        };
+        assert!(matches!(*dot, Val::String(dot) if dot.contains("digraph")));
+    }
+
+    #[test]
+    fn test_compose_requires_connected_inputs() {
+        let config: GraphConfig = serde_json::from_value(json!({
+            "node_implementations": {"increment": {"wasm": "increment"}},
+            "nodes": {"b": {"type": "increment"}},
+            "edges": []
+        }))
+        .unwrap();
+        let components = HashMap::from([("increment".to_string(), INCREMENT.as_bytes().to_vec())]);
+        let err = compose_graph(&Graph::new(&config).unwrap(), &components).unwrap_err();
+        assert!(matches!(err, ComposeError::UnsupportedNodeError(node, _) if node == "b"));
+    }
+}
@@ -0,0 +1,920 @@
+//! Type-directed conversion between JSON values and component model values.
+//!
+//! The JSON representation follows the shape of the component type:
+//!
+//! - records are objects and tuples, lists and flags are arrays
+//! - `option` values are `null` or the inner value
+//! - `result` values are `{"ok": value}` or `{"err": value}`
+//! - variants are `{"case": payload}`, or just `"case"` for cases without payload
+//! - enums and chars are strings
+
+use cillio_config::PortType;
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+use wasmtime::component::{Type, Val};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{path}: {message}")]
+pub struct ConversionError {
+    /// Location of the offending value, e.g. `inputs.a` or `state.values[2]`.
+    pub path: String,
+    pub message: String,
+}
+
+impl ConversionError {
+    pub(crate) fn new(path: &str, message: String) -> Self {
+        Self {
+            path: path.to_string(),
+            message,
+        }
+    }
+
+    fn expected(path: &str, ty: &Type, value: &Value) -> Self {
+        Self::new(
+            path,
+            format!("expected {}, got {}", type_name(ty), json_type_name(value)),
+        )
+    }
+}
+
+/// Returns the WIT name of a component type.
+pub fn type_name(ty: &Type) -> &'static str {
+    match ty {
+        Type::Bool => "bool",
+        Type::S8 => "s8",
+        Type::U8 => "u8",
+        Type::S16 => "s16",
+        Type::U16 => "u16",
+        Type::S32 => "s32",
+        Type::U32 => "u32",
+        Type::S64 => "s64",
+        Type::U64 => "u64",
+        Type::Float32 => "f32",
+        Type::Float64 => "f64",
+        Type::Char => "char",
+        Type::String => "string",
+        Type::List(_) => "list",
+        Type::Record(_) => "record",
+        Type::Tuple(_) => "tuple",
+        Type::Variant(_) => "variant",
+        Type::Enum(_) => "enum",
+        Type::Option(_) => "option",
+        Type::Result(_) => "result",
+        Type::Flags(_) => "flags",
+        Type::Own(_) => "own",
+        Type::Borrow(_) => "borrow",
+    }
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn child_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+fn json_integer<T: TryFrom<i64> + TryFrom<u64>>(
+    value: &Value,
+    ty: &Type,
+    path: &str,
+) -> Result<T, ConversionError> {
+    let converted = match value {
+        Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (_, Some(unsigned)) => <T as TryFrom<u64>>::try_from(unsigned).ok(),
+            (Some(signed), None) => <T as TryFrom<i64>>::try_from(signed).ok(),
+            (None, None) => {
+                return Err(ConversionError::new(
+                    path,
+                    format!(
+                        "expected {}, got fractional number {}",
+                        type_name(ty),
+                        number
+                    ),
+                ))
+            }
+        },
+        _ => return Err(ConversionError::expected(path, ty, value)),
+    };
+    converted.ok_or_else(|| {
+        ConversionError::new(
+            path,
+            format!("{} is out of range for {}", value, type_name(ty)),
+        )
+    })
+}
+
+fn json_float(value: &Value, ty: &Type, path: &str) -> Result<f64, ConversionError> {
+    value
+        .as_f64()
+        .ok_or_else(|| ConversionError::expected(path, ty, value))
+}
+
+/// Converts a JSON value into a component value of type `ty`.
+///
+/// `path` names the root of the value in error messages, e.g. `inputs`.
+pub fn json_to_val(value: &Value, ty: &Type, path: &str) -> Result<Val, ConversionError> {
+    let val = match ty {
+        Type::Bool => Val::Bool(
+            value
+                .as_bool()
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?,
+        ),
+        Type::S8 => Val::S8(json_integer(value, ty, path)?),
+        Type::U8 => Val::U8(json_integer(value, ty, path)?),
+        Type::S16 => Val::S16(json_integer(value, ty, path)?),
+        Type::U16 => Val::U16(json_integer(value, ty, path)?),
+        Type::S32 => Val::S32(json_integer(value, ty, path)?),
+        Type::U32 => Val::U32(json_integer(value, ty, path)?),
+        Type::S64 => Val::S64(json_integer(value, ty, path)?),
+        Type::U64 => Val::U64(json_integer(value, ty, path)?),
+        Type::Float32 => {
+            let float = json_float(value, ty, path)?;
+            if float.abs() > f64::from(f32::MAX) {
+                return Err(ConversionError::new(
+                    path,
+                    format!("{} is out of range for {}", value, type_name(ty)),
+                ));
+            }
+            Val::Float32(float as f32)
+        }
+        Type::Float64 => Val::Float64(json_float(value, ty, path)?),
+        Type::Char => {
+            let string = value
+                .as_str()
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?;
+            let mut chars = string.chars();
+            match (chars.next(), chars.next()) {
+                (Some(char), None) => Val::Char(char),
+                _ => {
+                    return Err(ConversionError::new(
+                        path,
+                        format!(
+                            "expected char, got string of length {}",
+                            string.chars().count()
+                        ),
+                    ))
+                }
+            }
+        }
+        Type::String => Val::String(
+            value
+                .as_str()
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?
+                .to_string(),
+        ),
+        Type::List(list) => {
+            let items = value
+                .as_array()
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?;
+            let item_type = list.ty();
+            Val::List(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| json_to_val(item, &item_type, &index_path(path, index)))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        Type::Tuple(tuple) => {
+            let items = value
+                .as_array()
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?;
+            let types: Vec<_> = tuple.types().collect();
+            if items.len() != types.len() {
+                return Err(ConversionError::new(
+                    path,
+                    format!(
+                        "expected tuple of {} values, got {}",
+                        types.len(),
+                        items.len()
+                    ),
+                ));
+            }
+            Val::Tuple(
+                items
+                    .iter()
+                    .zip(types.iter())
+                    .enumerate()
+                    .map(|(index, (item, item_type))| {
+                        json_to_val(item, item_type, &index_path(path, index))
+                    })
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        Type::Record(record) => {
+            let object = value
+                .as_object()
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?;
+            let mut fields = Vec::new();
+            for field in record.fields() {
+                let field_path = child_path(path, field.name);
+                let field_value = match (object.get(field.name), &field.ty) {
+                    (Some(field_value), field_type) => {
+                        json_to_val(field_value, field_type, &field_path)?
+                    }
+                    (None, Type::Option(_)) => Val::Option(None),
+                    (None, _) => {
+                        return Err(ConversionError::new(
+                            &field_path,
+                            "missing field".to_string(),
+                        ))
+                    }
+                };
+                fields.push((field.name.to_string(), field_value));
+            }
+            if let Some(unknown) = object
+                .keys()
+                .find(|key| !fields.iter().any(|(name, _)| name == *key))
+            {
+                return Err(ConversionError::new(
+                    &child_path(path, unknown),
+                    "unknown field".to_string(),
+                ));
+            }
+            Val::Record(fields)
+        }
+        Type::Variant(variant) => {
+            let (case_name, payload) = match value {
+                Value::String(case_name) => (case_name.as_str(), None),
+                Value::Object(object) if object.len() == 1 => {
+                    let (case_name, payload) = object.iter().next().unwrap();
+                    (case_name.as_str(), Some(payload))
+                }
+                _ => return Err(ConversionError::expected(path, ty, value)),
+            };
+            let case = variant
+                .cases()
+                .find(|case| case.name == case_name)
+                .ok_or_else(|| {
+                    ConversionError::new(path, format!("unknown variant case <{}>", case_name))
+                })?;
+            let case_path = child_path(path, case_name);
+            let payload = match (case.ty, payload) {
+                (Some(case_type), Some(payload)) => {
+                    Some(Box::new(json_to_val(payload, &case_type, &case_path)?))
+                }
+                (None, None) | (None, Some(Value::Null)) => None,
+                (Some(_), None) => {
+                    return Err(ConversionError::new(
+                        &case_path,
+                        "missing variant payload".to_string(),
+                    ))
+                }
+                (None, Some(_)) => {
+                    return Err(ConversionError::new(
+                        &case_path,
+                        "variant case takes no payload".to_string(),
+                    ))
+                }
+            };
+            Val::Variant(case_name.to_string(), payload)
+        }
+        Type::Enum(enum_type) => {
+            let name = value
+                .as_str()
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?;
+            if !enum_type.names().any(|case| case == name) {
+                return Err(ConversionError::new(
+                    path,
+                    format!("unknown enum case <{}>", name),
+                ));
+            }
+            Val::Enum(name.to_string())
+        }
+        Type::Option(option) => match value {
+            Value::Null => Val::Option(None),
+            value => Val::Option(Some(Box::new(json_to_val(value, &option.ty(), path)?))),
+        },
+        Type::Result(result) => {
+            let object = value
+                .as_object()
+                .filter(|object| object.len() == 1)
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?;
+            let (case, payload) = object.iter().next().unwrap();
+            let (payload_type, is_ok) = match case.as_str() {
+                "ok" => (result.ok(), true),
+                "err" => (result.err(), false),
+                other => {
+                    return Err(ConversionError::new(
+                        path,
+                        format!("expected <ok> or <err>, got <{}>", other),
+                    ))
+                }
+            };
+            let payload = match payload_type {
+                Some(payload_type) => Some(Box::new(json_to_val(
+                    payload,
+                    &payload_type,
+                    &child_path(path, case),
+                )?)),
+                None => None,
+            };
+            Val::Result(if is_ok { Ok(payload) } else { Err(payload) })
+        }
+        Type::Flags(flags) => {
+            let items = value
+                .as_array()
+                .ok_or_else(|| ConversionError::expected(path, ty, value))?;
+            let mut names = Vec::new();
+            for (index, item) in items.iter().enumerate() {
+                let name = item.as_str().ok_or_else(|| {
+                    ConversionError::new(
+                        &index_path(path, index),
+                        format!("expected flag name, got {}", json_type_name(item)),
+                    )
+                })?;
+                if !flags.names().any(|flag| flag == name) {
+                    return Err(ConversionError::new(
+                        &index_path(path, index),
+                        format!("unknown flag <{}>", name),
+                    ));
+                }
+                names.push(name.to_string());
+            }
+            Val::Flags(names)
+        }
+        Type::Own(_) | Type::Borrow(_) => {
+            return Err(ConversionError::new(
+                path,
+                format!("{} resources cannot be converted from JSON", type_name(ty)),
+            ))
+        }
+    };
+    Ok(val)
+}
+
+fn float_to_json(value: f64, path: &str) -> Result<Value, ConversionError> {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or_else(|| ConversionError::new(path, format!("{} has no JSON representation", value)))
+}
+
+fn mismatch(path: &str, ty: &Type, val: &Val) -> ConversionError {
+    ConversionError::new(path, format!("expected {}, got {:?}", type_name(ty), val))
+}
+
+/// Converts a component value of type `ty` into JSON.
+pub fn val_to_json(val: &Val, ty: &Type, path: &str) -> Result<Value, ConversionError> {
+    let value = match (val, ty) {
+        (Val::Bool(value), Type::Bool) => Value::Bool(*value),
+        (Val::S8(value), Type::S8) => Value::from(*value),
+        (Val::U8(value), Type::U8) => Value::from(*value),
+        (Val::S16(value), Type::S16) => Value::from(*value),
+        (Val::U16(value), Type::U16) => Value::from(*value),
+        (Val::S32(value), Type::S32) => Value::from(*value),
+        (Val::U32(value), Type::U32) => Value::from(*value),
+        (Val::S64(value), Type::S64) => Value::from(*value),
+        (Val::U64(value), Type::U64) => Value::from(*value),
+        (Val::Float32(value), Type::Float32) => float_to_json(f64::from(*value), path)?,
+        (Val::Float64(value), Type::Float64) => float_to_json(*value, path)?,
+        (Val::Char(value), Type::Char) => Value::String(value.to_string()),
+        (Val::String(value), Type::String) => Value::String(value.clone()),
+        (Val::List(items), Type::List(list)) => {
+            let item_type = list.ty();
+            Value::Array(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| val_to_json(item, &item_type, &index_path(path, index)))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        (Val::Tuple(items), Type::Tuple(tuple)) => Value::Array(
+            items
+                .iter()
+                .zip(tuple.types())
+                .enumerate()
+                .map(|(index, (item, item_type))| {
+                    val_to_json(item, &item_type, &index_path(path, index))
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        (Val::Record(fields), Type::Record(record)) => {
+            if fields.len() != record.fields().len() {
+                return Err(ConversionError::new(
+                    path,
+                    format!(
+                        "expected record of {} fields, got {}",
+                        record.fields().len(),
+                        fields.len()
+                    ),
+                ));
+            }
+            let mut object = Map::new();
+            for ((name, field_value), field) in fields.iter().zip(record.fields()) {
+                let field_path = child_path(path, name);
+                if *name != field.name {
+                    return Err(ConversionError::new(
+                        &field_path,
+                        format!("expected field <{}>", field.name),
+                    ));
+                }
+                object.insert(
+                    name.clone(),
+                    val_to_json(field_value, &field.ty, &field_path)?,
+                );
+            }
+            Value::Object(object)
+        }
+        (Val::Variant(case_name, payload), Type::Variant(variant)) => {
+            let case = variant
+                .cases()
+                .find(|case| case.name == case_name)
+                .ok_or_else(|| {
+                    ConversionError::new(path, format!("unknown variant case <{}>", case_name))
+                })?;
+            let case_path = child_path(path, case_name);
+            match (payload, case.ty) {
+                (Some(payload), Some(case_type)) => {
+                    let mut object = Map::new();
+                    object.insert(
+                        case_name.clone(),
+                        val_to_json(payload, &case_type, &case_path)?,
+                    );
+                    Value::Object(object)
+                }
+                (None, None) => Value::String(case_name.clone()),
+                (None, Some(_)) => {
+                    return Err(ConversionError::new(
+                        &case_path,
+                        "missing variant payload".to_string(),
+                    ))
+                }
+                (Some(_), None) => {
+                    return Err(ConversionError::new(
+                        &case_path,
+                        "variant case takes no payload".to_string(),
+                    ))
+                }
+            }
+        }
+        (Val::Enum(name), Type::Enum(_)) => Value::String(name.clone()),
+        (Val::Option(None), Type::Option(_)) => Value::Null,
+        (Val::Option(Some(inner)), Type::Option(option)) => val_to_json(inner, &option.ty(), path)?,
+        (Val::Result(result), Type::Result(result_type)) => {
+            let (case, payload, payload_type) = match result {
+                Ok(payload) => ("ok", payload, result_type.ok()),
+                Err(payload) => ("err", payload, result_type.err()),
+            };
+            let case_path = child_path(path, case);
+            let payload = match (payload, payload_type) {
+                (Some(payload), Some(payload_type)) => {
+                    val_to_json(payload, &payload_type, &case_path)?
+                }
+                (None, None) => Value::Null,
+                (None, Some(_)) => {
+                    return Err(ConversionError::new(
+                        &case_path,
+                        "missing result payload".to_string(),
+                    ))
+                }
+                (Some(_), None) => {
+                    return Err(ConversionError::new(
+                        &case_path,
+                        "result case takes no payload".to_string(),
+                    ))
+                }
+            };
+            let mut object = Map::new();
+            object.insert(case.to_string(), payload);
+            Value::Object(object)
+        }
+        (Val::Flags(names), Type::Flags(_)) => {
+            Value::Array(names.iter().cloned().map(Value::String).collect())
+        }
+        (val, ty) => return Err(mismatch(path, ty, val)),
+    };
+    Ok(value)
+}
+
+/// Converts a component value into JSON without type information.
+///
+/// Used where the type of a value is not at hand, e.g. for values routed between nodes.
+/// Variants and results use the same shape as [`val_to_json`], resources become `null`.
+pub fn val_to_json_untyped(val: &Val) -> Value {
+    match val {
+        Val::Bool(value) => Value::Bool(*value),
+        Val::S8(value) => Value::from(*value),
+        Val::U8(value) => Value::from(*value),
+        Val::S16(value) => Value::from(*value),
+        Val::U16(value) => Value::from(*value),
+        Val::S32(value) => Value::from(*value),
+        Val::U32(value) => Value::from(*value),
+        Val::S64(value) => Value::from(*value),
+        Val::U64(value) => Value::from(*value),
+        Val::Float32(value) => {
+            Number::from_f64(f64::from(*value)).map_or(Value::Null, Value::Number)
+        }
+        Val::Float64(value) => Number::from_f64(*value).map_or(Value::Null, Value::Number),
+        Val::Char(value) => Value::String(value.to_string()),
+        Val::String(value) => Value::String(value.clone()),
+        Val::List(items) | Val::Tuple(items) => {
+            Value::Array(items.iter().map(val_to_json_untyped).collect())
+        }
+        Val::Record(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), val_to_json_untyped(value)))
+                .collect(),
+        ),
+        Val::Variant(case_name, Some(payload)) => {
+            let mut object = Map::new();
+            object.insert(case_name.clone(), val_to_json_untyped(payload));
+            Value::Object(object)
+        }
+        Val::Variant(case_name, None) | Val::Enum(case_name) => Value::String(case_name.clone()),
+        Val::Option(value) => value
+            .as_ref()
+            .map_or(Value::Null, |value| val_to_json_untyped(value)),
+        Val::Result(result) => {
+            let (case, payload) = match result {
+                Ok(payload) => ("ok", payload),
+                Err(payload) => ("err", payload),
+            };
+            let mut object = Map::new();
+            object.insert(
+                case.to_string(),
+                payload
+                    .as_ref()
+                    .map_or(Value::Null, |payload| val_to_json_untyped(payload)),
+            );
+            Value::Object(object)
+        }
+        Val::Flags(names) => Value::Array(names.iter().cloned().map(Value::String).collect()),
+        Val::Resource(_) => Value::Null,
+    }
+}
+
+/// Converts a JSON value into the component value of a port type.
+///
+/// `int` ports hold `s64` and `float` ports `f64` values, and `any` ports the value of
+/// [`json_to_val_untyped`].
+pub fn json_to_port_val(value: &Value, ty: &PortType, path: &str) -> Result<Val, ConversionError> {
+    let expected = || {
+        ConversionError::new(
+            path,
+            format!("expected {}, got {}", ty, json_type_name(value)),
+        )
+    };
+    let val = match ty {
+        PortType::Any => json_to_val_untyped(value),
+        PortType::Bool => Val::Bool(value.as_bool().ok_or_else(expected)?),
+        PortType::Int => Val::S64(value.as_i64().ok_or_else(expected)?),
+        PortType::Float => Val::Float64(value.as_f64().ok_or_else(expected)?),
+        PortType::String => Val::String(value.as_str().ok_or_else(expected)?.to_string()),
+        PortType::List(item_type) => Val::List(
+            value
+                .as_array()
+                .ok_or_else(expected)?
+                .iter()
+                .enumerate()
+                .map(|(index, item)| json_to_port_val(item, item_type, &index_path(path, index)))
+                .collect::<Result<_, _>>()?,
+        ),
+        PortType::Option(inner) => match value {
+            Value::Null => Val::Option(None),
+            value => Val::Option(Some(Box::new(json_to_port_val(value, inner, path)?))),
+        },
+        PortType::Record(record) => {
+            let object = value.as_object().ok_or_else(expected)?;
+            let mut fields = Vec::new();
+            for (name, field_type) in record {
+                let field_path = child_path(path, name);
+                let field_value = match (object.get(name), field_type) {
+                    (Some(field_value), field_type) => {
+                        json_to_port_val(field_value, field_type, &field_path)?
+                    }
+                    (None, PortType::Option(_)) => Val::Option(None),
+                    (None, _) => {
+                        return Err(ConversionError::new(
+                            &field_path,
+                            "missing field".to_string(),
+                        ))
+                    }
+                };
+                fields.push((name.clone(), field_value));
+            }
+            if let Some(unknown) = object
+                .keys()
+                .find(|key| !fields.iter().any(|(name, _)| name == *key))
+            {
+                return Err(ConversionError::new(
+                    &child_path(path, unknown),
+                    "unknown field".to_string(),
+                ));
+            }
+            Val::Record(fields)
+        }
+    };
+    Ok(val)
+}
+
+/// Converts a JSON value into a component value without type information.
+///
+/// Integers become `s64` (or `u64` beyond its range) and other numbers `f64`, arrays
+/// become lists, objects records and `null` an empty option.
+pub fn json_to_val_untyped(value: &Value) -> Val {
+    match value {
+        Value::Null => Val::Option(None),
+        Value::Bool(value) => Val::Bool(*value),
+        Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(signed), _) => Val::S64(signed),
+            (None, Some(unsigned)) => Val::U64(unsigned),
+            (None, None) => Val::Float64(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(value) => Val::String(value.clone()),
+        Value::Array(items) => Val::List(items.iter().map(json_to_val_untyped).collect()),
+        Value::Object(object) => Val::Record(
+            object
+                .iter()
+                .map(|(name, value)| (name.clone(), json_to_val_untyped(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Converts a value routed into a port of type `ty` if its shape differs from the type,
+/// e.g. an `f64` produced by a native node fed into an `f32` port.
+pub fn coerce_val(val: Val, ty: &Type, path: &str) -> Result<Val, ConversionError> {
+    if has_type(&val, ty) {
+        Ok(val)
+    } else {
+        json_to_val(&val_to_json_untyped(&val), ty, path)
+    }
+}
+
+/// Whether a value has the shape of a type. Tuples, variants, enums, results, flags and
+/// resources are taken as is and left to the type check of the call.
+fn has_type(val: &Val, ty: &Type) -> bool {
+    match (val, ty) {
+        (Val::Bool(_), Type::Bool)
+        | (Val::S8(_), Type::S8)
+        | (Val::U8(_), Type::U8)
+        | (Val::S16(_), Type::S16)
+        | (Val::U16(_), Type::U16)
+        | (Val::S32(_), Type::S32)
+        | (Val::U32(_), Type::U32)
+        | (Val::S64(_), Type::S64)
+        | (Val::U64(_), Type::U64)
+        | (Val::Float32(_), Type::Float32)
+        | (Val::Float64(_), Type::Float64)
+        | (Val::Char(_), Type::Char)
+        | (Val::String(_), Type::String)
+        | (Val::Option(None), Type::Option(_)) => true,
+        (Val::Option(Some(value)), Type::Option(option)) => has_type(value, &option.ty()),
+        (Val::List(items), Type::List(list)) => {
+            let item_type = list.ty();
+            items.iter().all(|item| has_type(item, &item_type))
+        }
+        (Val::Record(fields), Type::Record(record)) => {
+            fields.len() == record.fields().len()
+                && record.fields().all(|field| {
+                    fields
+                        .iter()
+                        .any(|(name, value)| name == field.name && has_type(value, &field.ty))
+                })
+        }
+        (
+            Val::Tuple(_)
+            | Val::Variant(..)
+            | Val::Enum(_)
+            | Val::Result(_)
+            | Val::Flags(_)
+            | Val::Resource(_),
+            _,
+        ) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wasmtime::component::types::ComponentItem;
+    use wasmtime::component::Component;
+    use wasmtime::{Config, Engine};
+
+    /// Compiles a component exporting the type `r` and returns that type.
+    fn exported_type(wat: &str) -> Type {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let component = Component::new(&engine, wat).unwrap();
+        let component_type = component.component_type();
+        let (_, item) = component_type
+            .exports(&engine)
+            .find(|(name, _)| *name == "r")
+            .unwrap();
+        match item {
+            ComponentItem::Type(ty) => ty,
+            other => panic!("unexpected export: {:?}", other),
+        }
+    }
+
+    fn inputs_type() -> Type {
+        exported_type(
+            r#"(component
+                (type $e (enum "x" "y"))
+                (export $e' "e" (type $e))
+                (type $v (variant (case "none") (case "some" u8)))
+                (export $v' "v" (type $v))
+                (type $f (flags "a" "b"))
+                (export $f' "f" (type $f))
+                (type $r (record
+                    (field "a" f32)
+                    (field "b" (list string))
+                    (field "c" (option s8))
+                    (field "d" (result u32 (error string)))
+                    (field "e" $e')
+                    (field "v" $v')
+                    (field "f" $f')
+                    (field "t" (tuple char u64))))
+                (export "r" (type $r))
+            )"#,
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ty = inputs_type();
+        let value = json!({
+            "a": 1.5,
+            "b": ["x"],
+            "c": -3,
+            "d": {"ok": 3},
+            "e": "y",
+            "v": {"some": 4},
+            "f": ["b"],
+            "t": ["z", 5],
+        });
+        let val = json_to_val(&value, &ty, "inputs").unwrap();
+        assert_eq!(val_to_json(&val, &ty, "inputs").unwrap(), value);
+        assert_eq!(val_to_json_untyped(&val), value);
+    }
+
+    #[test]
+    fn test_missing_option_field_is_none() {
+        let ty = inputs_type();
+        let value = json!({
+            "a": 1,
+            "b": [],
+            "d": {"err": "failed"},
+            "e": "x",
+            "v": "none",
+            "f": [],
+            "t": ["z", 5],
+        });
+        let val = json_to_val(&value, &ty, "inputs").unwrap();
+        let converted = val_to_json(&val, &ty, "inputs").unwrap();
+        assert_eq!(converted["c"], Value::Null);
+        assert_eq!(converted["v"], json!("none"));
+    }
+
+    #[test]
+    fn test_error_paths() {
+        let ty = inputs_type();
+        let error = |value: Value| json_to_val(&value, &ty, "inputs").unwrap_err().to_string();
+
+        assert_eq!(
+            error(json!({"a": "1"})),
+            "inputs.a: expected f32, got string"
+        );
+        assert_eq!(
+            error(json!({"a": 1, "b": [1]})),
+            "inputs.b[0]: expected string, got number"
+        );
+        assert_eq!(
+            error(json!({"a": 1, "b": [], "c": 300})),
+            "inputs.c: 300 is out of range for s8"
+        );
+        assert_eq!(error(json!({"a": 1, "b": []})), "inputs.d: missing field");
+        assert_eq!(
+            error(json!({"a": 1, "b": [], "d": {"ok": 1}, "e": "z"})),
+            "inputs.e: unknown enum case <z>"
+        );
+        assert_eq!(
+            error(json!({
+                "a": 1, "b": [], "d": {"ok": 1}, "e": "x", "v": "none", "f": [], "t": ["日本", 5]
+            })),
+            "inputs.t[0]: expected char, got string of length 2"
+        );
+    }
+
+    #[test]
+    fn test_payload_mismatch_errors() {
+        let Type::Record(record) = inputs_type() else {
+            panic!("inputs type is not a record");
+        };
+        let field_type = |name: &str| record.fields().find(|field| field.name == name).unwrap().ty;
+        let variant = field_type("v");
+        let error = |val: Val, ty: &Type| val_to_json(&val, ty, "inputs").unwrap_err().to_string();
+
+        let none_with_payload = Val::Variant("none".to_string(), Some(Box::new(Val::U32(1))));
+        assert_eq!(
+            error(none_with_payload, &variant),
+            "inputs.none: variant case takes no payload"
+        );
+        assert_eq!(
+            error(Val::Variant("some".to_string(), None), &variant),
+            "inputs.some: missing variant payload"
+        );
+        assert_eq!(
+            error(Val::Result(Ok(None)), &field_type("d")),
+            "inputs.ok: missing result payload"
+        );
+
+        let ty = Type::Record(record.clone());
+        let value = json!({
+            "a": 1, "b": [], "d": {"ok": 1}, "e": "x", "v": "none", "f": [], "t": ["z", 5]
+        });
+        let fields = || match json_to_val(&value, &ty, "inputs").unwrap() {
+            Val::Record(fields) => fields,
+            other => panic!("unexpected value: {:?}", other),
+        };
+        let mut missing = fields();
+        missing.pop();
+        assert_eq!(
+            error(Val::Record(missing), &ty),
+            "inputs: expected record of 8 fields, got 7"
+        );
+        let mut swapped = fields();
+        swapped.swap(0, 1);
+        assert_eq!(
+            error(Val::Record(swapped), &ty),
+            "inputs.b: expected field <a>"
+        );
+    }
+
+    #[test]
+    fn test_float32_range() {
+        let ty = match inputs_type() {
+            Type::Record(record) => record.fields().next().unwrap().ty,
+            _ => panic!("inputs type is not a record"),
+        };
+        assert_eq!(
+            json_to_val(&json!(1e39), &ty, "inputs.a")
+                .unwrap_err()
+                .to_string(),
+            "inputs.a: 1e39 is out of range for f32"
+        );
+        assert_eq!(
+            json_to_val(&json!(-3.0e38), &ty, "inputs.a").unwrap(),
+            Val::Float32(-3.0e38)
+        );
+    }
+
+    #[test]
+    fn test_port_values() {
+        let ty: PortType = "record<x: float, n: int?, tags: list<string>>"
+            .parse()
+            .unwrap();
+        let val = json_to_port_val(&json!({"x": 2, "tags": ["a"]}), &ty, "inputs").unwrap();
+        assert_eq!(
+            val,
+            Val::Record(vec![
+                ("x".to_string(), Val::Float64(2.0)),
+                ("n".to_string(), Val::Option(None)),
+                (
+                    "tags".to_string(),
+                    Val::List(vec![Val::String("a".to_string())])
+                ),
+            ])
+        );
+        let err = json_to_port_val(&json!({"x": "2", "tags": []}), &ty, "inputs").unwrap_err();
+        assert_eq!(err.to_string(), "inputs.x: expected float, got string");
+    }
+
+    #[test]
+    fn test_coerce_val() {
+        let ty = match inputs_type() {
+            Type::Record(record) => record.fields().next().unwrap().ty,
+            other => panic!("unexpected type: {:?}", other),
+        };
+        assert_eq!(
+            coerce_val(Val::Float64(1.5), &ty, "inputs.a").unwrap(),
+            Val::Float32(1.5)
+        );
+        assert_eq!(
+            coerce_val(Val::Float32(1.5), &ty, "inputs.a").unwrap(),
+            Val::Float32(1.5)
+        );
+        assert!(coerce_val(Val::String("x".to_string()), &ty, "inputs.a").is_err());
+    }
+}
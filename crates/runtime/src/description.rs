@@ -0,0 +1,112 @@
+//! Node descriptions returned by the `describe` export of self-describing components, see
+//! the `self-describing` world in `wit/node/generic.wit`.
+
+use cillio_config::{NodeDescription, NodeImplementationData, PortDescription, PortType};
+use serde_json::Value;
+use wasmtime::component::Val;
+
+use crate::convert::val_to_json_untyped;
+
+pub(crate) const DESCRIBE_FN_NAME: &str = "describe";
+
+const PORT_SECTIONS: [&str; 3] = ["inputs", "outputs", "state"];
+
+/// Reads the `node-description` returned by a component's `describe` export.
+///
+/// The JSON encoded defaults of the ports are decoded.
+pub fn description_from_val(description: &Val) -> Result<NodeDescription, String> {
+    let mut json = val_to_json_untyped(description);
+    for section in PORT_SECTIONS {
+        let ports = json.get_mut(section).and_then(Value::as_array_mut);
+        for port in ports.into_iter().flatten() {
+            let name = port["name"].clone();
+            if let Some(default) = port.get_mut("default") {
+                if let Value::String(encoded) = default {
+                    let decoded = serde_json::from_str(encoded).map_err(|err| {
+                        format!("invalid default of {} port {}: {}", section, name, err)
+                    })?;
+                    *default = decoded;
+                }
+            }
+        }
+    }
+    serde_json::from_value(json).map_err(|err| format!("invalid description: {}", err))
+}
+
+/// Derives the node implementation of a component from its description.
+///
+/// Port types are normalized, e.g. `number` becomes `float`, and kept as written if they
+/// do not parse.
+pub fn implementation_from_description(
+    description: &NodeDescription,
+    wasm: &str,
+) -> NodeImplementationData {
+    let normalized = |ports: &[PortDescription]| -> Vec<PortDescription> {
+        ports
+            .iter()
+            .map(|port| PortDescription {
+                r#type: port
+                    .r#type
+                    .parse::<PortType>()
+                    .map_or_else(|_| port.r#type.clone(), |ty| ty.to_string()),
+                ..port.clone()
+            })
+            .collect()
+    };
+    let description = NodeDescription {
+        inputs: normalized(&description.inputs),
+        outputs: normalized(&description.outputs),
+        state: normalized(&description.state),
+        ..description.clone()
+    };
+    let mut implementation = NodeImplementationData {
+        input: None,
+        output: None,
+        state: None,
+        wasm: Some(wasm.to_string()),
+        graph: None,
+        native: None,
+        limits: None,
+        capabilities: None,
+        deterministic: true,
+    };
+    description.fill_implementation(&mut implementation);
+    implementation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_description_from_val() {
+        let string = |value: &str| Val::String(value.to_string());
+        let port = Val::Record(vec![
+            ("name".to_string(), string("gain")),
+            ("type".to_string(), string("number")),
+            (
+                "default".to_string(),
+                Val::Option(Some(Box::new(string("0.5")))),
+            ),
+            ("doc".to_string(), Val::Option(None)),
+        ]);
+        let description = Val::Record(vec![
+            ("name".to_string(), string("amplify")),
+            ("version".to_string(), string("0.2.0")),
+            ("description".to_string(), string("Amplifies a signal.")),
+            ("author".to_string(), Val::Option(None)),
+            ("categories".to_string(), Val::List(vec![string("audio")])),
+            ("inputs".to_string(), Val::List(vec![])),
+            ("outputs".to_string(), Val::List(vec![])),
+            ("state".to_string(), Val::List(vec![port])),
+        ]);
+        let description = description_from_val(&description).unwrap();
+        assert_eq!(description.author, None);
+        assert_eq!(description.state[0].default, Some(json!(0.5)));
+
+        let implementation = implementation_from_description(&description, "amplify");
+        assert_eq!(implementation.wasm.as_deref(), Some("amplify"));
+        assert_eq!(implementation.state.unwrap()["gain"], "float");
+    }
+}
@@ -1,115 +1,777 @@
-use std::process::Termination;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use cillio_config::{GraphPortData, NodeCapabilities, NodeLimits};
 use cillio_graph::Graph;
-use petgraph::algo::toposort;
-use serde_json::Value;
-use wasmtime::component::Val;
+use futures::{stream, StreamExt, TryStreamExt};
+use petgraph::{algo::toposort, visit::EdgeRef, Direction};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use thiserror::Error;
 
-use crate::Runtime;
+use crate::batch::BatchRow;
+use crate::logs::NodeLogs;
+use crate::node_instance::NodeInstance;
+use crate::result_cache::{CachedResult, ResultCache};
+use crate::runtime_data::PortValues;
+use crate::{Runtime, RuntimeError};
+
+/// Describes where a node input is read from.
+#[derive(Debug, Clone)]
+pub(crate) struct InputBinding {
+    pub(crate) from_node: String,
+    from_port: Option<String>,
+    to_port: Option<String>,
+}
+
+/// A node port exposed as an input or output of the graph.
+#[derive(Debug, Clone)]
+struct GraphPort {
+    node_id: String,
+    port: String,
+}
+
+/// Result of executing a single node.
+struct NodeRun {
+    inputs: PortValues,
+    outputs: PortValues,
+    logs: NodeLogs,
+    /// Fingerprint of everything besides the inputs the outputs depend on, see
+    /// [`ExecutionStep::run_hash`].
+    run_hash: u64,
+    /// Whether the outputs were loaded from the result cache instead of executing the node.
+    cached: bool,
+}
 
 #[derive(Debug)]
-struct ExecutionStep<S> {
-    node_id: usize,
-    node_type: String,
-    node_state: Option<S>,
+pub(crate) struct ExecutionStep<S> {
+    pub(crate) node_id: String,
+    pub(crate) node_type: String,
+    pub(crate) node_state: Option<S>,
+    /// States taken on successive ticks in streaming mode.
+    pub(crate) sequence: Option<Vec<S>>,
+    pub(crate) limits: NodeLimits,
+    pub(crate) capabilities: NodeCapabilities,
+    deterministic: bool,
+    pub(crate) inputs: Vec<InputBinding>,
 }
 
-impl<S: std::fmt::Debug> ExecutionStep<S> {
-    async fn execute(&self, runtime: &mut Runtime) -> Result<(), anyhow::Error> {
-        println!(
-            "Executing node {} - {}: {:?}",
-            self.node_id, self.node_type, self.node_state,
+impl<S: std::fmt::Debug + Serialize> ExecutionStep<S> {
+    /// Runs the node in its own instance on the given inputs and returns its outputs and
+    /// the lines it logged. Logged lines are also emitted as `tracing` events.
+    ///
+    /// The instance is taken from and returned to the runtime's instance pool if instances
+    /// are pooled.
+    async fn execute(
+        &self,
+        runtime: &Runtime,
+        inputs: &PortValues,
+        run_id: &str,
+    ) -> Result<(PortValues, NodeLogs), RuntimeError> {
+        tracing::debug!(
+            node_id = %self.node_id,
+            node_type = %self.node_type,
+            run_id,
+            state = ?self.node_state,
+            "Executing node"
         );
-        let instance = runtime
-            .initialize_node(self.node_type.as_str(), Some(&self.node_state))
+        let mut instance = self
+            .acquire_instance(runtime, self.node_state.as_ref())
             .await?;
 
-        let run_fn_name = "process";
+        let result = instance.process(inputs).await;
+        let logs = instance.take_logs();
+        logs.emit(&self.node_id, &self.node_type, run_id);
+        let outputs = result?;
+        self.release_instance(runtime, instance);
+        tracing::debug!(node_id = %self.node_id, run_id, ?outputs, "Executed node");
+        Ok((outputs, logs))
+    }
+
+    /// Returns an instance of the node with the given state, see [`Runtime::acquire_node`].
+    pub(crate) async fn acquire_instance(
+        &self,
+        runtime: &Runtime,
+        state: Option<&S>,
+    ) -> Result<NodeInstance, RuntimeError> {
+        runtime
+            .acquire_node(
+                &self.node_id,
+                &self.node_type,
+                state,
+                self.limits,
+                &self.capabilities,
+            )
+            .await
+    }
 
-        let run_fn = instance
-            .get_func(&mut runtime.store, run_fn_name)
-            .ok_or(anyhow::anyhow!("Function not found"))?;
+    pub(crate) fn release_instance(&self, runtime: &Runtime, instance: NodeInstance) {
+        runtime.release_node(&self.node_type, self.limits, &self.capabilities, instance);
+    }
 
-        let fn_params = run_fn.params(&runtime.store);
-        println!("Function params: {:?}", fn_params);
-        let params = [];
-        let mut returns = [Val::Record(Vec::new())];
-        run_fn
-            .call_async(&mut runtime.store, &params, &mut returns)
-            .await?;
-        println!("Returns: {:?}", returns);
-
-        // let params = runtime.get_params_data(&self.node_id).unwrap();
-        // let results = runtime.get_results_data(&self.node_id).unwrap();
-        // run_fn
-        //     .call_async(&mut runtime.store, params, results)
-        //     .await?;
-
-        //let run_fn = run_fn.typed::<(), ({ number: f32})>(&mut runtime.store)?;
-
-        // let results = run_fn.call_async(&mut runtime.store, ()).await?;
-        //println!("Results: {:?}", results);
-
-        // let run_fn = module
-        //     .get_export(&run_fn_name)
-        //     .
-        //     .ok_or(anyhow::anyhow!(format!(
-        //         "Function <{}> for <{}> not found",
-        //         run_fn_name, self.node_type
-        //     )))?;
-        // let params = run_fn.params(&runtime.store);
-        // let results = run_fn.results(&runtime.store);
-        // println!("Run function: {:?} - {:?}", &params, &results);
-        //run_fn.call_async(&mut runtime.store, , &[]);
-        // let run_fn = instance
-        //     .get_typed_func::<(), f32>(&mut runtime.store, "run")
-        //     .expect("Function not found");
-        //run_fn.call_async(&mut runtime.store, ()).await?;
-        Ok(())
+    /// Executes the node unless it already ran on the same inputs, state, implementation,
+    /// limits and capabilities, in which case `None` is returned and the previous outputs
+    /// stay valid. Nodes that are not deterministic always run.
+    ///
+    /// Deterministic nodes are looked up in the runtime's result cache before they are
+    /// executed, and their results are stored there afterwards.
+    async fn execute_if_changed(
+        &self,
+        runtime: &Runtime,
+        run_id: &str,
+    ) -> Result<Option<NodeRun>, RuntimeError> {
+        let data = runtime.get_runtime_data();
+        let inputs = self.collect_inputs(&runtime.input_ports(&self.node_type)?, data.outputs())?;
+        let run_hash = self.run_hash(runtime)?;
+        if self.deterministic && data.is_up_to_date(&self.node_id, &inputs, run_hash) {
+            return Ok(None);
+        }
+
+        let result_cache = runtime
+            .result_cache()
+            .filter(|_| self.deterministic && runtime.caches_results(&self.node_type));
+        let cache_key = match result_cache {
+            Some(_) => Some(ResultCache::key(
+                runtime.component_hash(&self.node_type)?,
+                self.node_state.as_ref(),
+                &self.limits,
+                &self.capabilities,
+                &inputs,
+            )?),
+            None => None,
+        };
+        if let (Some(cache), Some(key)) = (result_cache, &cache_key) {
+            if let Some(cached) = cache.load(key) {
+                tracing::debug!(node_id = %self.node_id, run_id, key, "Loaded result from cache");
+                return Ok(Some(NodeRun {
+                    outputs: runtime.outputs_from_json(&self.node_type, &cached.outputs)?,
+                    inputs,
+                    logs: cached.logs,
+                    run_hash,
+                    cached: true,
+                }));
+            }
+        }
+
+        let (outputs, logs) = self.execute(runtime, &inputs, run_id).await?;
+        if let (Some(cache), Some(key)) = (result_cache, &cache_key) {
+            // Outputs without a JSON representation, such as NaN, are not cached.
+            let stored = runtime
+                .outputs_to_json(&self.node_type, &outputs)
+                .map_err(|err| err.to_string())
+                .and_then(|json_outputs| {
+                    let cached = CachedResult {
+                        node_type: self.node_type.clone(),
+                        outputs: json_outputs,
+                        logs: logs.clone(),
+                    };
+                    cache.store(key, &cached).map_err(|err| err.to_string())
+                });
+            if let Err(err) = stored {
+                tracing::warn!(node_id = %self.node_id, run_id, "Failed to cache result: {}", err);
+            }
+        }
+        Ok(Some(NodeRun {
+            inputs,
+            outputs,
+            logs,
+            run_hash,
+            cached: false,
+        }))
+    }
+
+    /// Hashes the state, the implementation of the node type and the limits and capabilities
+    /// of the node.
+    fn run_hash(&self, runtime: &Runtime) -> Result<u64, RuntimeError> {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_vec(&self.node_state)?.hash(&mut hasher);
+        self.node_type.hash(&mut hasher);
+        runtime.component_hash(&self.node_type)?.hash(&mut hasher);
+        serde_json::to_vec(&self.limits)?.hash(&mut hasher);
+        serde_json::to_vec(&self.capabilities)?.hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    /// Routes the outputs of upstream nodes into this node's input ports.
+    pub(crate) fn collect_inputs(
+        &self,
+        input_ports: &[String],
+        node_outputs: &HashMap<String, PortValues>,
+    ) -> Result<PortValues, RuntimeError> {
+        let mut inputs = PortValues::new();
+        for binding in &self.inputs {
+            let upstream = node_outputs.get(&binding.from_node).ok_or_else(|| {
+                RuntimeError::ComputeError(format!(
+                    "{}: no outputs available from <{}>",
+                    self.node_id, binding.from_node
+                ))
+            })?;
+
+            let (from_port, value) = match &binding.from_port {
+                Some(port) => upstream.get_key_value(port),
+                None if upstream.len() == 1 => upstream.iter().next(),
+                None => None,
+            }
+            .ok_or_else(|| {
+                RuntimeError::ComputeError(format!(
+                    "{}: cannot resolve output port {:?} of <{}>",
+                    self.node_id, binding.from_port, binding.from_node
+                ))
+            })?;
+
+            let to_port = match &binding.to_port {
+                Some(port) => port.clone(),
+                None if input_ports.len() == 1 => input_ports[0].clone(),
+                None => from_port.clone(),
+            };
+            inputs.insert(to_port, value.clone());
+        }
+        Ok(inputs)
     }
 }
 
+#[derive(Error, Debug)]
+pub enum PlanError {
+    /// The graph contains cycles, each listed as the node ids along the loop.
+    #[error("The graph is cyclic: {}", format_cycles(.0))]
+    CyclicGraphError(Vec<Vec<String>>),
+
+    /// A composite node whose subgraph was not expanded, see
+    /// [`cillio_config::expand_subgraphs`].
+    #[error("Composite node <{0}> must be expanded before execution")]
+    UnexpandedSubgraphError(String),
+
+    /// A graph input or output does not name a port and its node declares several.
+    #[error("Cannot resolve the port of graph {0} <{1}>")]
+    UnresolvedGraphPortError(&'static str, String),
+}
+
+fn format_cycles(cycles: &[Vec<String>]) -> String {
+    cycles
+        .iter()
+        .map(|cycle| cycle.join(" -> "))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Outputs and logs of every node of a plan execution, keyed by node id.
+#[derive(Debug, Clone)]
+pub struct ExecutionResult {
+    /// Identifies the execution in the `run_id` field of node log events.
+    pub run_id: String,
+    pub outputs: HashMap<String, PortValues>,
+    pub logs: HashMap<String, NodeLogs>,
+    /// Nodes whose outputs and logs were reused from a previous execution.
+    pub reused: Vec<String>,
+    /// Nodes whose outputs and logs were loaded from the result cache.
+    pub cached: Vec<String>,
+}
+
+/// Executes a graph level by level.
+///
+/// Every level only depends on nodes of previous levels, so the nodes within a level run
+/// concurrently, each in its own store, bounded by the plan's maximum concurrency.
 #[derive(Debug)]
 pub struct ExecutionPlan {
-    steps: Vec<ExecutionStep<Value>>,
+    levels: Vec<Vec<ExecutionStep<Value>>>,
+    max_concurrency: usize,
+    inputs: HashMap<String, GraphPort>,
+    outputs: HashMap<String, GraphPort>,
 }
 
 impl ExecutionPlan {
     pub fn new() -> Self {
-        Self { steps: Vec::new() }
+        Self {
+            levels: Vec::new(),
+            max_concurrency: default_max_concurrency(),
+            inputs: HashMap::new(),
+            outputs: HashMap::new(),
+        }
     }
 
-    pub fn from_graph(graph: &Graph) -> Self {
+    pub fn from_graph(graph: &Graph) -> Result<Self, PlanError> {
         let digraph = graph.graph();
-        let sorted_nodes = toposort(digraph, None).expect("The graph is cyclic!");
-        let execution_steps: Vec<_> = sorted_nodes
-            .into_iter()
-            .map(|node_index| {
-                let node = digraph.node_weight(node_index).unwrap();
-                ExecutionStep {
-                    node_id: node_index.index(),
-                    node_type: node.data().r#type.clone(),
-                    node_state: node.data().state.clone(),
-                }
-            })
-            .collect();
-        Self {
-            steps: execution_steps,
+        let sorted_nodes = toposort(digraph, None)
+            .map_err(|_| PlanError::CyclicGraphError(graph.find_cycles()))?;
+
+        let mut node_levels = HashMap::new();
+        let mut levels: Vec<Vec<ExecutionStep<Value>>> = Vec::new();
+        for node_index in sorted_nodes {
+            let node = digraph.node_weight(node_index).unwrap();
+            let level = digraph
+                .neighbors_directed(node_index, Direction::Incoming)
+                .map(|source| node_levels[&source] + 1)
+                .max()
+                .unwrap_or(0);
+            node_levels.insert(node_index, level);
+
+            let inputs = digraph
+                .edges_directed(node_index, Direction::Incoming)
+                .map(|edge| InputBinding {
+                    from_node: digraph[edge.source()].id.clone(),
+                    from_port: edge.weight().from_port.clone(),
+                    to_port: edge.weight().to_port.clone(),
+                })
+                .collect();
+            let implementation = graph.node_implementation(node_index);
+            if implementation.is_some_and(|implementation| implementation.graph.is_some()) {
+                return Err(PlanError::UnexpandedSubgraphError(node.id.clone()));
+            }
+            let implementation_limits = implementation
+                .and_then(|implementation| implementation.limits)
+                .unwrap_or_default();
+            let limits = node
+                .data()
+                .limits
+                .unwrap_or_default()
+                .or(implementation_limits);
+            let capabilities = node
+                .data()
+                .capabilities
+                .as_ref()
+                .or_else(|| implementation.and_then(|i| i.capabilities.as_ref()))
+                .cloned()
+                .unwrap_or_default();
+            let deterministic = implementation.is_none_or(|i| i.deterministic);
+
+            if levels.len() <= level {
+                levels.resize_with(level + 1, Vec::new);
+            }
+            levels[level].push(ExecutionStep {
+                node_id: node.id.clone(),
+                node_type: node.data().r#type.clone(),
+                node_state: node.data().state.clone(),
+                sequence: node.data().sequence.clone(),
+                limits,
+                capabilities,
+                deterministic,
+                inputs,
+            });
         }
+
+        let graph_ports = |ports: &HashMap<String, GraphPortData>, output: bool| {
+            ports
+                .iter()
+                .map(|(name, graph_port)| {
+                    let port = graph.graph_port(graph_port, output).ok_or_else(|| {
+                        let direction = if output { "output" } else { "input" };
+                        PlanError::UnresolvedGraphPortError(direction, name.clone())
+                    })?;
+                    let graph_port = GraphPort {
+                        node_id: graph_port.node.clone(),
+                        port,
+                    };
+                    Ok((name.clone(), graph_port))
+                })
+                .collect::<Result<HashMap<_, _>, PlanError>>()
+        };
+
+        Ok(Self {
+            levels,
+            max_concurrency: default_max_concurrency(),
+            inputs: graph_ports(graph.inputs(), false)?,
+            outputs: graph_ports(graph.outputs(), true)?,
+        })
+    }
+
+    /// Limits how many nodes of a level are executed at the same time.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    /// Returns the node ids of each dependency level.
+    pub fn levels(&self) -> Vec<Vec<&str>> {
+        self.levels
+            .iter()
+            .map(|level| level.iter().map(|step| step.node_id.as_str()).collect())
+            .collect()
+    }
+
+    pub(crate) fn steps(&self) -> impl Iterator<Item = &ExecutionStep<Value>> {
+        self.levels.iter().flatten()
+    }
+
+    /// Returns the names of the graph inputs in alphabetical order.
+    pub fn input_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.inputs.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// Returns the names of the graph outputs in alphabetical order.
+    pub fn output_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.outputs.keys().map(String::as_str).collect();
+        names.sort();
+        names
     }
 
-    pub async fn execute(&self, runtime: &mut Runtime) -> Result<Vec<()>, anyhow::Error> {
-        let store_data = runtime.get_store().data();
-        println!("Execute: {:?}", store_data);
-        for step in &self.steps {
-            step.execute(runtime).await?;
+    /// Feeds a value of the graph input `name` into the node port it is declared for.
+    pub(crate) fn feed_input(
+        &self,
+        row: &mut BatchRow,
+        name: &str,
+        value: &Value,
+    ) -> Result<(), RuntimeError> {
+        let graph_port = self.inputs.get(name).ok_or_else(|| {
+            RuntimeError::ComputeError(format!(
+                "Unknown graph input <{}> (declared: {:?})",
+                name,
+                self.input_names()
+            ))
+        })?;
+        row.inputs
+            .entry(graph_port.node_id.clone())
+            .or_default()
+            .insert(graph_port.port.clone(), value.clone());
+        Ok(())
+    }
+
+    /// Executes the plan as a function from the graph inputs to the graph outputs, both
+    /// keyed by name and encoded as JSON.
+    ///
+    /// The inputs feed the node ports they are declared for. Unlike [`Self::execute`] the
+    /// runtime keeps no results, so calls do not affect each other and may run concurrently.
+    pub async fn call(
+        &self,
+        runtime: &Runtime,
+        inputs: &Map<String, Value>,
+    ) -> Result<Map<String, Value>, anyhow::Error> {
+        let mut row = BatchRow::default();
+        for (name, value) in inputs {
+            self.feed_input(&mut row, name, value)?;
+        }
+
+        let result = self
+            .execute_batch(runtime, std::slice::from_ref(&row))
+            .await?
+            .pop()
+            .expect("a batch yields one result per row");
+        let row_outputs = result.result?;
+
+        let mut outputs = Map::new();
+        for (name, graph_port) in &self.outputs {
+            let step = self
+                .steps()
+                .find(|step| step.node_id == graph_port.node_id)
+                .expect("graph outputs refer to nodes of the plan");
+            let node_outputs =
+                runtime.outputs_to_json(&step.node_type, &row_outputs.outputs[&step.node_id])?;
+            let value = node_outputs.get(&graph_port.port).ok_or_else(|| {
+                RuntimeError::ComputeError(format!(
+                    "Node <{}> has no output <{}> for graph output <{}>",
+                    graph_port.node_id, graph_port.port, name
+                ))
+            })?;
+            outputs.insert(name.clone(), value.clone());
+        }
+        Ok(outputs)
+    }
+
+    /// Executes all levels in order and returns the outputs and logs of every node.
+    pub async fn execute(&self, runtime: &mut Runtime) -> Result<ExecutionResult, anyhow::Error> {
+        runtime.runtime_data.clear();
+        self.execute_incremental(runtime).await
+    }
+
+    /// Executes the plan, reusing the results the runtime kept from its previous execution.
+    ///
+    /// Only nodes whose state or inputs changed since then are executed again. Nodes
+    /// downstream of an executed node therefore rerun whenever its outputs changed.
+    pub async fn execute_incremental(
+        &self,
+        runtime: &mut Runtime,
+    ) -> Result<ExecutionResult, anyhow::Error> {
+        let run_id = new_run_id();
+        let mut reused = Vec::new();
+        let mut cached = Vec::new();
+        for level in &self.levels {
+            let results: Vec<_> = {
+                let runtime: &Runtime = runtime;
+                let run_id = run_id.as_str();
+                stream::iter(level)
+                    .map(|step| async move {
+                        let run = step.execute_if_changed(runtime, run_id).await?;
+                        Ok::<_, RuntimeError>((step.node_id.as_str(), run))
+                    })
+                    .buffer_unordered(self.max_concurrency)
+                    .try_collect()
+                    .await?
+            };
+            for (node_id, run) in results {
+                let Some(run) = run else {
+                    reused.push(node_id.to_string());
+                    continue;
+                };
+                if run.cached {
+                    cached.push(node_id.to_string());
+                }
+                runtime.runtime_data.set_node_input(node_id, run.inputs);
+                runtime.runtime_data.set_node_output(node_id, run.outputs);
+                runtime.runtime_data.set_node_logs(node_id, run.logs);
+                runtime
+                    .runtime_data
+                    .set_node_run_hash(node_id, run.run_hash);
+            }
         }
-        Ok(vec![])
+
+        let node_ids: HashSet<&str> = self.levels().into_iter().flatten().collect();
+        runtime.runtime_data.retain_nodes(&node_ids);
+        Ok(ExecutionResult {
+            run_id,
+            outputs: runtime.runtime_data.outputs().clone(),
+            logs: runtime.runtime_data.logs().clone(),
+            reused,
+            cached,
+        })
     }
 }
 
+pub(crate) fn new_run_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos());
+    format!("{:x}", nanos)
+}
+
+fn default_max_concurrency() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
 impl Default for ExecutionPlan {
     fn default() -> Self {
         ExecutionPlan::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use serde_json::json;
+    use wasmtime::component::Val;
+
+    use super::*;
+    use crate::instance_pool::{InstanceReuse, RuntimeConfig};
+    use crate::test_utils::{plan_from_json, runtime_with_components, INCREMENT, TIMED};
+
+    const SOURCE: &str = r#"(component
+        (core module $m (func (export "process") (result i32) i32.const 1))
+        (core instance $i (instantiate $m))
+        (type $r (record (field "value" u32)))
+        (export $r' "r" (type $r))
+        (func $f (result $r') (canon lift (core func $i "process")))
+        (export "process" (func $f))
+    )"#;
+
+    fn plan(source_state: i64) -> ExecutionPlan {
+        plan_from_json(json!({
+            "node_implementations": {
+                "source": {"output": {"value": "int"}, "wasm": "source"},
+                "increment": {
+                    "input": {"value": "int"},
+                    "output": {"value": "int"},
+                    "wasm": "increment"
+                }
+            },
+            "nodes": {
+                "a": {"type": "source", "state": {"seed": source_state}},
+                "b": {"type": "increment"},
+                "c": {"type": "increment"}
+            },
+            "edges": [{"from": "a", "to": "b"}, {"from": "b", "to": "c"}]
+        }))
+    }
+
+    fn runtime() -> Runtime {
+        runtime_from_config(RuntimeConfig::default())
+    }
+
+    fn runtime_from_config(config: RuntimeConfig) -> Runtime {
+        runtime_with_components(config, &[("source", SOURCE), ("increment", INCREMENT)])
+    }
+
+    fn sorted(mut nodes: Vec<String>) -> Vec<String> {
+        nodes.sort();
+        nodes
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut runtime = runtime();
+        let result = block_on(plan(1).execute(&mut runtime)).unwrap();
+        assert_eq!(result.outputs["c"]["value"], Val::U32(3));
+        assert!(result.reused.is_empty());
+    }
+
+    #[test]
+    fn test_level_concurrency() {
+        // Adds the end times of two timed nodes of the same level.
+        const ADD: &str = r#"(component
+            (core module $m
+                (func (export "process") (param i64 i64) (result i64)
+                    (i64.add (local.get 0) (local.get 1))))
+            (core instance $i (instantiate $m))
+            (type $in (record (field "a" u64) (field "b" u64)))
+            (export $in' "in" (type $in))
+            (type $out (record (field "value" u64)))
+            (export $out' "out" (type $out))
+            (func $f (param "inputs" $in') (result $out')
+                (canon lift (core func $i "process")))
+            (export "process" (func $f))
+        )"#;
+        let plan = || {
+            plan_from_json(json!({
+                "node_implementations": {
+                    "timed": {
+                        "output": {"start": "int", "end": "int"},
+                        "wasm": "timed",
+                        "capabilities": {"clocks": true}
+                    },
+                    "add": {
+                        "input": {"a": "int", "b": "int"},
+                        "output": {"value": "int"},
+                        "wasm": "add"
+                    }
+                },
+                "nodes": {
+                    "x": {"type": "timed"},
+                    "y": {"type": "timed"},
+                    "sum": {"type": "add"}
+                },
+                "edges": [
+                    {"from": "x", "from_port": "end", "to": "sum", "to_port": "a"},
+                    {"from": "y", "from_port": "end", "to": "sum", "to_port": "b"}
+                ]
+            }))
+        };
+        let levels: Vec<_> = plan()
+            .levels()
+            .into_iter()
+            .map(|level| sorted(level.into_iter().map(String::from).collect()))
+            .collect();
+        assert_eq!(levels, [vec!["x", "y"], vec!["sum"]]);
+
+        // Nodes only yield to the other nodes of their level on epoch ticks.
+        let config = RuntimeConfig {
+            enforce_limits: true,
+            ..Default::default()
+        };
+        let mut runtime = runtime_with_components(config, &[("timed", TIMED), ("add", ADD)]);
+        let mut overlap = |max_concurrency| {
+            let plan = plan().with_max_concurrency(max_concurrency);
+            let outputs = block_on(plan.execute(&mut runtime)).unwrap().outputs;
+            let time = |node: &str, port: &str| match outputs[node][port] {
+                Val::U64(time) => time,
+                ref other => panic!("unexpected value: {:?}", other),
+            };
+            assert_eq!(
+                outputs["sum"]["value"],
+                Val::U64(time("x", "end") + time("y", "end"))
+            );
+            time("x", "start") < time("y", "end") && time("y", "start") < time("x", "end")
+        };
+        assert!(overlap(2));
+        assert!(!overlap(1));
+    }
+
+    #[test]
+    fn test_execute_incremental_reuses_unchanged_nodes() {
+        let mut runtime = runtime_from_config(RuntimeConfig {
+            enforce_limits: true,
+            ..Default::default()
+        });
+        block_on(plan(1).execute(&mut runtime)).unwrap();
+
+        let result = block_on(plan(1).execute_incremental(&mut runtime)).unwrap();
+        assert_eq!(sorted(result.reused), ["a", "b", "c"]);
+        assert_eq!(result.outputs["c"]["value"], Val::U32(3));
+
+        // The changed state reruns `a`, which yields the same outputs as before.
+        let result = block_on(plan(2).execute_incremental(&mut runtime)).unwrap();
+        assert_eq!(sorted(result.reused), ["b", "c"]);
+
+        // Changed limits rerun `c`, and nodes that are not deterministic always run.
+        let mut changed = plan(2);
+        for step in changed.levels.iter_mut().flatten() {
+            match step.node_id.as_str() {
+                "a" => step.deterministic = false,
+                "c" => step.limits.fuel = Some(1_000_000),
+                _ => {}
+            }
+        }
+        let result = block_on(changed.execute_incremental(&mut runtime)).unwrap();
+        assert_eq!(result.reused, ["b"]);
+    }
+
+    #[test]
+    fn test_execute_with_result_cache() {
+        let dir = std::env::temp_dir().join(format!(
+            "cillio-plan-result-cache-test-{}",
+            std::process::id()
+        ));
+        let result =
+            block_on(plan(1).execute(&mut runtime().with_result_cache(ResultCache::new(&dir))))
+                .unwrap();
+        assert!(result.cached.is_empty());
+
+        // A fresh runtime takes every result from the cache.
+        let result =
+            block_on(plan(1).execute(&mut runtime().with_result_cache(ResultCache::new(&dir))))
+                .unwrap();
+        assert_eq!(sorted(result.cached), ["a", "b", "c"]);
+        assert_eq!(result.outputs["c"]["value"], Val::U32(3));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_call() {
+        let plan = plan_from_json(json!({
+            "node_implementations": {
+                "increment": {
+                    "input": {"value": "int"},
+                    "output": {"value": "int"},
+                    "wasm": "increment"
+                }
+            },
+            "nodes": {"b": {"type": "increment"}, "c": {"type": "increment"}},
+            "edges": [{"from": "b", "to": "c"}],
+            "inputs": {"x": {"node": "b", "port": "value"}},
+            "outputs": {"y": {"node": "c"}}
+        }));
+        assert_eq!(plan.input_names(), ["x"]);
+        assert_eq!(plan.output_names(), ["y"]);
+
+        let runtime = runtime();
+        let outputs = block_on(plan.call(&runtime, json!({"x": 5}).as_object().unwrap()));
+        assert_eq!(Value::Object(outputs.unwrap()), json!({"y": 7}));
+        assert!(block_on(plan.call(&runtime, json!({"z": 5}).as_object().unwrap())).is_err());
+    }
+
+    #[test]
+    fn test_pooled_instances_are_reused_across_runs() {
+        let mut runtime = runtime_from_config(RuntimeConfig {
+            instance_reuse: InstanceReuse::Pooled,
+            pooling_allocator: Some(8),
+            ..Default::default()
+        });
+        block_on(plan(1).execute(&mut runtime)).unwrap();
+        assert_eq!(runtime.idle_instances(), 3);
+
+        let result = block_on(plan(1).execute(&mut runtime)).unwrap();
+        assert_eq!(result.outputs["c"]["value"], Val::U32(3));
+        assert_eq!(runtime.idle_instances(), 3);
+
+        runtime.clear_instance_pool();
+        assert_eq!(runtime.idle_instances(), 0);
+        // Instances that are not pooled are dropped after every call.
+        let mut runtime = self::runtime();
+        block_on(plan(1).execute(&mut runtime)).unwrap();
+        assert_eq!(runtime.idle_instances(), 0);
+    }
+}
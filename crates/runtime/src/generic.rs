@@ -0,0 +1,235 @@
+//! Support for components of the generic node world in `wit/node/generic.wit`.
+//!
+//! Instead of an `inputs` record, the `process` function of a generic node takes a list of
+//! `port-value`s and returns another one. The values are in the order of the ports the
+//! component's `describe` export returns, which the runtime calls when loading it.
+
+use cillio_config::PortType;
+use serde_json::Value;
+use wasmtime::component::{Type, Val};
+
+use crate::convert::{json_to_port_val, val_to_json_untyped, ConversionError};
+use crate::node_ports::NodePorts;
+use crate::runtime_data::PortValues;
+
+/// Whether the first parameter of a `process` function is a list of port values rather
+/// than an inputs record.
+pub fn is_generic(process_param: Option<&Type>) -> bool {
+    matches!(process_param, Some(Type::List(_)))
+}
+
+/// Encodes a value of the canonical port type representation as a `port-value`.
+pub fn to_port_value(value: &Val) -> Val {
+    let case = |name: &str, payload: Val| Val::Variant(name.to_string(), Some(Box::new(payload)));
+    match value {
+        Val::Option(None) => Val::Variant("none".to_string(), None),
+        Val::Option(Some(value)) => to_port_value(value),
+        Val::Bool(_) => case("boolean", value.clone()),
+        Val::S64(_) => case("integer", value.clone()),
+        Val::Float64(_) => case("float", value.clone()),
+        Val::String(_) => case("text", value.clone()),
+        other => case("json", Val::String(val_to_json_untyped(other).to_string())),
+    }
+}
+
+/// Decodes a `port-value` into the canonical representation of the port type `ty`.
+pub fn from_port_value(value: &Val, ty: &PortType, path: &str) -> Result<Val, ConversionError> {
+    let json = match value {
+        Val::Variant(case, None) if case == "none" => Value::Null,
+        Val::Variant(case, Some(payload)) if case == "json" => match payload.as_ref() {
+            Val::String(json) => serde_json::from_str(json)
+                .map_err(|err| ConversionError::new(path, format!("invalid JSON: {}", err)))?,
+            other => val_to_json_untyped(other),
+        },
+        Val::Variant(_, Some(payload)) => val_to_json_untyped(payload),
+        other => {
+            return Err(ConversionError::new(
+                path,
+                format!("expected port-value, got {:?}", other),
+            ))
+        }
+    };
+    json_to_port_val(&json, ty, path)
+}
+
+/// Encodes the inputs of a generic node as the list passed to `process`.
+pub(crate) fn inputs_param(ports: &NodePorts, inputs: &PortValues) -> Result<Val, ConversionError> {
+    let inputs = ports.convert_inputs(inputs)?;
+    Ok(Val::List(
+        ports
+            .inputs
+            .iter()
+            .map(|(port, _)| to_port_value(&inputs[port]))
+            .collect(),
+    ))
+}
+
+/// Extracts the outputs from the value returned by the `process` function of a generic node.
+pub(crate) fn outputs_from_return(value: Val, ports: &NodePorts) -> Result<PortValues, String> {
+    match value {
+        Val::Result(Ok(Some(values))) => match *values {
+            Val::List(values) if values.len() == ports.outputs.len() => ports
+                .outputs
+                .iter()
+                .zip(values.iter())
+                .map(|((port, ty), value)| {
+                    from_port_value(value, ty, &format!("outputs.{}", port))
+                        .map(|value| (port.clone(), value))
+                        .map_err(|err| err.to_string())
+                })
+                .collect(),
+            Val::List(values) => Err(format!(
+                "expected {} output values, got {}",
+                ports.outputs.len(),
+                values.len()
+            )),
+            other => Err(format!("unsupported return value: {:?}", other)),
+        },
+        Val::Result(Err(error)) => Err(format!("node failed: {:?}", error)),
+        other => Err(format!("unsupported return value: {:?}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    use super::*;
+    use crate::instance_pool::RuntimeConfig;
+    use crate::test_utils::{plan_from_json, runtime_with_components};
+
+    /// A generic node with an `int` port `value` that returns its input plus one.
+    const INCREMENT: &str = r#"(component
+        (core module $m
+            (memory (export "memory") 1)
+            (global $next (mut i32) (i32.const 1024))
+            ;; The port at 100, the categories at 272 and the description at 300.
+            (data (i32.const 100) "\c8\00\00\00\05\00\00\00\d0\00\00\00\03\00\00\00\01\00\00\00\d4\00\00\00\01\00\00\00\01\00\00\00\d8\00\00\00\12\00\00\00")
+            (data (i32.const 200) "value")
+            (data (i32.const 208) "int")
+            (data (i32.const 212) "0")
+            (data (i32.const 216) "Value to increment")
+            (data (i32.const 240) "increment")
+            (data (i32.const 252) "1.0.0")
+            (data (i32.const 260) "Adds one.")
+            (data (i32.const 272) "\18\01\00\00\04\00\00\00")
+            (data (i32.const 280) "math")
+            (data (i32.const 300) "\f0\00\00\00\09\00\00\00\fc\00\00\00\05\00\00\00\04\01\00\00\09\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\10\01\00\00\01\00\00\00\64\00\00\00\01\00\00\00\64\00\00\00\01\00\00\00\00\00\00\00\00\00\00\00")
+            (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr
+                    (i32.and
+                        (i32.add (global.get $next) (i32.sub (local.get 2) (i32.const 1)))
+                        (i32.sub (i32.const 0) (local.get 2))))
+                (global.set $next (i32.add (local.get $ptr) (local.get 3)))
+                (local.get $ptr))
+            (func (export "describe") (result i32) i32.const 300)
+            (func (export "process") (param $ptr i32) (param $len i32) (result i32)
+                (i32.store8 (i32.const 512) (i32.const 2))
+                (i64.store (i32.const 520)
+                    (i64.add (i64.load (i32.add (local.get $ptr) (i32.const 8))) (i64.const 1)))
+                (i32.store (i32.const 400) (i32.const 0))
+                (i32.store (i32.const 404) (i32.const 512))
+                (i32.store (i32.const 408) (i32.const 1))
+                i32.const 400))
+        (core instance $i (instantiate $m))
+        (type $pv (variant
+            (case "none")
+            (case "boolean" bool)
+            (case "integer" s64)
+            (case "float" f64)
+            (case "text" string)
+            (case "json" string)))
+        (export $pv' "port-value" (type $pv))
+        (type $port (record
+            (field "name" string)
+            (field "type" string)
+            (field "default" (option string))
+            (field "doc" (option string))))
+        (export $port' "port" (type $port))
+        (type $d (record
+            (field "name" string)
+            (field "version" string)
+            (field "description" string)
+            (field "author" (option string))
+            (field "categories" (list string))
+            (field "inputs" (list $port'))
+            (field "outputs" (list $port'))
+            (field "state" (list $port'))))
+        (export $d' "node-description" (type $d))
+        (type $e (variant (case "invalid-input" string) (case "process-error" string)))
+        (export $e' "node-error" (type $e))
+        (func $describe (result $d') (canon lift (core func $i "describe") (memory $i "memory")))
+        (export "describe" (func $describe))
+        (func $process (param "inputs" (list $pv')) (result (result (list $pv') (error $e')))
+            (canon lift (core func $i "process") (memory $i "memory") (realloc (func $i "realloc"))))
+        (export "process" (func $process))
+    )"#;
+
+    #[test]
+    fn test_port_values() {
+        let ty: PortType = "list<int>".parse().unwrap();
+        let value = Val::List(vec![Val::S64(1), Val::S64(2)]);
+        let port_value = to_port_value(&value);
+        assert_eq!(
+            port_value,
+            Val::Variant(
+                "json".to_string(),
+                Some(Box::new(Val::String("[1,2]".to_string())))
+            )
+        );
+        assert_eq!(
+            from_port_value(&port_value, &ty, "outputs.x").unwrap(),
+            value
+        );
+
+        let ty: PortType = "float?".parse().unwrap();
+        let none = to_port_value(&Val::Option(None));
+        assert_eq!(
+            from_port_value(&none, &ty, "outputs.x").unwrap(),
+            Val::Option(None)
+        );
+        let text = to_port_value(&Val::String("x".to_string()));
+        assert!(from_port_value(&text, &ty, "outputs.x").is_err());
+    }
+
+    #[test]
+    fn test_execute_generic_node() {
+        let mut runtime =
+            runtime_with_components(RuntimeConfig::default(), &[("increment", INCREMENT)]);
+        assert_eq!(runtime.input_ports("increment").unwrap(), ["value"]);
+        let implementation = runtime
+            .describe_component("increment", "increment")
+            .unwrap();
+        assert_eq!(implementation.output.unwrap()["value"], "int");
+        let description = runtime.node_description("increment").unwrap();
+        assert_eq!(description.name, "increment");
+        assert_eq!(description.categories, ["math"]);
+        assert_eq!(description.inputs[0].default, Some(json!(0)));
+        assert_eq!(
+            description.inputs[0].doc.as_deref(),
+            Some("Value to increment")
+        );
+
+        let plan = plan_from_json(json!({
+            "node_implementations": {
+                "increment": {
+                    "input": {"value": "int"},
+                    "output": {"value": "int"},
+                    "wasm": "increment"
+                }
+            },
+            "nodes": {"b": {"type": "increment"}, "c": {"type": "increment"}},
+            "edges": [{"from": "b", "to": "c"}],
+            "inputs": {"x": {"node": "b", "port": "value"}},
+            "outputs": {"y": {"node": "c"}}
+        }));
+        let outputs = block_on(plan.call(&runtime, json!({"x": 5}).as_object().unwrap()));
+        assert_eq!(Value::Object(outputs.unwrap()), json!({"y": 7}));
+
+        // Without the graph input, `b` gets the described default of its port.
+        let result = block_on(plan.execute(&mut runtime)).unwrap();
+        assert_eq!(result.outputs["c"]["value"], Val::S64(2));
+    }
+}
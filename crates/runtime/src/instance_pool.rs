@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use cillio_config::{NodeCapabilities, NodeLimits};
+use wasmtime::{InstanceAllocationStrategy, PoolingAllocationConfig};
+
+use crate::node_instance::NodeInstance;
+
+/// When node instances are created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InstanceReuse {
+    /// Every `process` call gets a new instance, so no call sees the memory of another.
+    Fresh,
+    /// Every node is instantiated once per run and reused for all events of a stream or all
+    /// rows a batch worker processes.
+    #[default]
+    PerRun,
+    /// Like [`InstanceReuse::PerRun`], and instances are kept in the runtime after a run to
+    /// be reused by later runs of the same node.
+    Pooled,
+}
+
+/// Settings of a [`crate::Runtime`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    pub instance_reuse: InstanceReuse,
+    /// Preallocates slots for this many component instances with wasmtime's pooling
+    /// allocator, which makes instantiation cheaper at the cost of reserved memory.
+    /// Instances are allocated on demand if `None`.
+    pub pooling_allocator: Option<u32>,
+    /// Meters fuel and interrupts running nodes on epoch ticks, which the fuel and timeout
+    /// limits of nodes need. Both slow down every node, so nodes with such limits are only
+    /// accepted if enabled.
+    pub enforce_limits: bool,
+}
+
+/// Returns the pooling allocation strategy with slots for `instances` node instances.
+pub(crate) fn pooling_strategy(instances: u32) -> InstanceAllocationStrategy {
+    let mut pooling = PoolingAllocationConfig::default();
+    // Components built by cargo-component consist of several core instances, memories and
+    // tables, such as the WASI adapter's.
+    pooling
+        .total_component_instances(instances)
+        .total_core_instances(instances.saturating_mul(16))
+        .total_memories(instances.saturating_mul(2))
+        .total_tables(instances.saturating_mul(4))
+        .total_stacks(instances);
+    InstanceAllocationStrategy::Pooling(pooling)
+}
+
+/// An idle instance together with what it was created for.
+struct IdleInstance {
+    node_type: String,
+    limits: NodeLimits,
+    capabilities: NodeCapabilities,
+    instance: NodeInstance,
+}
+
+/// Idle node instances kept for reuse by later runs, keyed by node id.
+#[derive(Default)]
+pub(crate) struct InstancePool {
+    idle: Mutex<HashMap<String, Vec<IdleInstance>>>,
+}
+
+impl InstancePool {
+    /// Takes an idle instance of the node created with the same limits and capabilities.
+    pub(crate) fn take(
+        &self,
+        node_id: &str,
+        node_type: &str,
+        limits: NodeLimits,
+        capabilities: &NodeCapabilities,
+    ) -> Option<NodeInstance> {
+        let mut idle = self.idle.lock().unwrap();
+        let instances = idle.get_mut(node_id)?;
+        // Instances created from an outdated configuration are dropped.
+        instances.retain(|idle| {
+            idle.node_type == node_type
+                && idle.limits == limits
+                && idle.capabilities == *capabilities
+        });
+        instances.pop().map(|idle| idle.instance)
+    }
+
+    pub(crate) fn put(
+        &self,
+        node_type: &str,
+        limits: NodeLimits,
+        capabilities: &NodeCapabilities,
+        instance: NodeInstance,
+    ) {
+        self.idle
+            .lock()
+            .unwrap()
+            .entry(instance.node_id().to_string())
+            .or_default()
+            .push(IdleInstance {
+                node_type: node_type.to_string(),
+                limits,
+                capabilities: capabilities.clone(),
+                instance,
+            });
+    }
+
+    /// Drops all idle instances and their stores.
+    pub(crate) fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.idle.lock().unwrap().values().map(Vec::len).sum()
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use cillio_config::{NodeImplementationData, PortType};
+use wasmtime::component::types::ComponentFunc;
+use wasmtime::component::Type;
+
+/// Maps a component model type onto the port type model of graph configs.
+///
+/// Types without a port type equivalent, such as variants, tuples or resources, map to
+/// [`PortType::Any`].
+pub fn port_type_of(ty: &Type) -> PortType {
+    match ty {
+        Type::Bool => PortType::Bool,
+        Type::S8
+        | Type::U8
+        | Type::S16
+        | Type::U16
+        | Type::S32
+        | Type::U32
+        | Type::S64
+        | Type::U64 => PortType::Int,
+        Type::Float32 | Type::Float64 => PortType::Float,
+        Type::Char | Type::String => PortType::String,
+        Type::List(list) => PortType::List(Box::new(port_type_of(&list.ty()))),
+        Type::Option(option) => PortType::Option(Box::new(port_type_of(&option.ty()))),
+        Type::Record(record) => PortType::Record(
+            record
+                .fields()
+                .map(|field| (field.name.to_string(), port_type_of(&field.ty)))
+                .collect(),
+        ),
+        _ => PortType::Any,
+    }
+}
+
+/// Returns the ports of the record a node exchanges through `process`.
+///
+/// Outputs may be returned directly or wrapped in the `ok` case of a `result`.
+fn record_ports(ty: Option<Type>) -> Option<HashMap<String, String>> {
+    match ty? {
+        Type::Record(record) => Some(
+            record
+                .fields()
+                .map(|field| (field.name.to_string(), port_type_of(&field.ty).to_string()))
+                .collect(),
+        ),
+        Type::Result(result) => record_ports(result.ok()),
+        _ => None,
+    }
+}
+
+/// Returns the names of the input ports of a node's `process` function.
+pub fn input_ports(process_fn: &ComponentFunc) -> Vec<String> {
+    match process_fn.params().next() {
+        Some(Type::Record(record)) => record
+            .fields()
+            .map(|field| field.name.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the names and types of the output ports of a node's `process` function.
+pub fn output_types(process_fn: &ComponentFunc) -> Vec<(String, Type)> {
+    match process_fn.results().next().and_then(output_record_type) {
+        Some(Type::Record(record)) => record
+            .fields()
+            .map(|field| (field.name.to_string(), field.ty))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Unwraps the outputs record type from the return type of `process`.
+pub(crate) fn output_record_type(ty: Type) -> Option<Type> {
+    match ty {
+        Type::Record(_) => Some(ty),
+        Type::Result(result) => output_record_type(result.ok()?),
+        _ => None,
+    }
+}
+
+/// Derives the port declarations of a node implementation from its `process` function.
+///
+/// Node state is read through the `get-state` import and cannot be derived, so the
+/// returned `state` is always `None`.
+pub fn implementation_from_process_fn(
+    process_fn: &ComponentFunc,
+    wasm: &str,
+) -> NodeImplementationData {
+    NodeImplementationData {
+        input: record_ports(process_fn.params().next()),
+        output: record_ports(process_fn.results().next()),
+        state: None,
+        wasm: Some(wasm.to_string()),
+        graph: None,
+        native: None,
+        limits: None,
+        capabilities: None,
+        deterministic: true,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// The port is declared in the config but not exposed by the component.
+    MissingInComponent,
+    /// The component exposes a port the config does not declare.
+    MissingInConfig,
+    /// The declared type differs from the component's type.
+    TypeMismatch { declared: String, actual: String },
+}
+
+/// A difference between a declared node implementation and its component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImplementationMismatch {
+    /// Either `input` or `output`.
+    pub section: &'static str,
+    pub port: String,
+    pub kind: MismatchKind,
+}
+
+impl fmt::Display for ImplementationMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            MismatchKind::MissingInComponent => write!(
+                f,
+                "{} port <{}> is declared but not exposed by the component",
+                self.section, self.port
+            ),
+            MismatchKind::MissingInConfig => write!(
+                f,
+                "{} port <{}> is exposed by the component but not declared",
+                self.section, self.port
+            ),
+            MismatchKind::TypeMismatch { declared, actual } => write!(
+                f,
+                "{} port <{}> is declared as <{}> but the component uses <{}>",
+                self.section, self.port, declared, actual
+            ),
+        }
+    }
+}
+
+fn compare_ports(
+    section: &'static str,
+    declared: Option<&HashMap<String, String>>,
+    actual: Option<&HashMap<String, String>>,
+    mismatches: &mut Vec<ImplementationMismatch>,
+) {
+    let empty = HashMap::new();
+    let declared = declared.unwrap_or(&empty);
+    let actual = actual.unwrap_or(&empty);
+
+    let mut ports: Vec<_> = declared.keys().chain(actual.keys()).collect();
+    ports.sort();
+    ports.dedup();
+    for port in ports {
+        let kind = match (declared.get(port), actual.get(port)) {
+            (Some(_), None) => MismatchKind::MissingInComponent,
+            (None, Some(_)) => MismatchKind::MissingInConfig,
+            (Some(declared_type), Some(actual_type)) => {
+                let matches = declared_type
+                    .parse::<PortType>()
+                    .is_ok_and(|declared| declared.to_string() == *actual_type);
+                if matches {
+                    continue;
+                }
+                MismatchKind::TypeMismatch {
+                    declared: declared_type.clone(),
+                    actual: actual_type.clone(),
+                }
+            }
+            (None, None) => continue,
+        };
+        mismatches.push(ImplementationMismatch {
+            section,
+            port: port.clone(),
+            kind,
+        });
+    }
+}
+
+/// Compares the ports of a declared node implementation with the ones derived from the
+/// component and reports every mismatched port.
+pub fn compare_implementations(
+    declared: &NodeImplementationData,
+    derived: &NodeImplementationData,
+) -> Vec<ImplementationMismatch> {
+    let mut mismatches = Vec::new();
+    compare_ports(
+        "input",
+        declared.input.as_ref(),
+        derived.input.as_ref(),
+        &mut mismatches,
+    );
+    compare_ports(
+        "output",
+        declared.output.as_ref(),
+        derived.output.as_ref(),
+        &mut mismatches,
+    );
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::component::types::ComponentItem;
+    use wasmtime::component::Component;
+    use wasmtime::{Config, Engine};
+
+    /// A node whose `process` returns its outputs in the `ok` case of a `result`. It is
+    /// only inspected, never called.
+    const COMPONENT: &str = r#"(component
+        (core module $m
+            (memory (export "mem") 1)
+            (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 0)
+            (func (export "process") (param i32 i32 i32) (result i32) i32.const 0))
+        (core instance $i (instantiate $m))
+        (alias core export $i "mem" (core memory $mem))
+        (alias core export $i "realloc" (core func $realloc))
+        (type $in (record (field "a" u32) (field "b" (list string))))
+        (export $in' "in" (type $in))
+        (type $out (record
+            (field "sum" f64)
+            (field "tag" (option string))
+            (field "pair" (tuple u8 u8))))
+        (export $out' "out" (type $out))
+        (func $f (param "inputs" $in') (result (result $out' (error string)))
+            (canon lift (core func $i "process") (memory $mem) (realloc $realloc)))
+        (export "process" (func $f))
+    )"#;
+
+    fn process_fn() -> ComponentFunc {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let component = Component::new(&engine, COMPONENT).unwrap();
+        let component_type = component.component_type();
+        let (_, item) = component_type
+            .exports(&engine)
+            .find(|(name, _)| *name == "process")
+            .unwrap();
+        match item {
+            ComponentItem::ComponentFunc(func) => func,
+            other => panic!("unexpected export: {:?}", other),
+        }
+    }
+
+    fn ports(ports: &[(&str, &str)]) -> Option<HashMap<String, String>> {
+        Some(
+            ports
+                .iter()
+                .map(|(name, ty)| (name.to_string(), ty.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_port_type_of() {
+        let process_fn = process_fn();
+        let types: HashMap<_, _> = output_types(&process_fn)
+            .into_iter()
+            .map(|(name, ty)| (name, port_type_of(&ty)))
+            .collect();
+        assert_eq!(types["sum"], PortType::Float);
+        assert_eq!(types["tag"], PortType::Option(Box::new(PortType::String)));
+        assert_eq!(types["pair"], PortType::Any);
+
+        let inputs = port_type_of(&process_fn.params().next().unwrap());
+        assert_eq!(inputs, "record<a: int, b: list<string>>".parse().unwrap());
+        assert_eq!(input_ports(&process_fn), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_implementation_from_process_fn() {
+        let implementation = implementation_from_process_fn(&process_fn(), "node.wasm");
+        assert_eq!(
+            implementation.input,
+            ports(&[("a", "int"), ("b", "list<string>")])
+        );
+        // The outputs record is unwrapped from the `result`.
+        assert_eq!(
+            implementation.output,
+            ports(&[("sum", "float"), ("tag", "option<string>"), ("pair", "any")])
+        );
+        assert_eq!(implementation.wasm.as_deref(), Some("node.wasm"));
+        assert_eq!(implementation.state, None);
+    }
+
+    #[test]
+    fn test_compare_implementations() {
+        let derived = implementation_from_process_fn(&process_fn(), "node.wasm");
+        assert_eq!(compare_implementations(&derived, &derived), vec![]);
+
+        let declared = NodeImplementationData {
+            input: ports(&[("a", "int"), ("c", "bool")]),
+            output: ports(&[("sum", "string"), ("tag", "string?"), ("pair", "any")]),
+            ..derived.clone()
+        };
+        let mismatches = compare_implementations(&declared, &derived);
+        assert_eq!(
+            mismatches,
+            [
+                ImplementationMismatch {
+                    section: "input",
+                    port: "b".to_string(),
+                    kind: MismatchKind::MissingInConfig,
+                },
+                ImplementationMismatch {
+                    section: "input",
+                    port: "c".to_string(),
+                    kind: MismatchKind::MissingInComponent,
+                },
+                ImplementationMismatch {
+                    section: "output",
+                    port: "sum".to_string(),
+                    kind: MismatchKind::TypeMismatch {
+                        declared: "string".to_string(),
+                        actual: "float".to_string(),
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            mismatches[2].to_string(),
+            "output port <sum> is declared as <string> but the component uses <float>"
+        );
+    }
+}
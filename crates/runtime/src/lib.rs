@@ -1,22 +1,54 @@
 #![feature(iterator_try_collect)]
 
+pub mod batch;
+mod capabilities;
+pub mod component_cache;
+pub mod compose;
+pub mod convert;
+pub mod description;
 pub mod execution_plan;
+pub mod generic;
+pub mod instance_pool;
+pub mod introspect;
+pub mod limits;
+pub mod logs;
+pub mod native;
+pub mod node_instance;
+pub mod node_ports;
+pub mod result_cache;
 pub mod runtime_data;
+pub mod streaming;
+#[cfg(test)]
+mod test_utils;
 
 use anyhow::Context;
 use cillio::node::host::{Host, State};
+use cillio_config::{NodeCapabilities, NodeDescription, NodeImplementationData, NodeLimits};
 use component::types::{ComponentFunc, ComponentItem};
-use runtime_data::RuntimeData;
+use component_cache::ComponentCache;
+use convert::{json_to_val, val_to_json};
+use instance_pool::{InstancePool, InstanceReuse, RuntimeConfig};
+use introspect::ImplementationMismatch;
+use limits::{EpochTicker, Limit, MemoryLimiter};
+use logs::{NodeLogs, OutputCapture};
+use native::{LoadedNative, NativeInstance, NativeRegistry};
+use node_instance::NodeInstance;
+use node_ports::NodePorts;
+use result_cache::ResultCache;
+use runtime_data::{PortValues, RuntimeData};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::time::Instant;
 use thiserror::Error;
-use wasmtime::component::{Component, Instance, Linker};
+use wasmtime::component::{Component, Linker};
 use wasmtime::Engine;
 use wasmtime::*;
 use wasmtime_wasi::*;
 
 wasmtime::component::bindgen!({
-    path: "../../wit/node/world.wit",
+    path: "../../wit/node",
     world: "node",
     async: true,
 });
@@ -26,6 +58,9 @@ pub enum RuntimeError {
     #[error("Failed to load WASM module: {0}")]
     WasmLoadError(#[from] anyhow::Error),
 
+    #[error("Failed to create engine: {0}")]
+    EngineError(String),
+
     #[error("Failed to initialize node: {0}")]
     NodeInitializationError(String),
 
@@ -37,12 +72,28 @@ pub enum RuntimeError {
 
     #[error("File read error: {0}")]
     FileReadError(#[from] std::io::Error),
+
+    #[error("Failed to encode node state: {0}")]
+    StateEncodingError(#[from] serde_json::Error),
+
+    #[error("Failed to convert value: {0}")]
+    ConversionError(#[from] convert::ConversionError),
+
+    #[error("Node <{node}> exceeded its {limit}")]
+    LimitExceeded { node: String, limit: Limit },
 }
 
+/// Store data of a single node instance.
+///
+/// The node state is kept as JSON encoded UTF-8 bytes and is handed to the guest through
+/// the `cillio:node/host.get-state` import.
 pub struct ServerWasiView {
     table: ResourceTable,
     ctx: WasiCtx,
-    nodes_state: HashMap<String, Vec<u8>>,
+    node_id: String,
+    state: Option<Vec<u8>>,
+    limiter: MemoryLimiter,
+    capture: OutputCapture,
 }
 
 impl std::fmt::Debug for ServerWasiView {
@@ -50,23 +101,52 @@ impl std::fmt::Debug for ServerWasiView {
         f.debug_struct("ServerWasiView")
             .field("table", &self.table)
             .field("ctx", &"WasiCtx")
-            .field("nodes_state", &self.nodes_state)
+            .field("node_id", &self.node_id)
+            .field("state", &self.state)
+            .field("memory_limit_exceeded", &self.limiter.exceeded())
+            .field("capture", &self.capture)
             .finish()
     }
 }
 
 impl ServerWasiView {
-    fn new() -> Self {
+    fn new(
+        node_id: &str,
+        state: Option<Vec<u8>>,
+        max_memory_bytes: Option<usize>,
+        ctx: WasiCtx,
+        capture: OutputCapture,
+    ) -> Self {
         let table = ResourceTable::new();
-        let ctx = WasiCtxBuilder::new().inherit_stdio().build();
-        let nodes_state = HashMap::new();
 
         Self {
             table,
             ctx,
-            nodes_state,
+            node_id: node_id.to_string(),
+            state,
+            limiter: MemoryLimiter::new(max_memory_bytes),
+            capture,
         }
     }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Replaces the JSON encoded state returned by `get-state`.
+    pub fn set_state(&mut self, state: Option<Vec<u8>>) {
+        self.state = state;
+    }
+
+    /// Returns the lines the node wrote to captured streams since the last call.
+    pub fn take_logs(&mut self) -> NodeLogs {
+        self.capture.take_logs()
+    }
+
+    /// Whether a memory of the node tried to grow beyond its limit.
+    pub fn memory_limit_exceeded(&self) -> bool {
+        self.limiter.exceeded()
+    }
 }
 
 impl WasiView for ServerWasiView {
@@ -95,26 +175,51 @@ impl Host for ServerWasiView {
         'life0: 'async_trait,
         Self: 'async_trait,
     {
-        todo!()
+        Box::pin(async move { self.state.clone() })
     }
 }
 
 pub struct Runtime {
     engine: Engine,
     linker: Linker<ServerWasiView>,
-    store: Store<ServerWasiView>,
     components: HashMap<String, Component>,
+    /// SHA-256 of the bytes of each loaded component.
+    component_hashes: HashMap<String, String>,
+    /// Native nodes loaded for node types instead of components.
+    native_nodes: HashMap<String, LoadedNative>,
+    native_registry: NativeRegistry,
+    /// Described ports of the loaded components of the generic node world.
+    generic_ports: HashMap<String, NodePorts>,
+    /// What the loaded components with a `describe` export returned.
+    descriptions: HashMap<String, NodeDescription>,
+    component_cache: Option<ComponentCache>,
+    result_cache: Option<ResultCache>,
     runtime_data: RuntimeData,
+    instance_reuse: InstanceReuse,
+    instance_pool: InstancePool,
+    /// Ticks while the runtime enforces fuel and timeout limits.
+    epoch_ticker: Option<EpochTicker>,
 }
 
 impl Runtime {
     pub fn new() -> Self {
+        Self::from_config(RuntimeConfig::default()).unwrap()
+    }
+
+    pub fn from_config(runtime_config: RuntimeConfig) -> Result<Self, RuntimeError> {
         let mut config = Config::default();
         config.wasm_component_model(true);
         config.async_support(true);
-        let engine = Engine::new(&config).unwrap();
-        let wasi_view = ServerWasiView::new();
-        let store = Store::new(&engine, wasi_view);
+        config.consume_fuel(runtime_config.enforce_limits);
+        config.epoch_interruption(runtime_config.enforce_limits);
+        if let Some(instances) = runtime_config.pooling_allocator {
+            config.allocation_strategy(instance_pool::pooling_strategy(instances));
+        }
+        let engine =
+            Engine::new(&config).map_err(|err| RuntimeError::EngineError(format!("{:#}", err)))?;
+        let epoch_ticker = runtime_config
+            .enforce_limits
+            .then(|| EpochTicker::start(&engine));
         let components = HashMap::new();
         let mut linker = Linker::new(&engine);
         let runtime_data = RuntimeData::new();
@@ -127,13 +232,73 @@ impl Runtime {
             .context("Failed to link command world")
             .unwrap();
 
-        Self {
+        Ok(Self {
             engine,
             linker,
-            store,
             components,
+            component_hashes: HashMap::new(),
+            native_nodes: HashMap::new(),
+            native_registry: NativeRegistry::default(),
+            generic_ports: HashMap::new(),
+            descriptions: HashMap::new(),
+            component_cache: None,
+            result_cache: None,
             runtime_data,
-        }
+            instance_reuse: runtime_config.instance_reuse,
+            instance_pool: InstancePool::default(),
+            epoch_ticker,
+        })
+    }
+
+    pub fn instance_reuse(&self) -> InstanceReuse {
+        self.instance_reuse
+    }
+
+    /// Reuses compiled components from the given on-disk cache.
+    pub fn with_component_cache(mut self, component_cache: ComponentCache) -> Self {
+        self.component_cache = Some(component_cache);
+        self
+    }
+
+    /// Reuses the results of deterministic nodes from the given on-disk cache.
+    pub fn with_result_cache(mut self, result_cache: ResultCache) -> Self {
+        self.result_cache = Some(result_cache);
+        self
+    }
+
+    pub fn result_cache(&self) -> Option<&ResultCache> {
+        self.result_cache.as_ref()
+    }
+
+    /// Replaces the registry native nodes are loaded from, the standard nodes by default.
+    pub fn with_native_registry(mut self, native_registry: NativeRegistry) -> Self {
+        self.native_registry = native_registry;
+        self
+    }
+
+    pub fn native_registry(&self) -> &NativeRegistry {
+        &self.native_registry
+    }
+
+    /// Implements the node type `id` by the native node registered as `name`.
+    pub fn load_native(&mut self, id: &str, name: &str) -> Result<(), RuntimeError> {
+        let node = self.native_registry.get(name).ok_or_else(|| {
+            RuntimeError::NodeNotFoundError(format!("Native node <{}> is not registered", name))
+        })?;
+        self.components.remove(id);
+        self.generic_ports.remove(id);
+        self.descriptions.remove(id);
+        // The version of the node stands in for the bytes of a component.
+        let hash = match node.version() {
+            Some(version) => format!("native:{}@{}", name, version),
+            None => format!("native:{}", name),
+        };
+        self.native_nodes
+            .insert(id.to_string(), LoadedNative::new(name, node));
+        self.component_hashes.insert(id.to_string(), hash);
+        self.runtime_data.clear();
+        self.instance_pool.clear();
+        Ok(())
     }
 
     pub async fn load_component(
@@ -142,33 +307,233 @@ impl Runtime {
         bytes: &Vec<u8>,
     ) -> Result<Component, anyhow::Error> {
         let start_time = Instant::now();
-        println!("-- Component from bytes...");
-        let component =
-            Component::new(&self.engine, bytes).context("Failed to load component from binary")?;
-        println!("-- Time taken: {} ms", start_time.elapsed().as_millis());
+        let cached = self
+            .component_cache
+            .as_ref()
+            .and_then(|cache| cache.load(&self.engine, bytes));
+        let from_cache = cached.is_some();
+        let component = match cached {
+            Some(component) => component,
+            None => {
+                let component = Component::new(&self.engine, bytes)
+                    .context("Failed to load component from binary")?;
+                if let Some(cache) = &self.component_cache {
+                    if let Err(err) = cache.store(&self.engine, bytes, &component) {
+                        tracing::warn!(id, "Failed to cache component: {}", err);
+                    }
+                }
+                component
+            }
+        };
+        tracing::debug!(
+            id,
+            from_cache,
+            elapsed_ms = start_time.elapsed().as_millis() as u64,
+            "Loaded component"
+        );
         // self.get_component_run_fn(id)
         //     .ok_or(anyhow::anyhow!("Component run function not found: {}", id))?;
+        self.native_nodes.remove(id);
+        self.generic_ports.remove(id);
+        self.descriptions.remove(id);
         self.components.insert(id.to_string(), component.clone());
+        self.component_hashes
+            .insert(id.to_string(), format!("{:x}", Sha256::digest(bytes)));
+        let generic = self
+            .get_component_run_fn(id)
+            .is_some_and(|process_fn| generic::is_generic(process_fn.params().next().as_ref()));
+        if self
+            .get_component_fn(id, description::DESCRIBE_FN_NAME)
+            .is_some()
+        {
+            let description = self.describe(id).await?;
+            if generic {
+                let ports = NodePorts::from_description(&description).map_err(|err| {
+                    RuntimeError::NodeInitializationError(format!("{}: {}", id, err))
+                })?;
+                self.generic_ports.insert(id.to_string(), ports);
+            }
+            self.descriptions.insert(id.to_string(), description);
+        } else if generic {
+            return Err(RuntimeError::NodeInitializationError(format!(
+                "{}: generic node has no <{}> export",
+                id,
+                description::DESCRIBE_FN_NAME
+            ))
+            .into());
+        }
+        // Results and instances of the previous execution may stem from the replaced component.
+        self.runtime_data.clear();
+        self.instance_pool.clear();
         Ok(component)
     }
 
+    /// Instantiates a loaded component and calls its `describe` export.
+    async fn describe(&self, id: &str) -> Result<NodeDescription, RuntimeError> {
+        let mut instance = self
+            .initialize_node(
+                id,
+                id,
+                None::<&Value>,
+                NodeLimits::default(),
+                &NodeCapabilities::default(),
+            )
+            .await?;
+        let description = instance
+            .describe()
+            .await?
+            .ok_or_else(|| RuntimeError::NodeNotFoundError(format!("Component <{}>", id)))?;
+        description::description_from_val(&description)
+            .map_err(|err| RuntimeError::NodeInitializationError(format!("{}: {}", id, err)))
+    }
+
+    /// Returns the ports of a loaded native node or component of the generic node world.
+    fn node_ports(&self, id: &str) -> Option<&NodePorts> {
+        self.native_nodes
+            .get(id)
+            .map(|native| &native.ports)
+            .or_else(|| self.generic_ports.get(id))
+    }
+
     pub fn get_component(&self, id: &str) -> Option<&Component> {
         self.components.get(id)
     }
 
+    /// Returns the description of a loaded component with a `describe` export, or of a
+    /// native node.
+    pub fn node_description(&self, id: &str) -> Option<NodeDescription> {
+        match self.native_nodes.get(id) {
+            Some(native) => Some(native.description()),
+            None => self.descriptions.get(id).cloned(),
+        }
+    }
+
     pub fn get_component_run_fn(&self, id: &str) -> Option<ComponentFunc> {
+        self.get_component_fn(id, "process")
+    }
+
+    fn get_component_fn(&self, id: &str, name: &str) -> Option<ComponentFunc> {
         let component = self.components.get(id)?;
         let component_type = component.component_type();
         let component_item = component_type
             .exports(&self.engine)
-            .find(|x| x.0 == "run")
-            .unwrap();
+            .find(|(export, _)| *export == name)?;
         match component_item {
             (_, ComponentItem::ComponentFunc(handle)) => Some(handle),
             _ => None,
         }
     }
 
+    /// Derives the node implementation of a loaded component from its description, or
+    /// from its `process` export if it has none, or of a native node from its ports.
+    pub fn describe_component(
+        &self,
+        id: &str,
+        wasm: &str,
+    ) -> Result<NodeImplementationData, RuntimeError> {
+        if let Some(native) = self.native_nodes.get(id) {
+            return Ok(native.implementation());
+        }
+        if let Some(description) = self.descriptions.get(id) {
+            return Ok(description::implementation_from_description(
+                description,
+                wasm,
+            ));
+        }
+        let process_fn = self.get_component_run_fn(id).ok_or_else(|| {
+            RuntimeError::NodeNotFoundError(format!("Component <{}> has no process export", id))
+        })?;
+        Ok(introspect::implementation_from_process_fn(
+            &process_fn,
+            wasm,
+        ))
+    }
+
+    /// Returns the input ports of a loaded component or native node.
+    pub fn input_ports(&self, id: &str) -> Result<Vec<String>, RuntimeError> {
+        if let Some(ports) = self.node_ports(id) {
+            return Ok(ports.input_names());
+        }
+        let process_fn = self.get_component_run_fn(id).ok_or_else(|| {
+            RuntimeError::NodeNotFoundError(format!("Component <{}> has no process export", id))
+        })?;
+        Ok(introspect::input_ports(&process_fn))
+    }
+
+    /// Whether results of a node type may be taken from the result cache: those of components
+    /// and of native nodes with a [`native::NativeNode::version`].
+    pub fn caches_results(&self, id: &str) -> bool {
+        self.native_nodes
+            .get(id)
+            .is_none_or(|native| native.node.version().is_some())
+    }
+
+    /// Returns the SHA-256 of the bytes of a loaded component, or the name and version of a
+    /// native node.
+    pub fn component_hash(&self, id: &str) -> Result<&str, RuntimeError> {
+        self.component_hashes
+            .get(id)
+            .map(String::as_str)
+            .ok_or_else(|| RuntimeError::NodeNotFoundError(format!("Node not found: {}", id)))
+    }
+
+    /// Converts the output port values of a loaded component or native node into JSON.
+    pub fn outputs_to_json(
+        &self,
+        id: &str,
+        outputs: &PortValues,
+    ) -> Result<Map<String, Value>, RuntimeError> {
+        if let Some(ports) = self.node_ports(id) {
+            return Ok(ports.outputs_to_json(outputs));
+        }
+        let mut values = Map::new();
+        for (port, ty) in self.output_types(id)? {
+            if let Some(value) = outputs.get(&port) {
+                let json = val_to_json(value, &ty, &format!("outputs.{}", port))?;
+                values.insert(port, json);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Converts JSON output values back into the output port values of a loaded component
+    /// or native node.
+    pub fn outputs_from_json(
+        &self,
+        id: &str,
+        outputs: &Map<String, Value>,
+    ) -> Result<PortValues, RuntimeError> {
+        if let Some(ports) = self.node_ports(id) {
+            return Ok(ports.outputs_from_json(outputs)?);
+        }
+        let mut values = PortValues::new();
+        for (port, ty) in self.output_types(id)? {
+            if let Some(value) = outputs.get(&port) {
+                let val = json_to_val(value, &ty, &format!("outputs.{}", port))?;
+                values.insert(port, val);
+            }
+        }
+        Ok(values)
+    }
+
+    fn output_types(&self, id: &str) -> Result<Vec<(String, component::Type)>, RuntimeError> {
+        let process_fn = self.get_component_run_fn(id).ok_or_else(|| {
+            RuntimeError::NodeNotFoundError(format!("Component <{}> has no process export", id))
+        })?;
+        Ok(introspect::output_types(&process_fn))
+    }
+
+    /// Verifies that a declared node implementation matches the loaded component or native
+    /// node.
+    pub fn verify_component(
+        &self,
+        id: &str,
+        declared: &NodeImplementationData,
+    ) -> Result<Vec<ImplementationMismatch>, RuntimeError> {
+        let derived = self.describe_component(id, declared.wasm.as_deref().unwrap_or(id))?;
+        Ok(introspect::compare_implementations(declared, &derived))
+    }
+
     // pub async fn load_graph(
     //     &mut self,
     //     path: PathBuf,
@@ -187,27 +552,129 @@ impl Runtime {
     //     ret
     // }
 
-    pub fn get_store(&self) -> &Store<ServerWasiView> {
-        &self.store
-    }
-
     pub fn get_linker(&self) -> &Linker<ServerWasiView> {
         &self.linker
     }
 
-    pub async fn initialize_node<S>(
-        &mut self,
+    pub fn get_runtime_data(&self) -> &RuntimeData {
+        &self.runtime_data
+    }
+
+    /// Instantiates the component of `node_type` for the node instance `node_id` in its
+    /// own store.
+    ///
+    /// The given state is serialized to JSON and returned by `get-state` while the node runs.
+    /// The limits apply to every `process` call of the instance, and the node gets a WASI
+    /// context of its own that grants nothing but the given capabilities.
+    ///
+    /// Native node types get an instance of their native node, to which limits and
+    /// capabilities do not apply.
+    pub async fn initialize_node<S: Serialize>(
+        &self,
         node_id: &str,
-        _state: Option<S>,
-    ) -> Result<Instance, RuntimeError> {
-        let component = self.components.get(node_id).ok_or_else(|| {
-            RuntimeError::NodeNotFoundError(format!("Node not found: {}", node_id))
+        node_type: &str,
+        state: Option<&S>,
+        limits: NodeLimits,
+        capabilities: &NodeCapabilities,
+    ) -> Result<NodeInstance, RuntimeError> {
+        if let Some(native) = self.native_nodes.get(node_type) {
+            return Ok(NodeInstance::native(NativeInstance::new(
+                node_id,
+                native.clone(),
+                state,
+            )?));
+        }
+        let component = self.components.get(node_type).ok_or_else(|| {
+            RuntimeError::NodeNotFoundError(format!("Node not found: {}", node_type))
         })?;
-        let instance = self
-            .linker
-            .instantiate_async(&mut self.store, component)
-            .await?;
-        Ok(instance)
+        let enforce_limits = self.epoch_ticker.is_some();
+        if !enforce_limits && limits.needs_enforcement() {
+            return Err(RuntimeError::NodeInitializationError(format!(
+                "{}: fuel and timeout limits need a runtime that enforces limits",
+                node_id
+            )));
+        }
+        let state = state.map(serde_json::to_vec).transpose()?;
+        let (ctx, capture) = capabilities::wasi_ctx(node_id, capabilities)?;
+        let mut store = Store::new(
+            &self.engine,
+            ServerWasiView::new(node_id, state, limits.max_memory_bytes, ctx, capture),
+        );
+        store.limiter(|view| &mut view.limiter);
+        if enforce_limits {
+            store.set_fuel(u64::MAX)?;
+            store.epoch_deadline_async_yield_and_update(1);
+        }
+        let instance = match self.linker.instantiate_async(&mut store, component).await {
+            Ok(instance) => instance,
+            Err(_) if store.data().memory_limit_exceeded() => {
+                return Err(RuntimeError::LimitExceeded {
+                    node: node_id.to_string(),
+                    limit: Limit::Memory(limits.max_memory_bytes.unwrap_or_default()),
+                })
+            }
+            Err(err) => return Err(err.into()),
+        };
+        NodeInstance::new(
+            store,
+            instance,
+            limits,
+            enforce_limits,
+            self.generic_ports.get(node_type).cloned(),
+        )
+    }
+
+    /// Returns an instance for the node, taken from the instance pool when instances are
+    /// pooled and an idle one exists, or initialized otherwise.
+    ///
+    /// A pooled instance gets the given state but keeps the contents of its memory.
+    pub async fn acquire_node<S: Serialize>(
+        &self,
+        node_id: &str,
+        node_type: &str,
+        state: Option<&S>,
+        limits: NodeLimits,
+        capabilities: &NodeCapabilities,
+    ) -> Result<NodeInstance, RuntimeError> {
+        if self.instance_reuse == InstanceReuse::Pooled {
+            if let Some(mut instance) =
+                self.instance_pool
+                    .take(node_id, node_type, limits, capabilities)
+            {
+                instance.set_state(state)?;
+                return Ok(instance);
+            }
+        }
+        self.initialize_node(node_id, node_type, state, limits, capabilities)
+            .await
+    }
+
+    /// Hands an instance back after a successful run. It is kept in the instance pool when
+    /// instances are pooled and dropped together with its store otherwise.
+    ///
+    /// Instances whose last call failed must not be released, as a trap can leave them in
+    /// an unusable state.
+    pub fn release_node(
+        &self,
+        node_type: &str,
+        limits: NodeLimits,
+        capabilities: &NodeCapabilities,
+        instance: NodeInstance,
+    ) {
+        if self.instance_reuse == InstanceReuse::Pooled {
+            self.instance_pool
+                .put(node_type, limits, capabilities, instance);
+        }
+    }
+
+    /// Returns the number of idle instances in the instance pool.
+    pub fn idle_instances(&self) -> usize {
+        self.instance_pool.len()
+    }
+
+    /// Drops all idle instances and their stores.
+    pub fn clear_instance_pool(&self) {
+        self.instance_pool.clear();
     }
 }
 
@@ -0,0 +1,216 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use thiserror::Error;
+use wasmtime::{Engine, ResourceLimiter, StoreLimits, StoreLimitsBuilder};
+
+/// Interval in which the engine epoch is incremented.
+pub(crate) const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// A node limit that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Fuel(u64),
+    Timeout(Duration),
+    Memory(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Fuel(fuel) => write!(f, "fuel budget of {}", fuel),
+            Limit::Timeout(timeout) => write!(f, "timeout of {} ms", timeout.as_millis()),
+            Limit::Memory(bytes) => write!(f, "memory limit of {} bytes", bytes),
+        }
+    }
+}
+
+/// Trap raised from the epoch deadline callback once a node runs past its timeout.
+#[derive(Error, Debug)]
+#[error("node timed out")]
+pub(crate) struct TimeoutExceeded;
+
+/// Limits the linear memory of a node and remembers whether the limit was hit.
+pub(crate) struct MemoryLimiter {
+    limits: StoreLimits,
+    exceeded: bool,
+}
+
+impl MemoryLimiter {
+    pub(crate) fn new(max_memory_bytes: Option<usize>) -> Self {
+        let mut builder = StoreLimitsBuilder::new().trap_on_grow_failure(true);
+        if let Some(max_memory_bytes) = max_memory_bytes {
+            builder = builder.memory_size(max_memory_bytes);
+        }
+        Self {
+            limits: builder.build(),
+            exceeded: false,
+        }
+    }
+
+    pub(crate) fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        let result = self.limits.memory_growing(current, desired, maximum);
+        self.exceeded |= result.is_err();
+        result
+    }
+
+    fn table_growing(
+        &mut self,
+        current: u32,
+        desired: u32,
+        maximum: Option<u32>,
+    ) -> anyhow::Result<bool> {
+        self.limits.table_growing(current, desired, maximum)
+    }
+}
+
+/// Increments the engine epoch every [`EPOCH_TICK`] until dropped.
+pub(crate) struct EpochTicker {
+    stopped: Arc<AtomicBool>,
+}
+
+impl EpochTicker {
+    pub(crate) fn start(engine: &Engine) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let engine = engine.clone();
+        let ticker_stopped = stopped.clone();
+        thread::spawn(move || {
+            while !ticker_stopped.load(Ordering::Relaxed) {
+                thread::sleep(EPOCH_TICK);
+                engine.increment_epoch();
+            }
+        });
+        Self { stopped }
+    }
+}
+
+impl Drop for EpochTicker {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cillio_config::NodeLimits;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::instance_pool::RuntimeConfig;
+    use crate::runtime_data::PortValues;
+    use crate::{Runtime, RuntimeError};
+
+    const SPIN: &str = r#"(component
+        (core module $m
+            (func (export "spin") (loop $l (br $l))))
+        (core instance $i (instantiate $m))
+        (func (export "process") (canon lift (core func $i "spin")))
+    )"#;
+
+    /// Grows its memory from one to eleven pages.
+    const GROW: &str = r#"(component
+        (core module $m
+            (memory 1)
+            (func (export "grow") (drop (memory.grow (i32.const 10)))))
+        (core instance $i (instantiate $m))
+        (func (export "process") (canon lift (core func $i "grow")))
+    )"#;
+
+    fn runtime(enforce_limits: bool) -> Runtime {
+        Runtime::from_config(RuntimeConfig {
+            enforce_limits,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn exceeded_limit(component: &str, limits: NodeLimits) -> Limit {
+        let mut runtime = runtime(limits.needs_enforcement());
+        block_on(runtime.load_component("node", &component.as_bytes().to_vec())).unwrap();
+        let mut instance = block_on(runtime.initialize_node::<()>(
+            "node",
+            "node",
+            None,
+            limits,
+            &Default::default(),
+        ))
+        .unwrap();
+        match block_on(instance.process(&PortValues::new())) {
+            Err(RuntimeError::LimitExceeded { node, limit }) => {
+                assert_eq!(node, "node");
+                limit
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_fuel_limit() {
+        let limits = NodeLimits {
+            fuel: Some(10_000),
+            ..Default::default()
+        };
+        assert_eq!(exceeded_limit(SPIN, limits), Limit::Fuel(10_000));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let limits = NodeLimits {
+            timeout_ms: Some(50),
+            ..Default::default()
+        };
+        assert_eq!(
+            exceeded_limit(SPIN, limits),
+            Limit::Timeout(Duration::from_millis(50))
+        );
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut limiter = MemoryLimiter::new(Some(2 << 16));
+        assert!(limiter.memory_growing(1 << 16, 2 << 16, None).unwrap());
+        assert!(limiter.memory_growing(2 << 16, 12 << 16, None).is_err());
+        assert!(limiter.exceeded());
+
+        let limits = NodeLimits {
+            max_memory_bytes: Some(2 << 16),
+            ..Default::default()
+        };
+        assert_eq!(exceeded_limit(GROW, limits), Limit::Memory(2 << 16));
+    }
+
+    #[test]
+    fn test_limits_need_enforcing_runtime() {
+        let mut runtime = runtime(false);
+        block_on(runtime.load_component("spin", &SPIN.as_bytes().to_vec())).unwrap();
+        let limits = NodeLimits {
+            timeout_ms: Some(50),
+            ..Default::default()
+        };
+        let result = block_on(runtime.initialize_node::<()>(
+            "node",
+            "spin",
+            None,
+            limits,
+            &Default::default(),
+        ));
+        assert!(matches!(
+            result,
+            Err(RuntimeError::NodeInitializationError(_))
+        ));
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use wasmtime_wasi::{HostOutputStream, StdoutStream, StreamResult, Subscribe};
+
+/// Maximum number of bytes buffered per stream of a node instance between two reads of its
+/// logs. Output beyond it is dropped.
+const CAPTURE_CAPACITY: usize = 1 << 20;
+
+/// Lines a node wrote to its standard streams.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeLogs {
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+impl NodeLogs {
+    pub fn is_empty(&self) -> bool {
+        self.stdout.is_empty() && self.stderr.is_empty()
+    }
+
+    /// Emits every line as a `tracing` event of the `cillio::node` target.
+    pub fn emit(&self, node_id: &str, node_type: &str, run_id: &str) {
+        for line in &self.stdout {
+            tracing::info!(
+                target: "cillio::node",
+                node_id,
+                node_type,
+                run_id,
+                stream = "stdout",
+                "{}",
+                line
+            );
+        }
+        for line in &self.stderr {
+            tracing::warn!(
+                target: "cillio::node",
+                node_id,
+                node_type,
+                run_id,
+                stream = "stderr",
+                "{}",
+                line
+            );
+        }
+    }
+}
+
+/// An output stream of a node instance that buffers what the node writes until the logs
+/// are taken.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CaptureStream {
+    buffer: Arc<Mutex<CaptureBuffer>>,
+}
+
+#[derive(Debug, Default)]
+struct CaptureBuffer {
+    bytes: Vec<u8>,
+    /// Number of bytes dropped since the logs were last taken because the buffer was full.
+    dropped: usize,
+}
+
+impl CaptureStream {
+    /// Removes the complete lines from the buffer. A trailing partial line is kept until
+    /// its newline arrives, unless output was dropped, which is then noted in a last line.
+    fn take_lines(&self) -> Vec<String> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let end = if buffer.dropped > 0 {
+            buffer.bytes.len()
+        } else {
+            match buffer.bytes.iter().rposition(|&byte| byte == b'\n') {
+                Some(end) => end + 1,
+                None => return Vec::new(),
+            }
+        };
+        let complete: Vec<u8> = buffer.bytes.drain(..end).collect();
+        let mut lines: Vec<String> = String::from_utf8_lossy(&complete)
+            .lines()
+            .map(String::from)
+            .collect();
+        if buffer.dropped > 0 {
+            lines.push(format!(
+                "[output truncated, {} bytes dropped]",
+                buffer.dropped
+            ));
+            buffer.dropped = 0;
+        }
+        lines
+    }
+}
+
+impl HostOutputStream for CaptureStream {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let kept = bytes.len().min(CAPTURE_CAPACITY - buffer.bytes.len());
+        buffer.bytes.extend_from_slice(&bytes[..kept]);
+        buffer.dropped += bytes.len() - kept;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        // Writes never fail or block, output beyond the capacity is dropped instead.
+        Ok(CAPTURE_CAPACITY)
+    }
+}
+
+impl Subscribe for CaptureStream {
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    fn ready<'life0, 'async_trait>(
+        &'life0 mut self,
+    ) -> ::core::pin::Pin<
+        Box<dyn ::core::future::Future<Output = ()> + ::core::marker::Send + 'async_trait>,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        // Writes never block.
+        Box::pin(async move {})
+    }
+}
+
+impl StdoutStream for CaptureStream {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn isatty(&self) -> bool {
+        false
+    }
+}
+
+/// In-memory stdout and stderr of a node instance.
+#[derive(Debug, Default)]
+pub(crate) struct OutputCapture {
+    stdout: Option<CaptureStream>,
+    stderr: Option<CaptureStream>,
+}
+
+impl OutputCapture {
+    /// Creates a stream to capture stdout into.
+    pub(crate) fn stdout_stream(&mut self) -> CaptureStream {
+        self.stdout
+            .get_or_insert_with(CaptureStream::default)
+            .clone()
+    }
+
+    /// Creates a stream to capture stderr into.
+    pub(crate) fn stderr_stream(&mut self) -> CaptureStream {
+        self.stderr
+            .get_or_insert_with(CaptureStream::default)
+            .clone()
+    }
+
+    /// Returns the lines written since the last call.
+    pub(crate) fn take_logs(&mut self) -> NodeLogs {
+        NodeLogs {
+            stdout: self
+                .stdout
+                .as_ref()
+                .map(CaptureStream::take_lines)
+                .unwrap_or_default(),
+            stderr: self
+                .stderr
+                .as_ref()
+                .map(CaptureStream::take_lines)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_logs() {
+        let mut capture = OutputCapture::default();
+        let mut stdout = capture.stdout_stream();
+        stdout.write(Bytes::from("first\nsecond ")).unwrap();
+        assert_eq!(capture.take_logs().stdout, ["first"]);
+        stdout.write(Bytes::from("line\n")).unwrap();
+        assert_eq!(capture.take_logs().stdout, ["second line"]);
+        assert!(capture.take_logs().is_empty());
+
+        // Taken lines free the buffer, so a long-lived instance never runs out of capacity.
+        let line = Bytes::from(vec![b'x'; CAPTURE_CAPACITY / 2 - 1]);
+        for _ in 0..4 {
+            stdout.write(line.clone()).unwrap();
+            stdout.write(Bytes::from("\n")).unwrap();
+            assert_eq!(capture.take_logs().stdout.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_truncate_logs() {
+        let mut capture = OutputCapture::default();
+        let mut stdout = capture.stdout_stream();
+
+        // Output beyond the capacity is dropped without failing the node.
+        let full = Bytes::from(vec![b'x'; CAPTURE_CAPACITY]);
+        stdout.write(full).unwrap();
+        stdout.write(Bytes::from("dropped\n")).unwrap();
+        assert_eq!(stdout.check_write().unwrap(), CAPTURE_CAPACITY);
+        let lines = capture.take_logs().stdout;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), CAPTURE_CAPACITY);
+        assert_eq!(lines[1], "[output truncated, 8 bytes dropped]");
+        stdout.write(Bytes::from("next\n")).unwrap();
+        assert_eq!(capture.take_logs().stdout, ["next"]);
+    }
+}
@@ -0,0 +1,424 @@
+//! Nodes implemented in Rust and run by the host instead of a component.
+//!
+//! A native node keeps the contract of a component: it reads its state, takes its inputs
+//! record and returns its outputs record. A node implementation selects one by its name in
+//! a [`NativeRegistry`] with `native` instead of `wasm`, e.g. `"native": "std/add"`.
+//!
+//! Native nodes are not sandboxed, so the limits and capabilities of the node do not apply.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use cillio_config::{NodeDescription, NodeImplementationData, PortDescription, PortType};
+use serde::Serialize;
+use serde_json::Value;
+use wasmtime::component::Val;
+
+use crate::logs::NodeLogs;
+use crate::node_ports::NodePorts;
+use crate::runtime_data::PortValues;
+use crate::RuntimeError;
+
+/// A node implemented in Rust.
+pub trait NativeNode: Send + Sync {
+    /// Returns the input ports and their types.
+    fn input_ports(&self) -> Vec<(String, PortType)>;
+
+    /// Returns the output ports and their types.
+    fn output_ports(&self) -> Vec<(String, PortType)>;
+
+    /// Computes the outputs of the node from its inputs and its state.
+    ///
+    /// Inputs are converted to the types of their ports first, so `int` ports hold `s64`,
+    /// `float` ports `f64` and `string` ports `string` values. An error fails the node like
+    /// the error case returned by a component.
+    fn process(&self, inputs: &PortValues, state: Option<&Value>) -> Result<PortValues, String>;
+
+    /// Tells what the node does, e.g. for node palettes.
+    fn description(&self) -> String {
+        String::new()
+    }
+
+    /// Version of the code of the node, which must change whenever its outputs for the same
+    /// inputs and state do. Results of native nodes without a version are not cached across
+    /// runs.
+    fn version(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Native nodes by name.
+///
+/// The default registry holds the standard nodes, whose names start with `std/`.
+#[derive(Clone)]
+pub struct NativeRegistry {
+    nodes: HashMap<String, Arc<dyn NativeNode>>,
+}
+
+impl NativeRegistry {
+    /// Creates a registry without any nodes.
+    pub fn empty() -> Self {
+        Self {
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Registers a node under the given name, replacing a node of the same name.
+    pub fn register<N: NativeNode + 'static>(&mut self, name: &str, node: N) {
+        self.nodes.insert(name.to_string(), Arc::new(node));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn NativeNode>> {
+        self.nodes.get(name).cloned()
+    }
+
+    /// Returns the names of the registered nodes in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.nodes.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
+
+impl Default for NativeRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(
+            "std/add",
+            BinaryFloat::new("sum", |a, b| a + b, "Adds the floats a and b."),
+        );
+        registry.register(
+            "std/multiply",
+            BinaryFloat::new("product", |a, b| a * b, "Multiplies the floats a and b."),
+        );
+        registry.register("std/number", Number);
+        registry
+    }
+}
+
+/// A native node loaded by the runtime for a node type.
+#[derive(Clone)]
+pub(crate) struct LoadedNative {
+    pub(crate) name: String,
+    pub(crate) node: Arc<dyn NativeNode>,
+    pub(crate) ports: NodePorts,
+}
+
+impl LoadedNative {
+    pub(crate) fn new(name: &str, node: Arc<dyn NativeNode>) -> Self {
+        let ports = NodePorts {
+            inputs: node.input_ports(),
+            outputs: node.output_ports(),
+            ..NodePorts::default()
+        };
+        Self {
+            name: name.to_string(),
+            node,
+            ports,
+        }
+    }
+
+    /// Derives the node implementation from the ports of the node.
+    pub(crate) fn implementation(&self) -> NodeImplementationData {
+        NodeImplementationData {
+            native: Some(self.name.clone()),
+            ..self.ports.implementation()
+        }
+    }
+
+    /// Describes the node like the `describe` export of a component.
+    pub(crate) fn description(&self) -> NodeDescription {
+        let ports = |ports: &[(String, PortType)]| {
+            ports
+                .iter()
+                .map(|(port, ty)| PortDescription {
+                    name: port.clone(),
+                    r#type: ty.to_string(),
+                    default: None,
+                    doc: None,
+                })
+                .collect()
+        };
+        NodeDescription {
+            name: self.name.clone(),
+            version: self.node.version().unwrap_or_default(),
+            description: self.node.description(),
+            author: None,
+            categories: vec!["native".to_string()],
+            inputs: ports(&self.ports.inputs),
+            outputs: ports(&self.ports.outputs),
+            state: Vec::new(),
+        }
+    }
+}
+
+/// An instance of a native node, holding the state of the node.
+pub(crate) struct NativeInstance {
+    node_id: String,
+    native: LoadedNative,
+    state: Option<Value>,
+}
+
+impl NativeInstance {
+    pub(crate) fn new<S: Serialize>(
+        node_id: &str,
+        native: LoadedNative,
+        state: Option<&S>,
+    ) -> Result<Self, RuntimeError> {
+        Ok(Self {
+            node_id: node_id.to_string(),
+            native,
+            state: state.map(serde_json::to_value).transpose()?,
+        })
+    }
+
+    pub(crate) fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub(crate) fn set_state<S: Serialize>(
+        &mut self,
+        state: Option<&S>,
+    ) -> Result<(), RuntimeError> {
+        self.state = state.map(serde_json::to_value).transpose()?;
+        Ok(())
+    }
+
+    /// Native nodes log through `tracing` directly, so nothing is captured.
+    pub(crate) fn take_logs(&mut self) -> NodeLogs {
+        NodeLogs::default()
+    }
+
+    pub(crate) fn ports(&self) -> &NodePorts {
+        &self.native.ports
+    }
+
+    /// Converts the inputs to the types of their ports and runs the node.
+    pub(crate) fn process(&self, inputs: &PortValues) -> Result<PortValues, RuntimeError> {
+        let inputs = self
+            .native
+            .ports
+            .convert_inputs(inputs)
+            .map_err(|err| self.compute_error(err.to_string()))?;
+        self.native
+            .node
+            .process(&inputs, self.state.as_ref())
+            .map_err(|err| self.compute_error(format!("node failed: {}", err)))
+    }
+
+    fn compute_error(&self, message: String) -> RuntimeError {
+        RuntimeError::ComputeError(format!("{}: {}", self.node_id, message))
+    }
+}
+
+/// Applies a function to the float inputs `a` and `b` and returns the result on a single
+/// output port.
+struct BinaryFloat {
+    output: &'static str,
+    apply: fn(f64, f64) -> f64,
+    description: &'static str,
+}
+
+impl BinaryFloat {
+    fn new(output: &'static str, apply: fn(f64, f64) -> f64, description: &'static str) -> Self {
+        Self {
+            output,
+            apply,
+            description,
+        }
+    }
+}
+
+impl NativeNode for BinaryFloat {
+    fn input_ports(&self) -> Vec<(String, PortType)> {
+        vec![
+            ("a".to_string(), PortType::Float),
+            ("b".to_string(), PortType::Float),
+        ]
+    }
+
+    fn output_ports(&self) -> Vec<(String, PortType)> {
+        vec![(self.output.to_string(), PortType::Float)]
+    }
+
+    fn process(&self, inputs: &PortValues, _state: Option<&Value>) -> Result<PortValues, String> {
+        let float = |port: &str| match inputs.get(port) {
+            Some(Val::Float64(value)) => Ok(*value),
+            other => Err(format!("expected float on <{}>, got {:?}", port, other)),
+        };
+        let result = (self.apply)(float("a")?, float("b")?);
+        Ok(PortValues::from([(
+            self.output.to_string(),
+            Val::Float64(result),
+        )]))
+    }
+
+    fn description(&self) -> String {
+        self.description.to_string()
+    }
+
+    fn version(&self) -> Option<String> {
+        Some(env!("CARGO_PKG_VERSION").to_string())
+    }
+}
+
+/// Emits the `number` of its state.
+struct Number;
+
+impl NativeNode for Number {
+    fn input_ports(&self) -> Vec<(String, PortType)> {
+        Vec::new()
+    }
+
+    fn output_ports(&self) -> Vec<(String, PortType)> {
+        vec![("number".to_string(), PortType::Float)]
+    }
+
+    fn process(&self, _inputs: &PortValues, state: Option<&Value>) -> Result<PortValues, String> {
+        let number = state
+            .and_then(|state| state.get("number"))
+            .and_then(Value::as_f64)
+            .ok_or_else(|| "state has no <number>".to_string())?;
+        Ok(PortValues::from([(
+            "number".to_string(),
+            Val::Float64(number),
+        )]))
+    }
+
+    fn description(&self) -> String {
+        "Emits the number of its state.".to_string()
+    }
+
+    fn version(&self) -> Option<String> {
+        Some(env!("CARGO_PKG_VERSION").to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use serde_json::{json, Map};
+
+    use super::*;
+    use crate::execution_plan::ExecutionPlan;
+    use crate::result_cache::ResultCache;
+    use crate::test_utils::plan_from_json;
+    use crate::Runtime;
+
+    fn plan() -> ExecutionPlan {
+        plan_from_json(json!({
+            "node_implementations": {
+                "number": {"output": {"number": "float"}, "native": "std/number"},
+                "add": {
+                    "input": {"a": "float", "b": "float"},
+                    "output": {"sum": "float"},
+                    "native": "std/add"
+                }
+            },
+            "nodes": {
+                "x": {"type": "number", "state": {"number": 1.5}},
+                "y": {"type": "number", "state": {"number": 2}},
+                "sum": {"type": "add"}
+            },
+            "edges": [
+                {"from": "x", "to": "sum", "to_port": "a"},
+                {"from": "y", "to": "sum", "to_port": "b"}
+            ],
+            "outputs": {"sum": {"node": "sum"}}
+        }))
+    }
+
+    fn runtime() -> Runtime {
+        let mut runtime = Runtime::new();
+        runtime.load_native("number", "std/number").unwrap();
+        runtime.load_native("add", "std/add").unwrap();
+        runtime
+    }
+
+    #[test]
+    fn test_execute_native_nodes() {
+        let mut runtime = runtime();
+        let result = block_on(plan().execute(&mut runtime)).unwrap();
+        assert_eq!(result.outputs["sum"]["sum"], Val::Float64(3.5));
+
+        let outputs = block_on(plan().call(&runtime, &Map::new())).unwrap();
+        assert_eq!(Value::Object(outputs), json!({"sum": 3.5}));
+    }
+
+    #[test]
+    fn test_native_results_are_cached() {
+        let dir = std::env::temp_dir().join(format!(
+            "cillio-native-result-cache-test-{}",
+            std::process::id()
+        ));
+        block_on(plan().execute(&mut runtime().with_result_cache(ResultCache::new(&dir)))).unwrap();
+        let result =
+            block_on(plan().execute(&mut runtime().with_result_cache(ResultCache::new(&dir))))
+                .unwrap();
+        let mut cached = result.cached;
+        cached.sort();
+        assert_eq!(cached, ["sum", "x", "y"]);
+        assert!(runtime().caches_results("add"));
+        assert_eq!(result.outputs["sum"]["sum"], Val::Float64(3.5));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_registry() {
+        struct Negate;
+
+        impl NativeNode for Negate {
+            fn input_ports(&self) -> Vec<(String, PortType)> {
+                vec![("value".to_string(), PortType::Int)]
+            }
+
+            fn output_ports(&self) -> Vec<(String, PortType)> {
+                vec![("value".to_string(), PortType::Int)]
+            }
+
+            fn process(
+                &self,
+                inputs: &PortValues,
+                _state: Option<&Value>,
+            ) -> Result<PortValues, String> {
+                match inputs.get("value") {
+                    Some(Val::S64(value)) => {
+                        Ok(PortValues::from([("value".to_string(), Val::S64(-value))]))
+                    }
+                    other => Err(format!("unexpected input {:?}", other)),
+                }
+            }
+        }
+
+        let mut registry = NativeRegistry::empty();
+        registry.register("test/negate", Negate);
+        assert_eq!(registry.names(), ["test/negate"]);
+
+        let mut runtime = Runtime::new().with_native_registry(registry);
+        assert!(runtime.load_native("add", "std/add").is_err());
+        runtime.load_native("negate", "test/negate").unwrap();
+        assert_eq!(runtime.input_ports("negate").unwrap(), ["value"]);
+        // Without a version, results of the node may come from an older build of it.
+        assert!(!runtime.caches_results("negate"));
+
+        let implementation = runtime.describe_component("negate", "negate").unwrap();
+        assert_eq!(implementation.native.as_deref(), Some("test/negate"));
+        assert_eq!(implementation.input.unwrap()["value"], "int");
+        let description = runtime.node_description("negate").unwrap();
+        assert_eq!(description.name, "test/negate");
+        assert_eq!(description.outputs[0].r#type, "int");
+
+        let mut instance = block_on(runtime.initialize_node(
+            "n",
+            "negate",
+            None::<&Value>,
+            Default::default(),
+            &Default::default(),
+        ))
+        .unwrap();
+        let outputs =
+            block_on(instance.process(&PortValues::from([("value".to_string(), Val::U8(3))])))
+                .unwrap();
+        assert_eq!(outputs["value"], Val::S64(-3));
+    }
+}
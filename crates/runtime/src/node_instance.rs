@@ -0,0 +1,417 @@
+use std::time::{Duration, Instant};
+
+use cillio_config::NodeLimits;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use wasmtime::component::{Func, Instance, Type, Val};
+use wasmtime::{Store, Trap, UpdateDeadline};
+
+use crate::convert::{coerce_val, json_to_val, val_to_json};
+use crate::description::DESCRIBE_FN_NAME;
+use crate::generic;
+use crate::introspect::output_record_type;
+use crate::limits::{Limit, TimeoutExceeded};
+use crate::logs::NodeLogs;
+use crate::native::NativeInstance;
+use crate::node_ports::NodePorts;
+use crate::runtime_data::PortValues;
+use crate::{RuntimeError, ServerWasiView};
+
+const PROCESS_FN_NAME: &str = "process";
+
+/// An instance of a node, either a component or a native node, see [`crate::native`].
+pub struct NodeInstance {
+    kind: InstanceKind,
+}
+
+enum InstanceKind {
+    Component(ComponentInstance),
+    Native(NativeInstance),
+}
+
+impl NodeInstance {
+    /// Wraps a component instance. `enforce_limits` tells whether the engine meters fuel
+    /// and interrupts on epochs. `ports` are the described ports of a component of the
+    /// generic node world, see [`crate::generic`].
+    pub(crate) fn new(
+        store: Store<ServerWasiView>,
+        instance: Instance,
+        limits: NodeLimits,
+        enforce_limits: bool,
+        ports: Option<NodePorts>,
+    ) -> Result<Self, RuntimeError> {
+        let instance = ComponentInstance::new(store, instance, limits, enforce_limits, ports)?;
+        Ok(Self {
+            kind: InstanceKind::Component(instance),
+        })
+    }
+
+    pub(crate) fn native(instance: NativeInstance) -> Self {
+        Self {
+            kind: InstanceKind::Native(instance),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        match &self.kind {
+            InstanceKind::Component(instance) => instance.node_id(),
+            InstanceKind::Native(instance) => instance.node_id(),
+        }
+    }
+
+    /// Replaces the state the node reads in subsequent calls.
+    pub fn set_state<S: Serialize>(&mut self, state: Option<&S>) -> Result<(), RuntimeError> {
+        match &mut self.kind {
+            InstanceKind::Component(instance) => instance.set_state(state),
+            InstanceKind::Native(instance) => instance.set_state(state),
+        }
+    }
+
+    /// Returns the lines the node wrote to its captured stdout and stderr since the last call.
+    pub fn take_logs(&mut self) -> NodeLogs {
+        match &mut self.kind {
+            InstanceKind::Component(instance) => instance.take_logs(),
+            InstanceKind::Native(instance) => instance.take_logs(),
+        }
+    }
+
+    /// Returns the component instance, or `None` for native nodes.
+    pub fn instance(&self) -> Option<&Instance> {
+        match &self.kind {
+            InstanceKind::Component(instance) => Some(&instance.instance),
+            InstanceKind::Native(_) => None,
+        }
+    }
+
+    /// Returns the names of the input ports of the node.
+    pub fn input_ports(&self) -> Vec<String> {
+        match &self.kind {
+            InstanceKind::Component(instance) => instance.input_ports(),
+            InstanceKind::Native(instance) => instance.ports().input_names(),
+        }
+    }
+
+    /// Converts JSON input values into port values using the types of the input ports.
+    pub fn inputs_from_json(
+        &self,
+        inputs: &Map<String, Value>,
+    ) -> Result<PortValues, RuntimeError> {
+        match &self.kind {
+            InstanceKind::Component(instance) => instance.inputs_from_json(inputs),
+            InstanceKind::Native(instance) => Ok(instance.ports().inputs_from_json(inputs)?),
+        }
+    }
+
+    /// Converts output port values into JSON using the types of the output ports.
+    pub fn outputs_to_json(
+        &self,
+        outputs: &PortValues,
+    ) -> Result<Map<String, Value>, RuntimeError> {
+        match &self.kind {
+            InstanceKind::Component(instance) => instance.outputs_to_json(outputs),
+            InstanceKind::Native(instance) => Ok(instance.ports().outputs_to_json(outputs)),
+        }
+    }
+
+    /// Calls the `describe` export of a component. Native nodes have none.
+    pub(crate) async fn describe(&mut self) -> Result<Option<Val>, RuntimeError> {
+        match &mut self.kind {
+            InstanceKind::Component(instance) => instance.describe().await.map(Some),
+            InstanceKind::Native(_) => Ok(None),
+        }
+    }
+
+    /// Runs the node on the given inputs and returns its outputs.
+    pub async fn process(&mut self, inputs: &PortValues) -> Result<PortValues, RuntimeError> {
+        match &mut self.kind {
+            InstanceKind::Component(instance) => instance.process(inputs).await,
+            InstanceKind::Native(instance) => instance.process(inputs),
+        }
+    }
+}
+
+/// An instantiated node component together with the store it lives in.
+struct ComponentInstance {
+    store: Store<ServerWasiView>,
+    instance: Instance,
+    process_fn: Func,
+    limits: NodeLimits,
+    enforce_limits: bool,
+    /// Ports of a component of the generic node world.
+    ports: Option<NodePorts>,
+}
+
+impl ComponentInstance {
+    fn new(
+        mut store: Store<ServerWasiView>,
+        instance: Instance,
+        limits: NodeLimits,
+        enforce_limits: bool,
+        ports: Option<NodePorts>,
+    ) -> Result<Self, RuntimeError> {
+        let process_fn = instance
+            .get_func(&mut store, PROCESS_FN_NAME)
+            .ok_or_else(|| {
+                RuntimeError::NodeInitializationError(format!(
+                    "{}: function <{}> not found",
+                    store.data().node_id(),
+                    PROCESS_FN_NAME
+                ))
+            })?;
+        Ok(Self {
+            store,
+            instance,
+            process_fn,
+            limits,
+            enforce_limits,
+            ports,
+        })
+    }
+
+    fn node_id(&self) -> &str {
+        self.store.data().node_id()
+    }
+
+    /// Replaces the state the node reads through `get-state` in subsequent calls.
+    fn set_state<S: Serialize>(&mut self, state: Option<&S>) -> Result<(), RuntimeError> {
+        let state = state.map(serde_json::to_vec).transpose()?;
+        self.store.data_mut().set_state(state);
+        Ok(())
+    }
+
+    /// Returns the lines the node wrote to its captured stdout and stderr since the last call.
+    fn take_logs(&mut self) -> NodeLogs {
+        self.store.data_mut().take_logs()
+    }
+
+    /// Returns the names of the fields of the `inputs` record taken by `process`, or the
+    /// described input ports of a generic node.
+    fn input_ports(&self) -> Vec<String> {
+        if let Some(ports) = &self.ports {
+            return ports.input_names();
+        }
+        match self.process_fn.params(&self.store).first() {
+            Some(Type::Record(record)) => record
+                .fields()
+                .map(|field| field.name.to_string())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the types of the fields of the `inputs` record taken by `process`.
+    fn input_types(&self) -> Vec<(String, Type)> {
+        match self.process_fn.params(&self.store).first() {
+            Some(Type::Record(record)) => record
+                .fields()
+                .map(|field| (field.name.to_string(), field.ty))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the types of the fields of the outputs record returned by `process`.
+    fn output_types(&self) -> Vec<(String, Type)> {
+        match self
+            .process_fn
+            .results(&self.store)
+            .first()
+            .and_then(|ty| output_record_type(ty.clone()))
+        {
+            Some(Type::Record(record)) => record
+                .fields()
+                .map(|field| (field.name.to_string(), field.ty))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Converts JSON input values into port values using the types of the input ports.
+    fn inputs_from_json(&self, inputs: &Map<String, Value>) -> Result<PortValues, RuntimeError> {
+        if let Some(ports) = &self.ports {
+            return Ok(ports.inputs_from_json(inputs)?);
+        }
+        let input_types = self.input_types();
+        let mut values = PortValues::new();
+        for (port, value) in inputs {
+            let (_, ty) = input_types
+                .iter()
+                .find(|(name, _)| name == port)
+                .ok_or_else(|| self.compute_error(format!("unknown input port <{}>", port)))?;
+            values.insert(
+                port.clone(),
+                json_to_val(value, ty, &format!("inputs.{}", port))?,
+            );
+        }
+        Ok(values)
+    }
+
+    /// Converts output port values into JSON using the types of the output ports.
+    fn outputs_to_json(&self, outputs: &PortValues) -> Result<Map<String, Value>, RuntimeError> {
+        if let Some(ports) = &self.ports {
+            return Ok(ports.outputs_to_json(outputs));
+        }
+        let output_types = self.output_types();
+        let mut values = Map::new();
+        for (port, ty) in &output_types {
+            if let Some(value) = outputs.get(port) {
+                values.insert(
+                    port.clone(),
+                    val_to_json(value, ty, &format!("outputs.{}", port))?,
+                );
+            }
+        }
+        Ok(values)
+    }
+
+    /// Calls the node's `process` function with the given inputs and returns its outputs.
+    async fn process(&mut self, inputs: &PortValues) -> Result<PortValues, RuntimeError> {
+        let params = match &self.ports {
+            Some(ports) => vec![generic::inputs_param(ports, inputs)
+                .map_err(|err| self.compute_error(err.to_string()))?],
+            None => self
+                .process_fn
+                .params(&self.store)
+                .iter()
+                .map(|param_type| self.build_param(param_type, inputs))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+
+        let mut returns = vec![Val::Bool(false); self.process_fn.results(&self.store).len()];
+        self.apply_limits()?;
+        if let Err(err) = self
+            .process_fn
+            .call_async(&mut self.store, &params, &mut returns)
+            .await
+        {
+            return Err(self.call_error(err));
+        }
+        self.process_fn
+            .post_return_async(&mut self.store)
+            .await
+            .map_err(|err| self.compute_error(format!("{:#}", err)))?;
+
+        match (returns.into_iter().next(), &self.ports) {
+            (Some(value), Some(ports)) => {
+                generic::outputs_from_return(value, ports).map_err(|err| self.compute_error(err))
+            }
+            (Some(value), None) => {
+                outputs_from_return(value).map_err(|err| self.compute_error(err))
+            }
+            (None, _) => Ok(PortValues::new()),
+        }
+    }
+
+    /// Calls the node's `describe` function and returns what it returned.
+    async fn describe(&mut self) -> Result<Val, RuntimeError> {
+        let describe_fn = self
+            .instance
+            .get_func(&mut self.store, DESCRIBE_FN_NAME)
+            .ok_or_else(|| {
+                self.compute_error(format!("function <{}> not found", DESCRIBE_FN_NAME))
+            })?;
+        let mut returns = vec![Val::Bool(false); describe_fn.results(&self.store).len()];
+        self.apply_limits()?;
+        if let Err(err) = describe_fn
+            .call_async(&mut self.store, &[], &mut returns)
+            .await
+        {
+            return Err(self.call_error(err));
+        }
+        describe_fn
+            .post_return_async(&mut self.store)
+            .await
+            .map_err(|err| self.compute_error(format!("{:#}", err)))?;
+        returns
+            .into_iter()
+            .next()
+            .ok_or_else(|| self.compute_error(format!("<{}> returns nothing", DESCRIBE_FN_NAME)))
+    }
+
+    /// Refuels the store and arms the timeout for the next `process` call.
+    fn apply_limits(&mut self) -> Result<(), RuntimeError> {
+        if !self.enforce_limits {
+            return Ok(());
+        }
+        self.store
+            .set_fuel(self.limits.fuel.unwrap_or(u64::MAX))
+            .map_err(|err| self.compute_error(format!("{:#}", err)))?;
+        match self.limits.timeout_ms {
+            Some(timeout_ms) => {
+                let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+                self.store.epoch_deadline_callback(move |_| {
+                    if Instant::now() >= deadline {
+                        Err(TimeoutExceeded.into())
+                    } else {
+                        Ok(UpdateDeadline::Yield(1))
+                    }
+                });
+                self.store.set_epoch_deadline(1);
+            }
+            None => self.store.epoch_deadline_async_yield_and_update(1),
+        }
+        Ok(())
+    }
+
+    /// Maps a failed `process` call to the limit it exceeded, if any.
+    fn call_error(&self, err: anyhow::Error) -> RuntimeError {
+        let limit = if err.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel) {
+            self.limits.fuel.map(Limit::Fuel)
+        } else if err.downcast_ref::<TimeoutExceeded>().is_some() {
+            self.limits
+                .timeout_ms
+                .map(|timeout_ms| Limit::Timeout(Duration::from_millis(timeout_ms)))
+        } else if self.store.data().memory_limit_exceeded() {
+            self.limits.max_memory_bytes.map(Limit::Memory)
+        } else {
+            None
+        };
+        match limit {
+            Some(limit) => RuntimeError::LimitExceeded {
+                node: self.node_id().to_string(),
+                limit,
+            },
+            None => self.compute_error(format!("{:#}", err)),
+        }
+    }
+
+    fn compute_error(&self, message: String) -> RuntimeError {
+        RuntimeError::ComputeError(format!("{}: {}", self.node_id(), message))
+    }
+
+    fn build_param(&self, param_type: &Type, inputs: &PortValues) -> Result<Val, RuntimeError> {
+        match param_type {
+            Type::Record(record) => record
+                .fields()
+                .map(|field| {
+                    let value = inputs.get(field.name).cloned().ok_or_else(|| {
+                        self.compute_error(format!("input port <{}> is not connected", field.name))
+                    })?;
+                    // Values from native nodes may not have the exact type of the port.
+                    let value = coerce_val(value, &field.ty, &format!("inputs.{}", field.name))?;
+                    Ok((field.name.to_string(), value))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Val::Record),
+            other => Err(self.compute_error(format!("unsupported parameter type {:?}", other))),
+        }
+    }
+}
+
+/// Extracts the output record from the value returned by a node's `process` function.
+///
+/// Nodes either return their outputs record directly, wrap it in a `result<outputs, error>`,
+/// or return an `option<error>` when they have no outputs.
+fn outputs_from_return(value: Val) -> Result<PortValues, String> {
+    match value {
+        Val::Record(fields) => Ok(fields.into_iter().collect()),
+        Val::Result(Ok(Some(value))) => outputs_from_return(*value),
+        Val::Result(Ok(None)) | Val::Option(None) => Ok(PortValues::new()),
+        Val::Result(Err(error)) => Err(format!("node failed: {:?}", error)),
+        Val::Option(Some(value)) => match *value {
+            Val::Record(fields) => Ok(fields.into_iter().collect()),
+            error => Err(format!("node failed: {:?}", error)),
+        },
+        other => Err(format!("unsupported return value: {:?}", other)),
+    }
+}
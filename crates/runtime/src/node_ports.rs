@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use cillio_config::{NodeDescription, NodeImplementationData, PortDescription, PortType};
+use serde_json::{Map, Value};
+use wasmtime::component::Val;
+
+use crate::convert::{json_to_port_val, val_to_json_untyped, ConversionError};
+use crate::runtime_data::PortValues;
+
+/// The ports of a node whose port values have the types of [`json_to_port_val`], such as
+/// native nodes and components of the generic node world.
+///
+/// Ports are kept in the order the node declares them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct NodePorts {
+    pub inputs: Vec<(String, PortType)>,
+    pub outputs: Vec<(String, PortType)>,
+    /// Values of input ports that are not connected, by port.
+    pub input_defaults: HashMap<String, Value>,
+}
+
+impl NodePorts {
+    /// Parses the port types of a node description.
+    pub fn from_description(description: &NodeDescription) -> Result<Self, String> {
+        let ports = |ports: &[PortDescription]| {
+            ports
+                .iter()
+                .map(|port| {
+                    let ty = port
+                        .r#type
+                        .parse()
+                        .map_err(|err| format!("port <{}>: {}", port.name, err))?;
+                    Ok((port.name.clone(), ty))
+                })
+                .collect::<Result<Vec<_>, String>>()
+        };
+        Ok(Self {
+            inputs: ports(&description.inputs)?,
+            outputs: ports(&description.outputs)?,
+            input_defaults: description
+                .inputs
+                .iter()
+                .filter_map(|port| Some((port.name.clone(), port.default.clone()?)))
+                .collect(),
+        })
+    }
+
+    pub fn input_names(&self) -> Vec<String> {
+        self.inputs.iter().map(|(port, _)| port.clone()).collect()
+    }
+
+    /// Derives a node implementation declaring the ports, without a `wasm`, `graph` or
+    /// `native` source.
+    pub fn implementation(&self) -> NodeImplementationData {
+        let declared = |ports: &[(String, PortType)]| {
+            Some(
+                ports
+                    .iter()
+                    .map(|(port, ty)| (port.clone(), ty.to_string()))
+                    .collect(),
+            )
+        };
+        NodeImplementationData {
+            input: declared(&self.inputs),
+            output: declared(&self.outputs),
+            state: None,
+            wasm: None,
+            graph: None,
+            native: None,
+            limits: None,
+            capabilities: None,
+            deterministic: true,
+        }
+    }
+
+    /// Converts JSON input values into port values.
+    pub fn inputs_from_json(
+        &self,
+        inputs: &Map<String, Value>,
+    ) -> Result<PortValues, ConversionError> {
+        port_values_from_json(&self.inputs, inputs, "inputs")
+    }
+
+    /// Converts JSON output values into port values.
+    pub fn outputs_from_json(
+        &self,
+        outputs: &Map<String, Value>,
+    ) -> Result<PortValues, ConversionError> {
+        port_values_from_json(&self.outputs, outputs, "outputs")
+    }
+
+    /// Converts output port values into JSON.
+    pub fn outputs_to_json(&self, outputs: &PortValues) -> Map<String, Value> {
+        self.outputs
+            .iter()
+            .filter_map(|(port, _)| Some((port.clone(), val_to_json_untyped(outputs.get(port)?))))
+            .collect()
+    }
+
+    /// Converts the values routed into the input ports to the types of the ports.
+    ///
+    /// Ports that are not connected get their default, or none if they are optional.
+    pub fn convert_inputs(&self, inputs: &PortValues) -> Result<PortValues, ConversionError> {
+        let mut values = PortValues::new();
+        for (port, ty) in &self.inputs {
+            let path = format!("inputs.{}", port);
+            let value = match (inputs.get(port), self.input_defaults.get(port)) {
+                (Some(value), _) => json_to_port_val(&val_to_json_untyped(value), ty, &path)?,
+                (None, Some(default)) => json_to_port_val(default, ty, &path)?,
+                (None, None) if ty.is_optional() => Val::Option(None),
+                (None, None) => {
+                    return Err(ConversionError::new(
+                        &path,
+                        "input port is not connected".to_string(),
+                    ))
+                }
+            };
+            values.insert(port.clone(), value);
+        }
+        Ok(values)
+    }
+}
+
+fn port_values_from_json(
+    ports: &[(String, PortType)],
+    values: &Map<String, Value>,
+    path: &str,
+) -> Result<PortValues, ConversionError> {
+    let mut port_values = PortValues::new();
+    for (port, value) in values {
+        let port_path = format!("{}.{}", path, port);
+        let (_, ty) = ports
+            .iter()
+            .find(|(name, _)| name == port)
+            .ok_or_else(|| ConversionError::new(&port_path, "unknown port".to_string()))?;
+        port_values.insert(port.clone(), json_to_port_val(value, ty, &port_path)?);
+    }
+    Ok(port_values)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_convert_unconnected_inputs() {
+        let ports = NodePorts {
+            inputs: vec![
+                ("required".to_string(), PortType::Int),
+                ("optional".to_string(), "int?".parse().unwrap()),
+                ("defaulted".to_string(), PortType::Float),
+            ],
+            outputs: Vec::new(),
+            input_defaults: HashMap::from([("defaulted".to_string(), json!(0.5))]),
+        };
+        let inputs = PortValues::from([("required".to_string(), Val::U8(1))]);
+        let values = ports.convert_inputs(&inputs).unwrap();
+        assert_eq!(values["required"], Val::S64(1));
+        assert_eq!(values["optional"], Val::Option(None));
+        assert_eq!(values["defaulted"], Val::Float64(0.5));
+
+        let error = ports.convert_inputs(&PortValues::new()).unwrap_err();
+        assert!(error.to_string().contains("inputs.required"), "{}", error);
+    }
+}
@@ -0,0 +1,334 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use cillio_config::{NodeCapabilities, NodeLimits};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use wasmtime::component::Val;
+
+use crate::component_cache::{
+    cache_entries, cache_root, cache_stats, remove_entries, touch, CacheStats,
+};
+use crate::logs::NodeLogs;
+use crate::runtime_data::PortValues;
+
+const ENTRY_EXTENSION: &str = "json";
+
+/// On-disk cache of node results, shared across runs.
+///
+/// Entries are keyed by the SHA-256 of the component bytes, the node state, its limits and
+/// capabilities and the input values, so a node is only skipped when it would run the exact
+/// same code with the same grants on the exact same data. Only nodes declared deterministic
+/// are cached; the contents of preopened directories are not part of the key.
+#[derive(Debug, Clone)]
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+/// Outputs and logs of a node run as stored in the cache.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedResult {
+    pub node_type: String,
+    pub outputs: Map<String, Value>,
+    pub logs: NodeLogs,
+}
+
+/// Summary of a cache entry, as listed by [`ResultCache::entries`].
+#[derive(Debug, Clone)]
+pub struct ResultCacheEntry {
+    pub key: String,
+    pub node_type: String,
+    pub size_bytes: u64,
+    /// When the entry was last written or read.
+    pub last_used: SystemTime,
+}
+
+impl ResultCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the `results` directory in the [`cache_root`].
+    pub fn default_dir() -> PathBuf {
+        Self::dir_in(&cache_root())
+    }
+
+    /// Returns the `results` directory in the given cache root.
+    pub fn dir_in(root: &Path) -> PathBuf {
+        root.join("results")
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the cache key of a run of the component with the given hash.
+    pub fn key<S: Serialize>(
+        component_hash: &str,
+        state: Option<&S>,
+        limits: &NodeLimits,
+        capabilities: &NodeCapabilities,
+        inputs: &PortValues,
+    ) -> Result<String, serde_json::Error> {
+        // Ports are sorted, so equal inputs always encode to the same bytes.
+        let mut ports: Vec<_> = inputs.iter().collect();
+        ports.sort_by_key(|(port, _)| *port);
+        let mut encoded = Vec::new();
+        for (port, value) in ports {
+            encode_bytes(port.as_bytes(), &mut encoded);
+            encode_val(value, &mut encoded);
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(component_hash.as_bytes());
+        hasher.update([0]);
+        hasher.update(serde_json::to_vec(&state)?);
+        hasher.update([0]);
+        hasher.update(serde_json::to_vec(limits)?);
+        hasher.update([0]);
+        hasher.update(serde_json::to_vec(capabilities)?);
+        hasher.update([0]);
+        hasher.update(encoded);
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension(ENTRY_EXTENSION)
+    }
+
+    /// Loads the result stored under `key`, or `None` on a cache miss.
+    ///
+    /// Entries that cannot be read are removed.
+    pub fn load(&self, key: &str) -> Option<CachedResult> {
+        let path = self.entry_path(key);
+        let content = fs::read(&path).ok()?;
+        match serde_json::from_slice(&content) {
+            Ok(result) => {
+                let _ = touch(&path);
+                Some(result)
+            }
+            Err(_) => {
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Stores `result` under `key`.
+    pub fn store(&self, key: &str, result: &CachedResult) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.entry_path(key);
+        let temp_path = path.with_extension(format!("{}.{}", ENTRY_EXTENSION, std::process::id()));
+        fs::write(&temp_path, serde_json::to_vec(result)?)?;
+        fs::rename(&temp_path, &path)
+    }
+
+    /// Lists all entries, most recently used first.
+    pub fn entries(&self) -> io::Result<Vec<ResultCacheEntry>> {
+        let mut entries = Vec::new();
+        for path in cache_entries(&self.dir, ENTRY_EXTENSION)? {
+            let metadata = fs::metadata(&path)?;
+            let node_type = fs::read(&path)
+                .ok()
+                .and_then(|content| serde_json::from_slice::<CachedResult>(&content).ok())
+                .map_or_else(|| "<unreadable>".to_string(), |result| result.node_type);
+            entries.push(ResultCacheEntry {
+                key: path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                node_type,
+                size_bytes: metadata.len(),
+                last_used: metadata.modified()?,
+            });
+        }
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_used));
+        Ok(entries)
+    }
+
+    pub fn stats(&self) -> io::Result<CacheStats> {
+        cache_stats(&self.dir, ENTRY_EXTENSION)
+    }
+
+    /// Removes all entries and returns what was removed.
+    pub fn clear(&self) -> io::Result<CacheStats> {
+        remove_entries(&self.dir, ENTRY_EXTENSION, Duration::ZERO)
+    }
+
+    /// Removes entries not used within `max_age` and returns what was removed.
+    pub fn gc(&self, max_age: Duration) -> io::Result<CacheStats> {
+        remove_entries(&self.dir, ENTRY_EXTENSION, max_age)
+    }
+}
+
+/// Encodes a value without loss for hashing: every value is tagged with its type, floats
+/// are encoded by their bits and sequences by their length.
+fn encode_val(val: &Val, out: &mut Vec<u8>) {
+    match val {
+        Val::Bool(value) => out.extend([0, u8::from(*value)]),
+        Val::S8(value) => encode_tagged(1, &value.to_le_bytes(), out),
+        Val::U8(value) => encode_tagged(2, &value.to_le_bytes(), out),
+        Val::S16(value) => encode_tagged(3, &value.to_le_bytes(), out),
+        Val::U16(value) => encode_tagged(4, &value.to_le_bytes(), out),
+        Val::S32(value) => encode_tagged(5, &value.to_le_bytes(), out),
+        Val::U32(value) => encode_tagged(6, &value.to_le_bytes(), out),
+        Val::S64(value) => encode_tagged(7, &value.to_le_bytes(), out),
+        Val::U64(value) => encode_tagged(8, &value.to_le_bytes(), out),
+        Val::Float32(value) => encode_tagged(9, &value.to_bits().to_le_bytes(), out),
+        Val::Float64(value) => encode_tagged(10, &value.to_bits().to_le_bytes(), out),
+        Val::Char(value) => encode_tagged(11, &u32::from(*value).to_le_bytes(), out),
+        Val::String(value) => {
+            out.push(12);
+            encode_bytes(value.as_bytes(), out);
+        }
+        Val::List(items) | Val::Tuple(items) => {
+            out.push(if matches!(val, Val::List(_)) { 13 } else { 14 });
+            out.extend((items.len() as u64).to_le_bytes());
+            items.iter().for_each(|item| encode_val(item, out));
+        }
+        Val::Record(fields) => {
+            out.push(15);
+            out.extend((fields.len() as u64).to_le_bytes());
+            for (name, value) in fields {
+                encode_bytes(name.as_bytes(), out);
+                encode_val(value, out);
+            }
+        }
+        Val::Variant(case, payload) => {
+            out.push(16);
+            encode_bytes(case.as_bytes(), out);
+            encode_payload(payload.as_deref(), out);
+        }
+        Val::Enum(case) => {
+            out.push(17);
+            encode_bytes(case.as_bytes(), out);
+        }
+        Val::Option(value) => {
+            out.push(18);
+            encode_payload(value.as_deref(), out);
+        }
+        Val::Result(result) => {
+            let (tag, payload) = match result {
+                Ok(payload) => (19, payload),
+                Err(payload) => (20, payload),
+            };
+            out.push(tag);
+            encode_payload(payload.as_deref(), out);
+        }
+        Val::Flags(names) => {
+            out.push(21);
+            out.extend((names.len() as u64).to_le_bytes());
+            names
+                .iter()
+                .for_each(|name| encode_bytes(name.as_bytes(), out));
+        }
+        // Resources are handles into a store, so only the same handle is the same value.
+        Val::Resource(resource) => {
+            out.push(22);
+            encode_bytes(format!("{:?}", resource).as_bytes(), out);
+        }
+    }
+}
+
+fn encode_tagged(tag: u8, bytes: &[u8], out: &mut Vec<u8>) {
+    out.push(tag);
+    out.extend_from_slice(bytes);
+}
+
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    out.extend((bytes.len() as u64).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn encode_payload(payload: Option<&Val>, out: &mut Vec<u8>) {
+    match payload {
+        Some(value) => {
+            out.push(1);
+            encode_val(value, out);
+        }
+        None => out.push(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_key_distinguishes_values() {
+        let limits = NodeLimits::default();
+        let capabilities = NodeCapabilities::default();
+        let key = |value: Val| {
+            let inputs = PortValues::from([("a".to_string(), value)]);
+            ResultCache::key("component", None::<&Value>, &limits, &capabilities, &inputs).unwrap()
+        };
+        let values = [
+            Val::Float64(f64::NAN),
+            Val::Float64(f64::INFINITY),
+            Val::Float64(f64::NEG_INFINITY),
+            Val::Option(None),
+            Val::Float64(1.0),
+            Val::Float32(1.0),
+            Val::S64(1),
+            Val::U32(1),
+            Val::String("1".to_string()),
+        ];
+        let keys: std::collections::HashSet<_> = values.into_iter().map(key).collect();
+        assert_eq!(keys.len(), 9);
+    }
+
+    #[test]
+    fn test_store_load_and_gc() {
+        let dir =
+            std::env::temp_dir().join(format!("cillio-result-cache-test-{}", std::process::id()));
+        let cache = ResultCache::new(&dir);
+        let inputs = PortValues::from([("a".to_string(), Val::U32(1))]);
+        let limits = NodeLimits::default();
+        let capabilities = NodeCapabilities::default();
+        let key_of = |component: &str, seed: i64, limits, capabilities| {
+            let state = json!({"seed": seed});
+            ResultCache::key(component, Some(&state), limits, capabilities, &inputs).unwrap()
+        };
+        let key = key_of("component", 1, &limits, &capabilities);
+        assert_ne!(key, key_of("component", 2, &limits, &capabilities));
+        assert_ne!(key, key_of("other", 1, &limits, &capabilities));
+        let fuel = NodeLimits {
+            fuel: Some(1_000),
+            ..NodeLimits::default()
+        };
+        assert_ne!(key, key_of("component", 1, &fuel, &capabilities));
+        let clocks = NodeCapabilities {
+            clocks: true,
+            ..NodeCapabilities::default()
+        };
+        assert_ne!(key, key_of("component", 1, &limits, &clocks));
+
+        let result = CachedResult {
+            node_type: "add".to_string(),
+            outputs: json!({"sum": 3}).as_object().unwrap().clone(),
+            logs: NodeLogs {
+                stdout: vec!["3".to_string()],
+                stderr: Vec::new(),
+            },
+        };
+        assert!(cache.load(&key).is_none());
+        cache.store(&key, &result).unwrap();
+        assert_eq!(cache.load(&key), Some(result));
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, key);
+        assert_eq!(entries[0].node_type, "add");
+
+        // Recently used entries survive a collection.
+        assert_eq!(cache.gc(Duration::from_secs(3600)).unwrap().entries, 0);
+        assert_eq!(cache.gc(Duration::ZERO).unwrap().entries, 1);
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
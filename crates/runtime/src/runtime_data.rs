@@ -1,20 +1,96 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use wasmtime::component::Val;
 
+use crate::logs::NodeLogs;
+
+/// Values keyed by port name, e.g. the fields of a node's `inputs` or `outputs` record.
+pub type PortValues = HashMap<String, Val>;
+
 #[derive(Debug, Clone)]
 pub struct RuntimeData {
-    data: HashMap<String, Val>,
+    inputs: HashMap<String, PortValues>,
+    outputs: HashMap<String, PortValues>,
+    logs: HashMap<String, NodeLogs>,
+    run_hashes: HashMap<String, u64>,
 }
 
 impl RuntimeData {
     pub fn new() -> Self {
         Self {
-            data: HashMap::new(),
+            inputs: HashMap::new(),
+            outputs: HashMap::new(),
+            logs: HashMap::new(),
+            run_hashes: HashMap::new(),
         }
     }
 
-    pub fn get_node_input(&self, node_id: &str) -> Option<&Val> {
-        self.data.get(node_id)
+    pub fn get_node_input(&self, node_id: &str) -> Option<&PortValues> {
+        self.inputs.get(node_id)
+    }
+
+    pub fn get_node_output(&self, node_id: &str) -> Option<&PortValues> {
+        self.outputs.get(node_id)
+    }
+
+    pub fn set_node_input(&mut self, node_id: &str, inputs: PortValues) {
+        self.inputs.insert(node_id.to_string(), inputs);
+    }
+
+    pub fn set_node_output(&mut self, node_id: &str, outputs: PortValues) {
+        self.outputs.insert(node_id.to_string(), outputs);
+    }
+
+    pub fn get_node_logs(&self, node_id: &str) -> Option<&NodeLogs> {
+        self.logs.get(node_id)
+    }
+
+    pub fn set_node_logs(&mut self, node_id: &str, logs: NodeLogs) {
+        self.logs.insert(node_id.to_string(), logs);
+    }
+
+    pub fn set_node_run_hash(&mut self, node_id: &str, run_hash: u64) {
+        self.run_hashes.insert(node_id.to_string(), run_hash);
+    }
+
+    /// Whether the node has outputs from a run on the same inputs and with the same run hash,
+    /// which covers the state, implementation, limits and capabilities of the node.
+    pub fn is_up_to_date(&self, node_id: &str, inputs: &PortValues, run_hash: u64) -> bool {
+        self.outputs.contains_key(node_id)
+            && self.inputs.get(node_id) == Some(inputs)
+            && self.run_hashes.get(node_id) == Some(&run_hash)
+    }
+
+    /// Forgets everything about nodes not in `node_ids`.
+    pub fn retain_nodes(&mut self, node_ids: &HashSet<&str>) {
+        self.inputs
+            .retain(|node_id, _| node_ids.contains(node_id.as_str()));
+        self.outputs
+            .retain(|node_id, _| node_ids.contains(node_id.as_str()));
+        self.logs
+            .retain(|node_id, _| node_ids.contains(node_id.as_str()));
+        self.run_hashes
+            .retain(|node_id, _| node_ids.contains(node_id.as_str()));
+    }
+
+    pub fn outputs(&self) -> &HashMap<String, PortValues> {
+        &self.outputs
+    }
+
+    pub fn logs(&self) -> &HashMap<String, NodeLogs> {
+        &self.logs
+    }
+
+    pub fn clear(&mut self) {
+        self.inputs.clear();
+        self.outputs.clear();
+        self.logs.clear();
+        self.run_hashes.clear();
+    }
+}
+
+impl Default for RuntimeData {
+    fn default() -> Self {
+        RuntimeData::new()
     }
 }
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::mpsc::{self, Receiver, Sender};
+use futures::future::try_join_all;
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+
+use crate::execution_plan::{new_run_id, ExecutionPlan, ExecutionStep};
+use crate::instance_pool::InstanceReuse;
+use crate::logs::NodeLogs;
+use crate::node_instance::NodeInstance;
+use crate::runtime_data::PortValues;
+use crate::{Runtime, RuntimeError};
+
+/// Settings of a streaming execution.
+#[derive(Debug, Clone)]
+pub struct StreamOptions {
+    /// Time between two polls of the source nodes.
+    pub interval: Duration,
+    /// Number of ticks after which the sources stop, unbounded if `None`.
+    pub ticks: Option<u64>,
+    /// Number of events buffered between two nodes before the sending node waits.
+    pub channel_capacity: usize,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+            ticks: None,
+            channel_capacity: 16,
+        }
+    }
+}
+
+/// Outputs and logs of one node for one event of a stream.
+#[derive(Debug, Clone)]
+pub struct StreamEvent {
+    /// Number of the source tick the event stems from, starting at zero.
+    pub sequence: u64,
+    pub node_id: String,
+    pub outputs: PortValues,
+    pub logs: NodeLogs,
+}
+
+/// Outputs of a node for one tick, as sent along an edge.
+type EdgeEvent = (u64, PortValues);
+
+/// Returns a stream of ticks, the first one immediately and then one every `interval`.
+///
+/// Ticks the receiver is not ready for are skipped, so a slow graph is never flooded with
+/// stale ticks. The ticking thread ends once the receiver is dropped.
+fn ticks(interval: Duration) -> Receiver<()> {
+    let (mut sender, receiver) = mpsc::channel(0);
+    thread::spawn(move || loop {
+        if let Err(err) = sender.try_send(()) {
+            if err.is_disconnected() {
+                break;
+            }
+        }
+        thread::sleep(interval);
+    });
+    receiver
+}
+
+impl ExecutionStep<Value> {
+    /// Runs the node for every event of a stream until its inputs are exhausted.
+    ///
+    /// Source nodes, those without inputs, run on every tick instead. Unless instances are
+    /// [`InstanceReuse::Fresh`], the node keeps a single instance for the whole stream and
+    /// only its state is replaced from tick to tick.
+    async fn run_stream(
+        &self,
+        runtime: &Runtime,
+        options: &StreamOptions,
+        mut inputs: Vec<(String, Receiver<EdgeEvent>)>,
+        mut outputs: Vec<Sender<EdgeEvent>>,
+        mut events: Sender<StreamEvent>,
+        run_id: &str,
+    ) -> Result<(), RuntimeError> {
+        let input_ports = runtime.input_ports(&self.node_type)?;
+        let fresh = runtime.instance_reuse() == InstanceReuse::Fresh;
+        let mut instance = self
+            .acquire_instance(runtime, self.node_state.as_ref())
+            .await?;
+
+        if inputs.is_empty() {
+            let mut ticks = ticks(options.interval);
+            for sequence in 0.. {
+                if options.ticks.is_some_and(|ticks| sequence >= ticks) {
+                    break;
+                }
+                let state = match &self.sequence {
+                    Some(states) => match states.get(sequence as usize) {
+                        Some(state) => Some(state),
+                        None => break,
+                    },
+                    None => self.node_state.as_ref(),
+                };
+                if ticks.next().await.is_none() {
+                    break;
+                }
+                if fresh && sequence > 0 {
+                    instance = self.acquire_instance(runtime, state).await?;
+                } else {
+                    instance.set_state(state)?;
+                }
+                let node_inputs = PortValues::new();
+                let abandoned = self
+                    .process_event(
+                        &mut instance,
+                        &node_inputs,
+                        sequence,
+                        run_id,
+                        &mut outputs,
+                        &mut events,
+                    )
+                    .await?;
+                if abandoned {
+                    break;
+                }
+            }
+            self.release_instance(runtime, instance);
+            return Ok(());
+        }
+
+        let mut processed = false;
+        loop {
+            // Every edge carries one event per tick, so the next event of each edge belongs
+            // to the same tick.
+            let mut upstream = HashMap::new();
+            let mut sequence = 0;
+            for (from_node, receiver) in &mut inputs {
+                let Some((event_sequence, values)) = receiver.next().await else {
+                    self.release_instance(runtime, instance);
+                    return Ok(());
+                };
+                sequence = event_sequence;
+                upstream.insert(from_node.clone(), values);
+            }
+            let node_inputs = self.collect_inputs(&input_ports, &upstream)?;
+            if fresh && processed {
+                instance = self
+                    .acquire_instance(runtime, self.node_state.as_ref())
+                    .await?;
+            }
+            self.process_event(
+                &mut instance,
+                &node_inputs,
+                sequence,
+                run_id,
+                &mut outputs,
+                &mut events,
+            )
+            .await?;
+            processed = true;
+        }
+    }
+
+    /// Processes one event and passes the outputs on to downstream nodes and to `events`.
+    ///
+    /// Sending waits while a downstream channel is full. Channels of nodes that stopped
+    /// listening are dropped, and `true` is returned once no downstream node is left.
+    async fn process_event(
+        &self,
+        instance: &mut NodeInstance,
+        inputs: &PortValues,
+        sequence: u64,
+        run_id: &str,
+        outputs: &mut Vec<Sender<EdgeEvent>>,
+        events: &mut Sender<StreamEvent>,
+    ) -> Result<bool, RuntimeError> {
+        let result = instance.process(inputs).await;
+        let logs = instance.take_logs();
+        logs.emit(&self.node_id, &self.node_type, run_id);
+        let node_outputs = result?;
+
+        let had_outputs = !outputs.is_empty();
+        let mut open = Vec::with_capacity(outputs.len());
+        for mut sender in outputs.drain(..) {
+            if sender.send((sequence, node_outputs.clone())).await.is_ok() {
+                open.push(sender);
+            }
+        }
+        *outputs = open;
+
+        let event = StreamEvent {
+            sequence,
+            node_id: self.node_id.clone(),
+            outputs: node_outputs,
+            logs,
+        };
+        // A dropped event receiver only means nobody watches the stream.
+        let _ = events.send(event).await;
+        Ok(had_outputs && outputs.is_empty())
+    }
+}
+
+impl ExecutionPlan {
+    /// Executes the plan as a stream of events.
+    ///
+    /// Source nodes are polled every [`StreamOptions::interval`], or take on the states of
+    /// their `sequence` one tick at a time, and each of their outputs flows through the graph
+    /// as an event. Every node runs concurrently with its own instance, connected to its
+    /// neighbours by bounded channels, so a slow node holds back its upstream nodes instead
+    /// of buffering events without limit. Every processed event is sent to `events`.
+    ///
+    /// Returns once all sources are exhausted and every event has been processed.
+    pub async fn execute_stream(
+        &self,
+        runtime: &Runtime,
+        options: &StreamOptions,
+        events: Sender<StreamEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let run_id = new_run_id();
+        let capacity = options.channel_capacity.max(1) - 1;
+        let mut senders: HashMap<&str, Vec<Sender<EdgeEvent>>> = HashMap::new();
+        let mut receivers: HashMap<&str, Vec<(String, Receiver<EdgeEvent>)>> = HashMap::new();
+        for step in self.steps() {
+            for binding in &step.inputs {
+                // Every sender adds a slot to the channel's buffer.
+                let (sender, receiver) = mpsc::channel(capacity);
+                senders
+                    .entry(binding.from_node.as_str())
+                    .or_default()
+                    .push(sender);
+                receivers
+                    .entry(step.node_id.as_str())
+                    .or_default()
+                    .push((binding.from_node.clone(), receiver));
+            }
+        }
+
+        let tasks = self.steps().map(|step| {
+            step.run_stream(
+                runtime,
+                options,
+                receivers.remove(step.node_id.as_str()).unwrap_or_default(),
+                senders.remove(step.node_id.as_str()).unwrap_or_default(),
+                events.clone(),
+                &run_id,
+            )
+        });
+        try_join_all(tasks).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::future::join;
+    use serde_json::json;
+    use wasmtime::component::Val;
+
+    use super::*;
+    use crate::instance_pool::RuntimeConfig;
+    use crate::test_utils::{plan_from_json, runtime_with_components};
+
+    const COUNTER: &str = r#"(component
+        (import "cillio:node/host@0.1.0" (instance $host
+            (export "get-state" (func (result (option (list u8)))))))
+        (core module $mem
+            (memory (export "memory") 1)
+            (func (export "realloc") (param i32 i32 i32 i32) (result i32) i32.const 1024))
+        (core instance $mem (instantiate $mem))
+        (core func $get-state (canon lower (func $host "get-state")
+            (memory $mem "memory") (realloc (func $mem "realloc"))))
+        (core module $m
+            (import "host" "get-state" (func $get-state (param i32)))
+            (import "mem" "memory" (memory 1))
+            (func (export "process") (result i32)
+                ;; The state is a single JSON digit, return its value.
+                (call $get-state (i32.const 0))
+                (i32.sub (i32.load8_u (i32.load (i32.const 4))) (i32.const 48))))
+        (core instance $i (instantiate $m
+            (with "host" (instance (export "get-state" (func $get-state))))
+            (with "mem" (instance $mem))))
+        (type $r (record (field "value" u32)))
+        (export $r' "r" (type $r))
+        (func $f (result $r') (canon lift (core func $i "process")))
+        (export "process" (func $f))
+    )"#;
+
+    const DOUBLE: &str = r#"(component
+        (core module $m
+            (func (export "process") (param i32) (result i32)
+                (i32.mul (local.get 0) (i32.const 2))))
+        (core instance $i (instantiate $m))
+        (type $r (record (field "value" u32)))
+        (export $r' "r" (type $r))
+        (func $f (param "inputs" $r') (result $r') (canon lift (core func $i "process")))
+        (export "process" (func $f))
+    )"#;
+
+    #[test]
+    fn test_execute_stream() {
+        let plan = plan_from_json(json!({
+            "node_implementations": {
+                "counter": {"output": {"value": "int"}, "wasm": "counter"},
+                "double": {
+                    "input": {"value": "int"},
+                    "output": {"value": "int"},
+                    "wasm": "double"
+                }
+            },
+            "nodes": {
+                "source": {"type": "counter", "sequence": [1, 2, 3]},
+                "sink": {"type": "double"}
+            },
+            "edges": [{"from": "source", "to": "sink"}]
+        }));
+        let runtime = runtime_with_components(
+            RuntimeConfig::default(),
+            &[("counter", COUNTER), ("double", DOUBLE)],
+        );
+
+        let options = StreamOptions {
+            interval: Duration::from_millis(1),
+            channel_capacity: 1,
+            ..Default::default()
+        };
+        let (sender, receiver) = mpsc::channel(0);
+        let (result, events) = block_on(join(
+            plan.execute_stream(&runtime, &options, sender),
+            receiver.collect::<Vec<_>>(),
+        ));
+        result.unwrap();
+
+        let sink_values: Vec<_> = events
+            .iter()
+            .filter(|event| event.node_id == "sink")
+            .map(|event| (event.sequence, event.outputs["value"].clone()))
+            .collect();
+        assert_eq!(
+            sink_values,
+            [(0, Val::U32(2)), (1, Val::U32(4)), (2, Val::U32(6))]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Components and setup shared by the tests of the runtime.
+
+use cillio_config::GraphConfig;
+use cillio_graph::Graph;
+use futures::executor::block_on;
+use serde_json::Value;
+
+use crate::execution_plan::ExecutionPlan;
+use crate::instance_pool::RuntimeConfig;
+use crate::Runtime;
+
+/// A typed node with a `u32` port `value` that returns its input plus one.
+pub(crate) const INCREMENT: &str = r#"(component
+    (core module $m
+        (func (export "process") (param i32) (result i32)
+            local.get 0
+            i32.const 1
+            i32.add))
+    (core instance $i (instantiate $m))
+    (type $r (record (field "value" u32)))
+    (export $r' "r" (type $r))
+    (func $f (param "inputs" $r') (result $r') (canon lift (core func $i "process")))
+    (export "process" (func $f))
+)"#;
+
+/// A node that busy-waits and outputs the `u64` wall-clock times in nanoseconds, as seen
+/// through WASI, at which it started and ended.
+pub(crate) const TIMED: &str = r#"(component
+    (import "wasi:clocks/wall-clock@0.2.0" (instance $clock
+        (type $dt (record (field "seconds" u64) (field "nanoseconds" u32)))
+        (export "datetime" (type $datetime (eq $dt)))
+        (export "now" (func (result $datetime)))))
+    (core module $memory (memory (export "memory") 1))
+    (core instance $memory (instantiate $memory))
+    (alias core export $memory "memory" (core memory $mem))
+    (alias export $clock "now" (func $now))
+    (core func $now (canon lower (func $now) (memory $mem)))
+    (core module $m
+        (import "host" "memory" (memory 1))
+        (import "host" "now" (func $now (param i32)))
+        (func $time (result i64)
+            (call $now (i32.const 0))
+            (i64.add
+                (i64.mul (i64.load (i32.const 0)) (i64.const 1000000000))
+                (i64.extend_i32_u (i32.load (i32.const 8)))))
+        (func (export "process") (result i32)
+            (local $n i32)
+            (i64.store (i32.const 16) (call $time))
+            (local.set $n (i32.const 50000000))
+            (loop $l
+                (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                (br_if $l (local.get $n)))
+            (i64.store (i32.const 24) (call $time))
+            i32.const 16))
+    (core instance $i (instantiate $m (with "host" (instance
+        (export "memory" (memory $mem))
+        (export "now" (func $now))))))
+    (type $r (record (field "start" u64) (field "end" u64)))
+    (export $r' "r" (type $r))
+    (func $f (result $r') (canon lift (core func $i "process") (memory $mem)))
+    (export "process" (func $f))
+)"#;
+
+/// Creates the execution plan of a graph config given as JSON.
+pub(crate) fn plan_from_json(config: Value) -> ExecutionPlan {
+    let config: GraphConfig = serde_json::from_value(config).unwrap();
+    ExecutionPlan::from_graph(&Graph::new(&config).unwrap()).unwrap()
+}
+
+/// Creates a runtime with the given components, in WAT text by node type, loaded.
+pub(crate) fn runtime_with_components(
+    config: RuntimeConfig,
+    components: &[(&str, &str)],
+) -> Runtime {
+    let mut runtime = Runtime::from_config(config).unwrap();
+    for (node_type, component) in components {
+        block_on(runtime.load_component(node_type, &component.as_bytes().to_vec())).unwrap();
+    }
+    runtime
+}
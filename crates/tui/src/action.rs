@@ -1,5 +1,5 @@
 use std::path::PathBuf;
-use cillio_config::GraphConfig;
+use cillio_config::{GraphConfig, NodeDescription};
 use serde::{Deserialize, Serialize};
 use strum::Display;
 
@@ -20,4 +20,6 @@ pub enum Action {
     //---
     SetGraphConfigPath(PathBuf),
     SetGraphConfig(GraphConfig),
+    /// Descriptions of the node types of the graph, by node type.
+    SetNodeDescriptions(Vec<(String, NodeDescription)>),
 }
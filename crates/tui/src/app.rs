@@ -9,7 +9,7 @@ use crate::{
     action::Action,
     components::{
         file_explorer::FileExplorer, fps::FpsCounter, graph_explorer::GraphExplorer, home::Home,
-        ComponentManager,
+        node_palette::NodePalette, ComponentManager,
     },
     config::Config,
     tui::{Event, Tui},
@@ -50,6 +50,7 @@ impl App {
             "FileExplorer",
             Box::new(FileExplorer::default()),
         );
+        component_manager.register_component("NodePalette", Box::new(NodePalette::new()));
         component_manager.register_component("FpsCounter", Box::new(FpsCounter::default()));
 
         Ok(Self {
@@ -13,6 +13,7 @@ pub mod file_explorer;
 pub mod fps;
 pub mod graph_explorer;
 pub mod home;
+pub mod node_palette;
 
 pub trait Component {
     fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
@@ -1,8 +1,14 @@
-use cillio_config::{load_config, GraphConfig};
+use cillio_config::{
+    expand_subgraphs_except, fill_implementations, load_config, GraphConfig, NodeDescription,
+};
 use cillio_graph::Graph as CillioGraph;
 use color_eyre::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
 use ratatui::{layout::{Rect, Size}, Frame};
-use tracing::info;
+use petgraph::graph::NodeIndex;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use tracing::{info, warn};
 
 use super::Component;
 
@@ -14,6 +20,12 @@ use crate::graph_renderer::GraphRenderer;
 #[derive(Default, Debug, Clone)]
 pub struct GraphExplorer {
     graph_config: Option<GraphConfig>,
+    /// Directory the subgraph files of the graph config are resolved against.
+    graph_dir: PathBuf,
+    /// Composite nodes shown as a single node instead of their subgraph.
+    collapsed: HashSet<String>,
+    /// Descriptions of the node types, declaring the ports their implementations leave out.
+    descriptions: HashMap<String, NodeDescription>,
     graph: Option<CillioGraph>,
     renderer: Option<GraphRenderer>,
 }
@@ -22,30 +34,73 @@ impl GraphExplorer {
     pub fn new() -> Self {
         Self {
             graph_config: None,
+            graph_dir: PathBuf::from("."),
+            collapsed: HashSet::new(),
+            descriptions: HashMap::new(),
             graph: None,
             renderer: None,
         }
     }
 
     pub fn with_graph_config(graph_config: GraphConfig) -> Self {
-        let graph = CillioGraph::new(&graph_config).unwrap();
-        let renderer = GraphRenderer::new(LayoutDirection::LeftToRight);
-        Self {
-            graph_config: Some(graph_config),
-            graph: Some(graph),
-            renderer: Some(renderer),
-        }
+        let mut graph_explorer = Self::new();
+        graph_explorer.set_graph_config(graph_config);
+        graph_explorer
     }
 
     pub fn set_graph_config(&mut self, graph_config: GraphConfig) {
-        let graph = CillioGraph::new(&graph_config).unwrap();
-        let renderer = GraphRenderer::new(LayoutDirection::LeftToRight);
+        self.collapsed.clear();
         self.graph_config = Some(graph_config);
+        self.rebuild_graph();
+    }
+
+    /// Builds the graph from the config, expanding all composite nodes that are not
+    /// collapsed into clusters of their subgraph's nodes.
+    fn rebuild_graph(&mut self) {
+        let Some(graph_config) = &self.graph_config else {
+            return;
+        };
+        let mut expanded =
+            expand_subgraphs_except(graph_config, &self.graph_dir, &self.collapsed)
+                .unwrap_or_else(|err| {
+                    warn!("Failed to expand subgraphs: {}", err);
+                    graph_config.clone()
+                });
+        fill_implementations(&mut expanded, &self.descriptions);
+        let graph = CillioGraph::new(&expanded).unwrap();
+        let mut renderer = GraphRenderer::new(LayoutDirection::LeftToRight);
+        renderer.set_highlighted_nodes(Self::cycle_nodes(&graph));
         self.graph = Some(graph);
         self.renderer = Some(renderer);
     }
 
-    // ... (other methods remain the same)
+    /// Collapses all expanded composite nodes, or expands them again if none is expanded.
+    fn toggle_collapsed(&mut self) {
+        let expanded: Vec<String> = self
+            .graph
+            .iter()
+            .flat_map(|graph| graph.subgraphs().keys().cloned())
+            .collect();
+        if expanded.is_empty() {
+            self.collapsed.clear();
+        } else {
+            self.collapsed.extend(expanded);
+        }
+        self.rebuild_graph();
+    }
+
+    /// Collects the nodes that are part of a cycle so they can be highlighted.
+    fn cycle_nodes(graph: &CillioGraph) -> HashSet<NodeIndex> {
+        let cycles = graph.find_cycles();
+        if !cycles.is_empty() {
+            warn!("Graph contains cycles: {:?}", cycles);
+        }
+        cycles
+            .iter()
+            .flatten()
+            .filter_map(|node_id| graph.node_map().get(node_id).copied())
+            .collect()
+    }
 }
 
 impl Component for GraphExplorer {
@@ -54,13 +109,26 @@ impl Component for GraphExplorer {
         Ok(())
     }
 
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('c') {
+            self.toggle_collapsed();
+        }
+        Ok(None)
+    }
+
     fn update(&mut self, action: Action) -> Result<Option<Action>> {
         match action {
             Action::SetGraphConfigPath(path) => {
                 info!("Setting graph config path: {:?}", path);
                 let graph_config = load_config(path.to_str().unwrap())?;
+                self.graph_dir = path.parent().map(PathBuf::from).unwrap_or_default();
+                self.descriptions.clear();
                 self.set_graph_config(graph_config);
             }
+            Action::SetNodeDescriptions(descriptions) => {
+                self.descriptions = descriptions.into_iter().collect();
+                self.rebuild_graph();
+            }
             _ => {}
         };
         Ok(None)
@@ -0,0 +1,232 @@
+use block::Title;
+use cillio_config::{
+    expand_subgraphs, load_config, NodeDescription, NodeImplementationData, PortDescription,
+};
+use cillio_runtime::Runtime;
+use color_eyre::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::{prelude::*, widgets::*};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use symbols::border;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{info, warn};
+
+use super::Component;
+use crate::action::Action;
+
+const PALETTE_WIDTH: u16 = 48;
+
+/// Lists the node types of the graph with what their implementations describe about
+/// themselves. Toggled with `p`.
+#[derive(Default)]
+pub struct NodePalette {
+    command_tx: Option<UnboundedSender<Action>>,
+    /// Declared implementations of the node types of the graph.
+    node_implementations: BTreeMap<String, NodeImplementationData>,
+    /// Implementations of the node types of the graph with its subgraphs expanded, which
+    /// are described for the palette and the graph explorer.
+    expanded_implementations: BTreeMap<String, NodeImplementationData>,
+    /// Descriptions of the node types whose implementation has one.
+    descriptions: BTreeMap<String, NodeDescription>,
+    list_state: ListState,
+    visible: bool,
+}
+
+impl NodePalette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the implementations of the node types in the background and sends their
+    /// descriptions once loaded.
+    fn load_descriptions(&mut self, graph_dir: PathBuf) {
+        let Some(command_tx) = self.command_tx.clone() else {
+            return;
+        };
+        let node_implementations = self.expanded_implementations.clone();
+        tokio::task::spawn_blocking(move || {
+            let descriptions =
+                futures::executor::block_on(describe_node_types(&node_implementations, &graph_dir));
+            let _ = command_tx.send(Action::SetNodeDescriptions(descriptions));
+        });
+    }
+}
+
+/// Lists the node types with their descriptions, or their declared ports if they have none.
+fn palette_items<'a>(
+    node_implementations: &'a BTreeMap<String, NodeImplementationData>,
+    descriptions: &'a BTreeMap<String, NodeDescription>,
+) -> Vec<ListItem<'a>> {
+    node_implementations
+        .iter()
+        .map(|(node_type, implementation)| {
+            let mut lines = Vec::new();
+            match descriptions.get(node_type) {
+                Some(description) => {
+                    lines.push(Line::from(vec![
+                        node_type.as_str().bold(),
+                        format!("  {} {}", description.name, description.version).dim(),
+                    ]));
+                    if !description.description.is_empty() {
+                        lines.push(Line::from(description.description.as_str()));
+                    }
+                    if !description.categories.is_empty() {
+                        lines.push(
+                            Line::from(format!("[{}]", description.categories.join(", "))).italic(),
+                        );
+                    }
+                    lines.extend(port_lines("in", &description.inputs));
+                    lines.extend(port_lines("out", &description.outputs));
+                    lines.extend(port_lines("state", &description.state));
+                }
+                None => {
+                    lines.push(Line::from(node_type.as_str().bold()));
+                    lines.push(Line::from("No description").dim());
+                    for (title, ports) in [
+                        ("in", &implementation.input),
+                        ("out", &implementation.output),
+                    ] {
+                        for (port, ty) in ports.iter().flatten() {
+                            lines.push(Line::from(format!("  {} {}: {}", title, port, ty)));
+                        }
+                    }
+                }
+            }
+            lines.push(Line::default());
+            ListItem::new(Text::from(lines))
+        })
+        .collect()
+}
+
+fn port_lines<'a>(title: &'a str, ports: &'a [PortDescription]) -> Vec<Line<'a>> {
+    ports
+        .iter()
+        .map(|port| {
+            let mut spans = vec![Span::raw(format!(
+                "  {} {}: {}",
+                title, port.name, port.r#type
+            ))];
+            if let Some(default) = &port.default {
+                spans.push(format!(" = {}", default).dim());
+            }
+            if let Some(doc) = &port.doc {
+                spans.push(format!("  {}", doc).italic());
+            }
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Loads the implementation of each node type into a runtime and collects the descriptions.
+/// Components are looked up relative to the graph file.
+async fn describe_node_types(
+    node_implementations: &BTreeMap<String, NodeImplementationData>,
+    graph_dir: &Path,
+) -> Vec<(String, NodeDescription)> {
+    let mut runtime = Runtime::new();
+    let mut descriptions = Vec::new();
+    for (node_type, implementation) in node_implementations {
+        let loaded = match (&implementation.native, &implementation.wasm) {
+            (Some(native), _) => runtime
+                .load_native(node_type, native)
+                .map_err(|err| err.to_string()),
+            (None, Some(wasm)) => {
+                let mut path = graph_dir.join(wasm);
+                if path.extension().is_none() {
+                    path.set_extension("wasm");
+                }
+                match std::fs::read(&path) {
+                    Ok(bytes) => runtime
+                        .load_component(node_type, &bytes)
+                        .await
+                        .map(|_| ())
+                        .map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            (None, None) => continue,
+        };
+        if let Err(err) = loaded {
+            warn!("Failed to load node type {}: {}", node_type, err);
+            continue;
+        }
+        if let Some(description) = runtime.node_description(node_type) {
+            descriptions.push((node_type.clone(), description));
+        }
+    }
+    descriptions
+}
+
+impl Component for NodePalette {
+    fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> Result<()> {
+        self.command_tx = Some(tx);
+        Ok(())
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        if key.kind != KeyEventKind::Press {
+            return Ok(None);
+        }
+        match key.code {
+            KeyCode::Char('p') => self.visible = !self.visible,
+            KeyCode::Char('j') | KeyCode::Down if self.visible => self.list_state.select_next(),
+            KeyCode::Char('k') | KeyCode::Up if self.visible => self.list_state.select_previous(),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::SetGraphConfigPath(path) => {
+                let graph_config = load_config(path.to_str().unwrap())?;
+                let graph_dir = path.parent().map(PathBuf::from).unwrap_or_default();
+                let expanded = expand_subgraphs(&graph_config, &graph_dir).unwrap_or_else(|err| {
+                    warn!("Failed to expand subgraphs: {}", err);
+                    graph_config.clone()
+                });
+                self.expanded_implementations = expanded.node_implementations.into_iter().collect();
+                self.node_implementations = graph_config.node_implementations.into_iter().collect();
+                self.descriptions.clear();
+                self.list_state.select(None);
+                self.load_descriptions(graph_dir);
+            }
+            Action::SetNodeDescriptions(descriptions) => {
+                info!("Loaded {} node descriptions", descriptions.len());
+                self.descriptions = descriptions.into_iter().collect();
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn render(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        if !self.visible {
+            return Ok(());
+        }
+        let [_, area] =
+            Layout::horizontal([Constraint::Fill(1), Constraint::Length(PALETTE_WIDTH)])
+                .areas(area);
+        let title = Title::from(" Node palette ".bold());
+        let block = Block::bordered()
+            .title(title.alignment(Alignment::Center))
+            .border_set(border::ROUNDED);
+        frame.render_widget(Clear, area);
+        if self.node_implementations.is_empty() {
+            frame.render_widget(
+                Paragraph::new("No graph configuration set").block(block),
+                area,
+            );
+            return Ok(());
+        }
+        let list = List::new(palette_items(
+            &self.node_implementations,
+            &self.descriptions,
+        ))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(block);
+        frame.render_stateful_widget(list, area, &mut self.list_state);
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use petgraph::{graph::NodeIndex, visit::EdgeRef, Direction};
 use ratatui::{
@@ -32,6 +32,7 @@ pub struct GraphRenderer {
     node_layouts: HashMap<NodeIndex, NodeLayout>,
     recompute_layout: bool,
     layout_direction: LayoutDirection,
+    highlighted_nodes: HashSet<NodeIndex>,
 }
 
 impl GraphRenderer {
@@ -41,9 +42,15 @@ impl GraphRenderer {
             node_layouts: HashMap::new(),
             recompute_layout: true,
             layout_direction,
+            highlighted_nodes: HashSet::new(),
         }
     }
 
+    /// Highlights the given nodes, e.g. the nodes forming a cycle.
+    pub fn set_highlighted_nodes(&mut self, nodes: HashSet<NodeIndex>) {
+        self.highlighted_nodes = nodes;
+    }
+
     /// Marks the renderer as needing to recompute positions.
     pub fn mark_dirty(&mut self) {
         self.recompute_layout = true;
@@ -58,10 +65,17 @@ impl GraphRenderer {
 
         let mut buffer = Buffer::empty(area);
 
+        // Draw frames around the nodes of expanded subgraphs
+        self.draw_clusters(&mut buffer, graph, area);
+
         // Draw nodes
         for (&node_idx, node_layout) in &self.node_layouts {
             let node = &graph.graph()[node_idx];
-            self.draw_node(&mut buffer, node, node_layout);
+            let is_highlighted = self.highlighted_nodes.contains(&node_idx);
+            let is_composite = graph
+                .node_implementation(node_idx)
+                .is_some_and(|implementation| implementation.graph.is_some());
+            self.draw_node(&mut buffer, node, node_layout, is_highlighted, is_composite);
         }
 
         // Draw connections
@@ -250,8 +264,22 @@ impl GraphRenderer {
     }
 
     /// Draws a node.
-    fn draw_node(&self, buffer: &mut Buffer, node: &Node, layout: &NodeLayout) {
-        let style = Style::default().bg(Color::DarkGray).fg(Color::White);
+    fn draw_node(
+        &self,
+        buffer: &mut Buffer,
+        node: &Node,
+        layout: &NodeLayout,
+        is_highlighted: bool,
+        is_composite: bool,
+    ) {
+        let background = if is_highlighted {
+            Color::Red
+        } else if is_composite {
+            Color::Blue
+        } else {
+            Color::DarkGray
+        };
+        let style = Style::default().bg(background).fg(Color::White);
         let (x, y) = layout.position;
         let (width, height) = layout.size;
 
@@ -326,6 +354,50 @@ impl GraphRenderer {
         }
     }
 
+    /// Draws a labelled frame around the laid out nodes of every expanded subgraph.
+    fn draw_clusters(&self, buffer: &mut Buffer, graph: &Graph, area: Rect) {
+        let style = Style::default().fg(Color::Blue);
+        let mut clusters: Vec<_> = graph.subgraphs().iter().collect();
+        clusters.sort_by_key(|(node_id, _)| *node_id);
+
+        for (node_id, subgraph) in clusters {
+            let prefix = format!("{}/", node_id);
+            let layouts: Vec<_> = self
+                .node_layouts
+                .iter()
+                .filter(|(node_idx, _)| graph.graph()[**node_idx].id.starts_with(&prefix))
+                .map(|(_, layout)| layout)
+                .collect();
+            if layouts.is_empty() {
+                continue;
+            }
+
+            // One cell of padding around the nodes, clipped to the drawing area
+            let left = layouts.iter().map(|l| l.position.0).min().unwrap().saturating_sub(1);
+            let top = layouts.iter().map(|l| l.position.1).min().unwrap().saturating_sub(1);
+            let right = layouts
+                .iter()
+                .map(|l| l.position.0 + l.size.0)
+                .max()
+                .unwrap()
+                .min(area.width.saturating_sub(1));
+            let bottom = layouts
+                .iter()
+                .map(|l| l.position.1 + l.size.1)
+                .max()
+                .unwrap()
+                .min(area.height.saturating_sub(1));
+            if right <= left + 1 || bottom <= top + 1 {
+                continue;
+            }
+
+            self.draw_borders(buffer, left, top, right - left + 1, bottom - top + 1, style);
+            let label = format!(" {} ({}) ", node_id, subgraph.r#type);
+            let max_width = (right - left).saturating_sub(2) as usize;
+            buffer.set_stringn(left + 2, top, &label, max_width, style);
+        }
+    }
+
     /// Draws all connections between nodes.
     fn draw_connections(&self, buffer: &mut Buffer, graph: &Graph) {
         let style = Style::default().fg(Color::White);